@@ -8,6 +8,10 @@ quick_error! {
             description(str)
             display("error: {}", str)
         }
+        Full(size: usize, total: usize, max: usize) {
+            description("carrier is full")
+            display("carrier is full: message of {} bytes would put us at {} bytes (max {})", size, total + size, max)
+        }
     }
 }
 
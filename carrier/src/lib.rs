@@ -23,90 +23,189 @@
 //!      it is recycled (removed entirely). This allows you to very cheaply make
 //!      and use new channels that clean themselves up when finished.
 
-extern crate crossbeam;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
+extern crate log;
+#[macro_use]
 extern crate quick_error;
 
 mod error;
 pub mod c;
 
-use ::std::sync::{Arc, RwLock};
-use ::std::collections::HashMap;
-
-use ::crossbeam::sync::MsQueue;
+use ::std::sync::{Arc, RwLock, Mutex, Condvar};
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+use ::std::collections::{HashMap, VecDeque};
+use ::std::time::{Duration, Instant};
+use ::std::task::Waker;
+#[cfg(feature = "async")]
+use ::std::future::Future;
+#[cfg(feature = "async")]
+use ::std::pin::Pin;
+#[cfg(feature = "async")]
+use ::std::task::{Context, Poll};
 
 pub use ::error::CError;
 use ::error::CResult;
 
+/// Unwrap a lock result, recovering the guard if the lock was poisoned. A
+/// poisoned lock only means some thread panicked while holding it, and our
+/// queues are still perfectly usable, so we log it and keep going instead of
+/// taking down every messaging call that comes after.
+macro_rules! recover {
+    ($lockres:expr, $from:expr) => {
+        match $lockres {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("{} -- recovering from a poisoned lock", $from);
+                e.into_inner()
+            }
+        }
+    }
+}
+
 lazy_static! {
     static ref CONN: Carrier = Carrier::new().expect("carrier -- global static: failed to create");
 }
 
-/// The carrier Queue is a quick and simple wrapper around MsQueue that keeps
-/// track of a bit more state than MsQueue does.
+/// Keeps track of the blocked receivers on a queue, deli-counter style. Each
+/// receiver takes a ticket when it starts waiting and only the receiver whose
+/// ticket is being served can take the next message, so messages get handed
+/// out round-robin instead of to whoever happens to wake up first.
+struct Line {
+    next_ticket: u64,
+    serving: u64,
+}
+
+/// The messages sitting in a queue. Priority messages live at the front (in
+/// the order they were sent) so they get handed out before any normal ones.
+struct Messages<T> {
+    items: VecDeque<T>,
+    /// How many of the messages at the front of `items` are priority messages
+    priority: usize,
+}
+
+impl<T> Messages<T> {
+    fn push(&mut self, val: T, priority: bool) {
+        if priority {
+            self.items.insert(self.priority, val);
+            self.priority += 1;
+        } else {
+            self.items.push_back(val);
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let res = self.items.pop_front();
+        if res.is_some() && self.priority > 0 {
+            self.priority -= 1;
+        }
+        res
+    }
+}
+
+/// The carrier Queue is a quick and simple wrapper around a deque of messages
+/// that keeps track of who's listening and how many messages are waiting.
 struct Queue<T> {
-    internal: MsQueue<T>,
+    internal: Mutex<Messages<T>>,
     messages: RwLock<i32>,
     users: RwLock<i32>,
+    line: Mutex<Line>,
+    line_signal: Condvar,
+    /// Async receivers waiting for the next message (see `recv_async()`)
+    wakers: Mutex<Vec<Waker>>,
 }
 
 impl<T> Queue<T> {
     /// Create a new carrier queue.
     fn new() -> Queue<T> {
         Queue {
-            internal: MsQueue::new(),
+            internal: Mutex::new(Messages { items: VecDeque::new(), priority: 0 }),
             messages: RwLock::new(0),
             users: RwLock::new(0),
+            line: Mutex::new(Line { next_ticket: 0, serving: 0 }),
+            line_signal: Condvar::new(),
+            wakers: Mutex::new(Vec::new()),
         }
     }
 
     /// Increment the number of messages this queue has by a certain amount (1).
     fn inc_messages(&self, val: i32) {
-        let mut mguard = self.messages.write().expect("Queue.inc_messages() -- failed to grab write lock");
+        let mut mguard = recover!(self.messages.write(), "Queue.inc_messages()");
         (*mguard) += val;
     }
 
     /// Increment the number of users this queue has by a certain amount (1).
     fn inc_users(&self, val: i32) {
-        let mut uguard = self.users.write().expect("Queue.inc_users() -- failed to grab write lock");
+        let mut uguard = recover!(self.users.write(), "Queue.inc_users()");
         (*uguard) += val;
     }
 
     /// Get how many messages this queue currently has listening to it.
     fn num_messages(&self) -> i32 {
-        let mguard = self.messages.read().expect("Queue.num_messages() -- failed to grab read lock");
+        let mguard = recover!(self.messages.read(), "Queue.num_messages()");
         (*mguard).clone()
     }
 
     /// Get how many users this queue currently has listening to it.
     fn num_users(&self) -> i32 {
-        let uguard = self.users.read().expect("Queue.num_users() -- failed to grab read lock");
+        let uguard = recover!(self.users.read(), "Queue.num_users()");
         (*uguard).clone()
     }
 
-    /// MsQueue.push()
-    fn push(&self, val: T) {
-        self.internal.push(val);
+    /// Push a message onto the queue. Priority messages skip ahead of all the
+    /// normal messages waiting.
+    fn push(&self, val: T, priority: bool) {
+        recover!(self.internal.lock(), "Queue.push()").push(val, priority);
         self.inc_messages(1);
+        // grab the line lock before notifying so a receiver can't miss the
+        // message between checking the queue and going to sleep
+        let line = recover!(self.line.lock(), "Queue.push()");
+        self.line_signal.notify_all();
+        drop(line);
+        let wakers = ::std::mem::take(&mut *recover!(self.wakers.lock(), "Queue.push()"));
+        for waker in wakers {
+            waker.wake();
+        }
     }
 
-    /// MsQueue.try_pop()
+    /// Have the given waker woken up the next time a message is pushed
+    #[cfg(feature = "async")]
+    fn register_waker(&self, waker: &Waker) {
+        let mut wakers = recover!(self.wakers.lock(), "Queue.register_waker()");
+        if !wakers.iter().any(|x| x.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Non-blocking pop
     fn try_pop(&self) -> Option<T> {
-        let res = self.internal.try_pop();
+        let res = recover!(self.internal.lock(), "Queue.try_pop()").pop();
         if res.is_some() {
             self.inc_messages(-1);
         } else {
-            *(self.messages.write().expect("Queue.try_pop() -- failed to grab write lock")) = 0;
+            *(recover!(self.messages.write(), "Queue.try_pop()")) = 0;
         }
         res
     }
 
-    /// MsQueue.pop()
+    /// Blocking pop. Receivers are served in the order they started waiting.
     fn pop(&self) -> T {
         self.inc_users(1);
-        let res = self.internal.pop();
+        let mut line = recover!(self.line.lock(), "Queue.pop()");
+        let ticket = line.next_ticket;
+        line.next_ticket += 1;
+        let res = loop {
+            if line.serving == ticket {
+                let next = recover!(self.internal.lock(), "Queue.pop()").pop();
+                if let Some(x) = next { break x; }
+            }
+            line = recover!(self.line_signal.wait(line), "Queue.pop()");
+        };
+        line.serving += 1;
+        drop(line);
+        // wake everyone up so the next in line can check for messages
+        self.line_signal.notify_all();
         self.inc_users(-1);
         self.inc_messages(-1);
         res
@@ -123,8 +222,61 @@ impl<T> Queue<T> {
     }
 }
 
+/// Wraps a message as it sits in a queue, recording when it was enqueued so we
+/// can tell how long it waited before being received.
+struct Envelope {
+    queued: Instant,
+    data: Vec<u8>,
+}
+
+impl Envelope {
+    /// Seal a message, stamping it with the current time.
+    fn new(data: Vec<u8>) -> Envelope {
+        Envelope {
+            queued: Instant::now(),
+            data: data,
+        }
+    }
+
+    /// Open the envelope, returning the message and how long it was queued.
+    fn open(self) -> (Vec<u8>, Duration) {
+        (self.data, self.queued.elapsed())
+    }
+}
+
+/// A function that gets called with a channel's name when it's removed
+type RecycleCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// A snapshot of how many messages have gone through carrier since startup
+/// (see `metrics()`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CarrierMetrics {
+    /// Messages successfully queued
+    pub sent: usize,
+    /// Messages taken off a queue by a receiver
+    pub received: usize,
+    /// Messages that never made it to a receiver, either because a send was
+    /// rejected for going over the byte limit or because they were wiped
+    pub dropped: usize,
+}
+
 pub struct Carrier {
-    queues: RwLock<HashMap<String, Arc<Queue<Vec<u8>>>>>,
+    queues: RwLock<HashMap<String, Arc<Queue<Envelope>>>>,
+    /// How many bytes of message data are sitting in our queues, total
+    bytes: AtomicUsize,
+    /// If set, the most bytes we'll hold across all queues before sends start
+    /// getting rejected
+    max_bytes: RwLock<Option<usize>>,
+    /// If set, called whenever a channel gets removed (abandoned or wiped)
+    recycle_cb: RwLock<Option<RecycleCallback>>,
+    /// Bumped every time a message lands on any queue, so `pop_any()` can wait
+    /// on several channels at once
+    activity: Mutex<u64>,
+    activity_signal: Condvar,
+    /// Running message totals (see `CarrierMetrics`)
+    sent: AtomicUsize,
+    received: AtomicUsize,
+    dropped: AtomicUsize,
 }
 
 //unsafe impl Send for Carrier {}
@@ -135,12 +287,20 @@ impl Carrier {
     pub fn new() -> CResult<Carrier> {
         Ok(Carrier {
             queues: RwLock::new(HashMap::new()),
+            bytes: AtomicUsize::new(0),
+            max_bytes: RwLock::new(None),
+            recycle_cb: RwLock::new(None),
+            activity: Mutex::new(0),
+            activity_signal: Condvar::new(),
+            sent: AtomicUsize::new(0),
+            received: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
         })
     }
 
     /// Ensure a channel exists
-    fn ensure(&self, channel: &String) -> Arc<Queue<Vec<u8>>> {
-        let mut guard = self.queues.write().expect("Carrier.ensure() -- failed to grab write lock");
+    fn ensure(&self, channel: &String) -> Arc<Queue<Envelope>> {
+        let mut guard = recover!(self.queues.write(), "Carrier.ensure()");
         if (*guard).contains_key(channel) {
             (*guard).get(channel).expect("Carrier.ensure() -- failed to grab map item").clone()
         } else {
@@ -151,33 +311,240 @@ impl Carrier {
     }
 
     fn exists(&self, channel: &String) -> bool {
-        let guard = self.queues.read().expect("Carrier.exists() -- failed to grab read lock");
+        let guard = recover!(self.queues.read(), "Carrier.exists()");
         (*guard).contains_key(channel)
     }
 
     /// Count how many active channels there are
     fn count(&self) -> u32 {
-        let guard = self.queues.read().expect("Carrier.count() -- failed to grab read lock");
+        let guard = recover!(self.queues.read(), "Carrier.count()");
         (*guard).len() as u32
     }
 
     /// Remove a channel
     fn remove(&self, channel: &String) {
-        let mut guard = self.queues.write().expect("Carrier.remove() -- failed to grab write lock");
-        (*guard).remove(channel);
+        let removed = {
+            let mut guard = recover!(self.queues.write(), "Carrier.remove()");
+            (*guard).remove(channel).is_some()
+        };
+        if removed { self.recycled(::std::slice::from_ref(channel)); }
     }
 
     fn wipe(&self) {
-        let mut guard = self.queues.write().expect("Carrier.wipe() -- failed to grab write lock");
-        guard.clear();
+        let channels = {
+            let mut guard = recover!(self.queues.write(), "Carrier.wipe()");
+            let channels = guard.keys().cloned().collect::<Vec<_>>();
+            let wiped = guard.values().fold(0, |acc, x| acc + x.num_messages().max(0) as usize);
+            self.dropped.fetch_add(wiped, Ordering::SeqCst);
+            guard.clear();
+            self.bytes.store(0, Ordering::SeqCst);
+            channels
+        };
+        self.recycled(&channels);
+    }
+
+    /// Set (or unset) the callback that fires when a channel is removed
+    fn set_recycle_callback(&self, cb: Option<RecycleCallback>) {
+        let mut guard = recover!(self.recycle_cb.write(), "Carrier.set_recycle_callback()");
+        *guard = cb;
+    }
+
+    /// Let our recycle callback (if any) know these channels are gone. Called
+    /// with the queue lock released so the callback is free to use carrier.
+    fn recycled(&self, channels: &[String]) {
+        let cb = recover!(self.recycle_cb.read(), "Carrier.recycled()").clone();
+        if let Some(cb) = cb {
+            for channel in channels {
+                cb(channel);
+            }
+        }
+    }
+
+    /// Set (or unset) the max number of bytes we hold across all queues
+    fn set_max_bytes(&self, max: Option<usize>) {
+        let mut guard = recover!(self.max_bytes.write(), "Carrier.set_max_bytes()");
+        *guard = max;
+    }
+
+    /// Get the number of bytes currently held in all our queues
+    fn total_bytes(&self) -> usize {
+        self.bytes.load(Ordering::SeqCst)
+    }
+
+    /// Grab the current message totals
+    fn metrics(&self) -> CarrierMetrics {
+        CarrierMetrics {
+            sent: self.sent.load(Ordering::SeqCst),
+            received: self.received.load(Ordering::SeqCst),
+            dropped: self.dropped.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Push a message onto a channel, making sure we don't go over our byte
+    /// limit (if we have one)
+    fn push(&self, channel: &String, message: Vec<u8>) -> CResult<()> {
+        self.push_message(channel, message, false)
+    }
+
+    /// Push a message onto the front of a channel, ahead of any normal
+    /// messages already waiting
+    fn push_priority(&self, channel: &String, message: Vec<u8>) -> CResult<()> {
+        self.push_message(channel, message, true)
+    }
+
+    fn push_message(&self, channel: &String, message: Vec<u8>, priority: bool) -> CResult<()> {
+        let size = message.len();
+        let max = *(recover!(self.max_bytes.read(), "Carrier.push()"));
+        match max {
+            Some(max) => {
+                // reserve our bytes up front so two senders can't both squeeze
+                // in under the limit
+                let total = self.bytes.fetch_add(size, Ordering::SeqCst);
+                if total + size > max {
+                    self.bytes.fetch_sub(size, Ordering::SeqCst);
+                    self.dropped.fetch_add(1, Ordering::SeqCst);
+                    return Err(CError::Full(size, total, max));
+                }
+            }
+            None => { self.bytes.fetch_add(size, Ordering::SeqCst); }
+        }
+        let queue = self.ensure(channel);
+        queue.push(Envelope::new(message), priority);
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        let mut activity = recover!(self.activity.lock(), "Carrier.push()");
+        *activity = activity.wrapping_add(1);
+        self.activity_signal.notify_all();
+        Ok(())
+    }
+
+    /// Blocking pop off a channel
+    fn pop(&self, channel: &String) -> (Vec<u8>, Duration) {
+        let queue = self.ensure(channel);
+        let res = queue.pop().open();
+        self.bytes.fetch_sub(res.0.len(), Ordering::SeqCst);
+        self.received.fetch_add(1, Ordering::SeqCst);
+        if queue.is_abandoned() { self.remove(channel); }
+        res
+    }
+
+    /// Non-blocking pop off a channel
+    fn try_pop(&self, channel: &String) -> Option<Vec<u8>> {
+        if !self.exists(channel) {
+            return None;
+        }
+        let queue = self.ensure(channel);
+        let res = queue.try_pop().map(|env| env.open().0);
+        if let Some(ref msg) = res {
+            self.bytes.fetch_sub(msg.len(), Ordering::SeqCst);
+            self.received.fetch_add(1, Ordering::SeqCst);
+        }
+        if queue.is_abandoned() { self.remove(channel); }
+        res
+    }
+
+    /// Non-blocking pop off a queue we're already holding on to (see `Recv`)
+    #[cfg(feature = "async")]
+    fn try_pop_queue(&self, queue: &Queue<Envelope>) -> Option<Vec<u8>> {
+        let res = queue.try_pop().map(|env| env.open().0);
+        if let Some(ref msg) = res {
+            self.bytes.fetch_sub(msg.len(), Ordering::SeqCst);
+            self.received.fetch_add(1, Ordering::SeqCst);
+        }
+        res
+    }
+
+    /// Pop off whichever of the given channels has a message first, waiting up
+    /// to `timeout` for one to show up. If several channels have messages
+    /// waiting, the one listed first wins.
+    fn pop_any(&self, channels: &[String], timeout: Duration) -> Option<(String, Vec<u8>)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let seen = *recover!(self.activity.lock(), "Carrier.pop_any()");
+            for channel in channels {
+                if let Some(msg) = self.try_pop(channel) {
+                    return Some((channel.clone(), msg));
+                }
+            }
+            // sleep until something gets pushed (anywhere) or we run out of
+            // time. if a push snuck in after we grabbed `seen`, we go right
+            // back around and check again.
+            let mut activity = recover!(self.activity.lock(), "Carrier.pop_any()");
+            while *activity == seen {
+                let now = Instant::now();
+                if now >= deadline { return None; }
+                let (guard, _) = recover!(self.activity_signal.wait_timeout(activity, deadline - now), "Carrier.pop_any()");
+                activity = guard;
+            }
+        }
     }
 }
 
-/// Send a message on a channel
+/// A Future that resolves to the next message on a channel (see `recv_async()`).
+/// While pending, it counts as a listener on the channel, so the channel won't
+/// get recycled out from under it.
+#[cfg(feature = "async")]
+pub struct Recv {
+    channel: String,
+    queue: Option<Arc<Queue<Envelope>>>,
+}
+
+#[cfg(feature = "async")]
+impl Recv {
+    /// Stop listening on our channel (if we were), cleaning it up if nobody
+    /// else is using it
+    fn release(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.inc_users(-1);
+            if queue.is_abandoned() { (*CONN).remove(&self.channel); }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Future for Recv {
+    type Output = CResult<Vec<u8>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let queue = match self.queue {
+            Some(ref x) => x.clone(),
+            None => {
+                let queue = (*CONN).ensure(&self.channel);
+                queue.inc_users(1);
+                self.queue = Some(queue.clone());
+                queue
+            }
+        };
+        // register before checking so a message that lands in between still
+        // wakes us up
+        queue.register_waker(cx.waker());
+        match (*CONN).try_pop_queue(&queue) {
+            Some(msg) => {
+                self.release();
+                Poll::Ready(Ok(msg))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for Recv {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Send a message on a channel. Errors if the message would put us over the
+/// limit set by `set_max_bytes()`.
 pub fn send(channel: &str, message: Vec<u8>) -> CResult<()> {
-    let queue = (*CONN).ensure(&String::from(channel));
-    queue.push(message);
-    Ok(())
+    (*CONN).push(&String::from(channel), message)
+}
+
+/// Send a message that jumps ahead of any normal messages already waiting on
+/// the channel, for things like shutdown/pause that can't wait for a backlog to
+/// clear. Priority messages are still received in the order they were sent.
+pub fn send_priority(channel: &str, message: Vec<u8>) -> CResult<()> {
+    (*CONN).push_priority(&String::from(channel), message)
 }
 
 /// Send a message on a channel
@@ -188,22 +555,43 @@ pub fn send_string(channel: &str, message: String) -> CResult<()> {
 
 /// Blocking receive
 pub fn recv(channel: &str) -> CResult<Vec<u8>> {
-    let queue = (*CONN).ensure(&String::from(channel));
-    let res = Ok(queue.pop());
-    if queue.is_abandoned() { (*CONN).remove(&String::from(channel)); }
-    res
+    let (message, _age) = recv_with_age(channel)?;
+    Ok(message)
+}
+
+/// Blocking receive that also returns how long the message sat in the queue
+/// before we got to it. Handy for tracking down slow message handling.
+pub fn recv_with_age(channel: &str) -> CResult<(Vec<u8>, Duration)> {
+    Ok((*CONN).pop(&String::from(channel)))
+}
+
+/// Async receive: returns a Future that resolves to the next message on the
+/// channel, so async tasks can `.await` messages without tying up a thread.
+/// The task is woken up when a message gets sent. Like `recv_nb()`, this
+/// doesn't take a place in line with blocking `recv()` callers.
+#[cfg(feature = "async")]
+pub fn recv_async(channel: &str) -> Recv {
+    Recv {
+        channel: String::from(channel),
+        queue: None,
+    }
 }
 
 /// Non-blocking receive
 pub fn recv_nb(channel: &str) -> CResult<Option<Vec<u8>>> {
-    let channel = String::from(channel);
-    if !(*CONN).exists(&channel) {
-        return Ok(None)
-    }
-    let queue = (*CONN).ensure(&channel);
-    let res = Ok(queue.try_pop());
-    if queue.is_abandoned() { (*CONN).remove(&channel); }
-    res
+    Ok((*CONN).try_pop(&String::from(channel)))
+}
+
+/// Receive from whichever of the given channels gets a message first, blocking
+/// for up to `timeout`. Returns the channel the message came from along with
+/// the message, or `None` if nothing showed up in time. Note that waiting here
+/// doesn't count as listening on the channels, so a blocking `recv()` on the
+/// same channel will still get served first.
+pub fn recv_any(channels: &[&str], timeout: Duration) -> CResult<Option<(String, Vec<u8>)>> {
+    let channels = channels.iter()
+        .map(|x| String::from(*x))
+        .collect::<Vec<_>>();
+    Ok((*CONN).pop_any(&channels, timeout))
 }
 
 /// Returns the number of active channels
@@ -211,6 +599,40 @@ pub fn count() -> u32 {
     (*CONN).count()
 }
 
+/// Returns how many bytes of messages are currently sitting in all channels
+/// combined
+pub fn total_bytes() -> usize {
+    (*CONN).total_bytes()
+}
+
+/// Returns how many messages have been sent, received, and dropped since
+/// startup. The counts only ever go up (even across `wipe()`), so to watch
+/// throughput, diff two snapshots.
+pub fn metrics() -> CarrierMetrics {
+    (*CONN).metrics()
+}
+
+/// Cap the number of bytes all channels combined can hold. Once we hit the cap,
+/// `send()` returns an error until something gets received. `None` means no
+/// limit (the default).
+pub fn set_max_bytes(max: Option<usize>) {
+    (*CONN).set_max_bytes(max);
+}
+
+/// Register a function that gets called with a channel's name whenever that
+/// channel is removed, either because it was abandoned (empty with nobody
+/// listening) or wiped. Useful for tracking down disappearing channels.
+pub fn set_recycle_callback<F>(cb: F)
+    where F: Fn(&str) + Send + Sync + 'static
+{
+    (*CONN).set_recycle_callback(Some(Arc::new(cb)));
+}
+
+/// Remove the callback set by `set_recycle_callback()`
+pub fn clear_recycle_callback() {
+    (*CONN).set_recycle_callback(None);
+}
+
 /// Wipe out all queues
 pub fn wipe() {
     (*CONN).wipe();
@@ -250,6 +672,16 @@ mod tests {
         handle.join().unwrap();
     }
 
+    #[test]
+    fn recv_reports_age() {
+        send_string("aging", String::from("sit tight")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let (msg, age) = recv_with_age("aging").unwrap();
+        assert_eq!(String::from_utf8(msg).unwrap(), "sit tight");
+        assert!(age >= Duration::from_millis(100));
+        assert!(age < Duration::from_millis(1000));
+    }
+
     #[test]
     fn lock_testing() {
         let num_tests = 999;
@@ -276,6 +708,264 @@ mod tests {
         assert_eq!(*(counter.read().unwrap()), num_tests);
     }
 
+    /// Just enough of an executor to drive a future from a test: park the thread
+    /// until the future's waker unparks us.
+    #[cfg(feature = "async")]
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+        impl ::std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) { self.0.unpark(); }
+        }
+        let mut fut = Box::pin(fut);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(x) => return x,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn recv_async_waits() {
+        struct CountingWaker(AtomicUsize);
+        impl ::std::task::Wake for CountingWaker {
+            fn wake(self: Arc<Self>) { self.0.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        // a message that's already waiting comes right back
+        send_string("async-ready", String::from("already here")).unwrap();
+        assert_eq!(String::from_utf8(block_on(recv_async("async-ready")).unwrap()).unwrap(), "already here");
+
+        // otherwise we're pending until a send wakes us up
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = recv_async("async-pending");
+        assert!(Pin::new(&mut fut).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut fut).poll(&mut cx).is_pending());
+        // pending receivers keep the channel around
+        assert!((*CONN).exists(&String::from("async-pending")));
+        send_string("async-pending", String::from("wake up")).unwrap();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        match Pin::new(&mut fut).poll(&mut cx) {
+            Poll::Ready(msg) => assert_eq!(String::from_utf8(msg.unwrap()).unwrap(), "wake up"),
+            Poll::Pending => panic!("recv_async() -- still pending after a send"),
+        }
+        drop(fut);
+        assert!(!(*CONN).exists(&String::from("async-pending")));
+
+        // and from another thread, with a task that's actually parked
+        let handle = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            send_string("async-thread", String::from("hello, there")).unwrap();
+        });
+        assert_eq!(String::from_utf8(block_on(recv_async("async-thread")).unwrap()).unwrap(), "hello, there");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn caps_memory() {
+        // use our own carrier here so we don't start rejecting messages the
+        // other tests are sending
+        let carrier = Carrier::new().unwrap();
+        let chan = String::from("bigmsgs");
+        carrier.set_max_bytes(Some(3 * 1024 * 1024));
+        carrier.push(&chan, vec![0u8; 1024 * 1024]).unwrap();
+        carrier.push(&chan, vec![1u8; 1024 * 1024]).unwrap();
+        assert_eq!(carrier.total_bytes(), 2 * 1024 * 1024);
+        carrier.push(&chan, vec![2u8; 1024 * 1024]).unwrap();
+        assert_eq!(carrier.total_bytes(), 3 * 1024 * 1024);
+        match carrier.push(&chan, vec![3u8; 1]) {
+            Err(CError::Full(size, total, max)) => {
+                assert_eq!(size, 1);
+                assert_eq!(total, 3 * 1024 * 1024);
+                assert_eq!(max, 3 * 1024 * 1024);
+            }
+            _ => panic!("expected a full carrier"),
+        }
+        // rejected messages don't count against us
+        assert_eq!(carrier.total_bytes(), 3 * 1024 * 1024);
+
+        // receiving frees up room
+        let (msg, _) = carrier.pop(&chan);
+        assert_eq!(msg[0], 0);
+        assert_eq!(carrier.total_bytes(), 2 * 1024 * 1024);
+        carrier.push(&chan, vec![3u8; 1024]).unwrap();
+        assert_eq!(carrier.try_pop(&chan).unwrap()[0], 1);
+        assert_eq!(carrier.try_pop(&chan).unwrap()[0], 2);
+        assert_eq!(carrier.try_pop(&chan).unwrap()[0], 3);
+        assert_eq!(carrier.total_bytes(), 0);
+        assert_eq!(carrier.try_pop(&chan), None);
+
+        // no cap, no problem
+        carrier.set_max_bytes(None);
+        carrier.push(&chan, vec![0u8; 4 * 1024 * 1024]).unwrap();
+        assert_eq!(carrier.total_bytes(), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn counts_messages() {
+        let carrier = Carrier::new().unwrap();
+        assert_eq!(carrier.metrics(), CarrierMetrics::default());
+        let chan = String::from("metrics");
+        carrier.push(&chan, vec![1, 2, 3]).unwrap();
+        carrier.push(&chan, vec![4, 5, 6]).unwrap();
+        carrier.push_priority(&chan, vec![7]).unwrap();
+        carrier.pop(&chan);
+        carrier.try_pop(&chan).unwrap();
+        assert_eq!(carrier.try_pop(&String::from("nothing here")), None);
+        assert_eq!(carrier.metrics(), CarrierMetrics { sent: 3, received: 2, dropped: 0 });
+
+        carrier.set_max_bytes(Some(4));
+        assert!(carrier.push(&chan, vec![0u8; 4]).is_err());
+        carrier.push(&String::from("metrics2"), vec![1]).unwrap();
+        assert_eq!(carrier.metrics(), CarrierMetrics { sent: 4, received: 2, dropped: 1 });
+        // wiped messages never got received, so they count as dropped too
+        carrier.wipe();
+        assert_eq!(carrier.metrics(), CarrierMetrics { sent: 4, received: 2, dropped: 3 });
+
+        let before = metrics();
+        send("metrics:global", vec![1]).unwrap();
+        recv("metrics:global").unwrap();
+        let after = metrics();
+        assert!(after.sent > before.sent);
+        assert!(after.received > before.received);
+    }
+
+    #[test]
+    fn recycle_callbacks() {
+        let carrier = Carrier::new().unwrap();
+        let recycled = Arc::new(Mutex::new(Vec::new()));
+        let recycled2 = recycled.clone();
+        carrier.set_recycle_callback(Some(Arc::new(move |channel: &str| {
+            recycled2.lock().unwrap().push(String::from(channel));
+        })));
+        let chan = String::from("recyclable");
+        carrier.push(&chan, Vec::from(String::from("reduce, reuse").as_bytes())).unwrap();
+        assert_eq!(recycled.lock().unwrap().len(), 0);
+        carrier.try_pop(&chan).unwrap();
+        assert_eq!(*recycled.lock().unwrap(), vec![String::from("recyclable")]);
+
+        carrier.push(&String::from("wipe1"), vec![1]).unwrap();
+        carrier.push(&String::from("wipe2"), vec![2]).unwrap();
+        carrier.wipe();
+        let mut recycled_now = recycled.lock().unwrap().clone();
+        recycled_now.sort();
+        assert_eq!(recycled_now, vec![String::from("recyclable"), String::from("wipe1"), String::from("wipe2")]);
+
+        carrier.set_recycle_callback(None);
+        carrier.push(&chan, vec![3]).unwrap();
+        carrier.try_pop(&chan).unwrap();
+        assert_eq!(recycled.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn round_robin_receivers() {
+        let num_receivers = 4;
+        let queue: Arc<Queue<usize>> = Arc::new(Queue::new());
+        let mut handles = Vec::with_capacity(num_receivers);
+        for i in 0..num_receivers {
+            let queue2 = queue.clone();
+            handles.push(thread::spawn(move || queue2.pop()));
+            // wait for this receiver to take its ticket so they line up in
+            // the order we spawned them
+            while recover!(queue.line.lock(), "round_robin_receivers()").next_ticket < (i as u64) + 1 {
+                thread::yield_now();
+            }
+        }
+        for i in 0..num_receivers {
+            queue.push(i, false);
+        }
+        let got = handles.into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(got, (0..num_receivers).collect::<Vec<_>>());
+        assert_eq!(queue.num_users(), 0);
+        assert_eq!(queue.num_messages(), 0);
+    }
+
+    #[test]
+    fn priority_messages() {
+        for i in 0..5 {
+            send_string("prio", format!("data {}", i)).unwrap();
+        }
+        send_priority("prio", Vec::from(String::from("pause").as_bytes())).unwrap();
+        send_priority("prio", Vec::from(String::from("shutdown").as_bytes())).unwrap();
+        send_string("prio", String::from("data 5")).unwrap();
+        let mut received = Vec::new();
+        while let Some(msg) = recv_nb("prio").unwrap() {
+            received.push(String::from_utf8(msg).unwrap());
+        }
+        assert_eq!(received, vec!["pause", "shutdown", "data 0", "data 1", "data 2", "data 3", "data 4", "data 5"]);
+
+        // blocking receivers get them first too
+        send_string("prio-blocking", String::from("data")).unwrap();
+        send_priority("prio-blocking", Vec::from(String::from("shutdown").as_bytes())).unwrap();
+        assert_eq!(recv("prio-blocking").unwrap(), b"shutdown");
+        assert_eq!(recv("prio-blocking").unwrap(), b"data");
+    }
+
+    #[test]
+    fn recv_from_any() {
+        let channels = ["any-events", "any-reqres"];
+        let handle = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            send_string("any-reqres", String::from("response")).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            send_string("any-events", String::from("event")).unwrap();
+        });
+        let (channel, msg) = recv_any(&channels, Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(channel, "any-reqres");
+        assert_eq!(String::from_utf8(msg).unwrap(), "response");
+        let (channel, msg) = recv_any(&channels, Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(channel, "any-events");
+        assert_eq!(String::from_utf8(msg).unwrap(), "event");
+        handle.join().unwrap();
+
+        // messages already waiting come back right away, first channel first
+        send_string("any-reqres", String::from("second")).unwrap();
+        send_string("any-events", String::from("first")).unwrap();
+        let (channel, msg) = recv_any(&channels, Duration::from_millis(0)).unwrap().unwrap();
+        assert_eq!(channel, "any-events");
+        assert_eq!(String::from_utf8(msg).unwrap(), "first");
+        let (channel, _) = recv_any(&channels, Duration::from_millis(0)).unwrap().unwrap();
+        assert_eq!(channel, "any-reqres");
+
+        let start = Instant::now();
+        assert_eq!(recv_any(&channels, Duration::from_millis(100)).unwrap(), None);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn survives_poisoned_locks() {
+        let carrier = Arc::new(Carrier::new().unwrap());
+        let channel = String::from("poisoned");
+        carrier.push(&channel, Vec::from(String::from("before").as_bytes())).unwrap();
+
+        // panic while holding everything we can get our hands on
+        let carrier2 = carrier.clone();
+        let channel2 = channel.clone();
+        let res = thread::spawn(move || {
+            let queue = carrier2.ensure(&channel2);
+            let _internal = queue.internal.lock().unwrap();
+            let _activity = carrier2.activity.lock().unwrap();
+            let _queues = carrier2.queues.write().unwrap();
+            panic!("oh no");
+        }).join();
+        assert!(res.is_err());
+        assert!(carrier.queues.is_poisoned());
+        assert!(carrier.activity.is_poisoned());
+
+        carrier.push(&channel, Vec::from(String::from("after").as_bytes())).unwrap();
+        assert_eq!(carrier.count(), 1);
+        assert_eq!(carrier.try_pop(&channel), Some(Vec::from(String::from("before").as_bytes())));
+        assert_eq!(carrier.pop(&channel).0, Vec::from(String::from("after").as_bytes()));
+        assert_eq!(carrier.pop_any(&[channel.clone()], Duration::from_millis(10)), None);
+    }
+
     // Would love to test wiping, but running in multi-thread mode screws up the
     // other tests, so for now it's disabled.
     /*
@@ -30,6 +30,10 @@ quick_error! {
             description("url parse error")
             display("url parse error: {}", err)
         }
+        Disallowed(url: String) {
+            description("clipping not allowed")
+            display("clipping not allowed for url: {}", url)
+        }
         Selector(err: String) {
             description("selector parse error")
             display("selector parse error: {}", err)
@@ -21,10 +21,13 @@ use ::error::{CResult, CError};
 use ::std::env;
 use ::std::io::Read;
 use ::url::Url;
-use ::scraper::{Html, Selector};
+use ::scraper::{Html, Selector, ElementRef};
 use ::regex::Regex;
 use ::std::path::PathBuf;
 use ::std::fs::File;
+use ::std::collections::HashMap;
+use ::std::sync::Mutex;
+use ::std::time::{Duration, Instant};
 use ::jedi::Value;
 
 lazy_static! {
@@ -63,6 +66,13 @@ lazy_static! {
             }
         }
     };
+
+    /// Our clip cache. Starts off disabled (see `set_cache()`).
+    static ref CACHE: Mutex<ClipCache> = Mutex::new(ClipCache::new(0, Duration::from_secs(0)));
+
+    /// Which pages we're willing to clip. Starts off allowing everything (see
+    /// `set_policy()`).
+    static ref POLICY: Mutex<ClipPolicy> = Mutex::new(Default::default());
 }
 
 /// A struct used to tell the bookmarker how to find various pieces of info
@@ -92,7 +102,7 @@ pub struct CustomParser {
 }
 
 /// A struct that wraps up a bookmark scrape result
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, Default)]
 pub struct ClipResult {
     /// The title of the resource we're bookmarking
     title: Option<String>,
@@ -100,6 +110,19 @@ pub struct ClipResult {
     description: Option<String>,
     /// The most prominent image for the url
     image_url: Option<String>,
+    /// The URL we actually ended up at (after following any redirects)
+    final_url: String,
+    /// The HTTP status of the final response
+    status: u16,
+    /// The page's self-declared canonical URL (`<link rel="canonical">`)
+    canonical_url: Option<String>,
+    /// The page's main body text (only filled in if asked for)
+    text: Option<String>,
+    /// All the (absolute) links on the page (only filled in if asked for)
+    links: Vec<String>,
+    /// Set if the page asked not to be indexed and we honored it, in which
+    /// case everything but the url/status is left blank
+    noindex: bool,
 }
 
 impl ClipResult {
@@ -109,12 +132,205 @@ impl ClipResult {
             title: title,
             description: desc,
             image_url: img,
+            ..Default::default()
+        }
+    }
+
+    /// Create an empty result for a page that asked not to be indexed
+    fn noindex(final_url: String, status: u16) -> Self {
+        ClipResult {
+            final_url: final_url,
+            status: status,
+            noindex: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Tells `clip()` how to go about clipping a page
+#[derive(Debug, Clone, Default)]
+pub struct ClipOptions {
+    /// A proxy (`host:port`) to grab the page through
+    pub proxy: Option<String>,
+    /// Try to pull out the main body text of the page (see `extract_text()`)
+    pub extract_text: bool,
+    /// Grab all the page's links (see `extract_links()`)
+    pub extract_links: bool,
+    /// If caching is on (see `set_cache()`), return a recent result for the
+    /// same URL without hitting the network
+    pub use_cache: bool,
+}
+
+/// A cached clip result
+struct CacheEntry {
+    result: ClipResult,
+    /// Whether we extracted the page text for this result
+    has_text: bool,
+    /// Whether we extracted the page links for this result
+    has_links: bool,
+    /// When we clipped this
+    stored: Instant,
+    /// When this entry was last handed out, for evicting the least recently
+    /// used entry
+    used: u64,
+}
+
+/// An in-memory LRU cache of clip results, keyed by URL. Entries older than
+/// `ttl` are ignored (and cleaned up), and once we hit `capacity` entries the
+/// least recently used one gets booted to make room.
+struct ClipCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<String, CacheEntry>,
+    /// Counts up every time we touch the cache, so entries know how recently
+    /// they were used
+    tick: u64,
+}
+
+impl ClipCache {
+    /// Create a new cache. A capacity of 0 disables caching.
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        ClipCache {
+            capacity: capacity,
+            ttl: ttl,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Grab a fresh result for the given URL, if we have one. If we want the
+    /// page text (or links), a result clipped without it doesn't count.
+    fn get(&mut self, url: &String, extract_text: bool, extract_links: bool) -> Option<ClipResult> {
+        let expired = match self.entries.get(url) {
+            Some(entry) => entry.stored.elapsed() >= self.ttl,
+            None => return None,
+        };
+        if expired {
+            self.entries.remove(url);
+            return None;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(url)?;
+        if extract_text && !entry.has_text { return None; }
+        if extract_links && !entry.has_links { return None; }
+        entry.used = tick;
+        Some(entry.result.clone())
+    }
+
+    /// Store a result for the given URL, making room if we need to.
+    fn put(&mut self, url: &String, result: &ClipResult, has_text: bool, has_links: bool) {
+        if self.capacity == 0 { return; }
+        if !self.entries.contains_key(url) && self.entries.len() >= self.capacity {
+            let lru = self.entries.iter()
+                .min_by_key(|&(_, entry)| entry.used)
+                .map(|(url, _)| url.clone());
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(url.clone(), CacheEntry {
+            result: result.clone(),
+            has_text: has_text,
+            has_links: has_links,
+            stored: Instant::now(),
+            used: self.tick,
+        });
+    }
+}
+
+/// Set up caching of clip results: we'll hold on to up to `capacity` results
+/// (dropping the least recently used ones first), and each result is good for
+/// `ttl`. A capacity of 0 turns the cache off (the default). This clears out
+/// anything already cached.
+pub fn set_cache(capacity: usize, ttl: Duration) {
+    let mut cache = CACHE.lock().expect("clippo::set_cache() -- failed to grab cache lock");
+    *cache = ClipCache::new(capacity, ttl);
+}
+
+/// Decides which pages we're willing to clip
+#[derive(Default)]
+struct ClipPolicy {
+    /// Whether to honor `<meta name="robots" content="noindex">`
+    respect_noindex: bool,
+    /// If non-empty, only these domains (and their subdomains) can be clipped
+    allow_domains: Vec<String>,
+    /// These domains (and their subdomains) can never be clipped
+    deny_domains: Vec<String>,
+}
+
+impl ClipPolicy {
+    /// Create a new policy. Domains are matched case-insensitively.
+    fn new(respect_noindex: bool, allow_domains: Vec<String>, deny_domains: Vec<String>) -> Self {
+        let normalize = |domains: Vec<String>| {
+            domains.into_iter()
+                .map(|x| x.trim().trim_start_matches('.').to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+        };
+        ClipPolicy {
+            respect_noindex: respect_noindex,
+            allow_domains: normalize(allow_domains),
+            deny_domains: normalize(deny_domains),
         }
     }
+
+    /// Check if we're allowed to clip pages on the given host. The deny list
+    /// wins over the allow list.
+    fn allows(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        let matches = |domain: &String| {
+            host == *domain || host.ends_with(format!(".{}", domain).as_str())
+        };
+        if self.deny_domains.iter().any(&matches) { return false; }
+        self.allow_domains.is_empty() || self.allow_domains.iter().any(&matches)
+    }
+
+    /// Make sure we're allowed to clip the given url, or else
+    fn check_url(&self, url: &String) -> CResult<()> {
+        let url_parsed = Url::parse(url.as_str())?;
+        if self.allows(url_parsed.host_str().unwrap_or("")) {
+            Ok(())
+        } else {
+            Err(CError::Disallowed(url.clone()))
+        }
+    }
+}
+
+/// Set which pages we're willing to clip. If `respect_noindex` is set, pages
+/// with a robots `noindex` meta tag come back as an empty result flagged with
+/// `noindex`. A non-empty `allow_domains` restricts clipping to those domains,
+/// and `deny_domains` are always refused (both include subdomains).
+pub fn set_policy(respect_noindex: bool, allow_domains: Vec<String>, deny_domains: Vec<String>) {
+    let mut policy = POLICY.lock().expect("clippo::set_policy() -- failed to grab policy lock");
+    *policy = ClipPolicy::new(respect_noindex, allow_domains, deny_domains);
+}
+
+/// Check if a page's robots meta tags ask us not to index it
+fn is_noindex(doc: &Html) -> CResult<bool> {
+    for el in doc.select(&parse_selector("meta[name]")?) {
+        let elv = el.value();
+        let name = elv.attr("name").unwrap_or("").to_lowercase();
+        if name != "robots" { continue; }
+        let noindex = elv.attr("content").unwrap_or("")
+            .split(',')
+            .map(|x| x.trim().to_lowercase())
+            .any(|x| x == "noindex" || x == "none");
+        if noindex { return Ok(true); }
+    }
+    Ok(false)
+}
+
+/// Holds the bits of an HTTP response we care about when clipping
+struct Grabbed {
+    html: String,
+    final_url: String,
+    status: u16,
 }
 
 /// Convert a URL to HTML
-fn grab_url(url: &String, proxy: Option<String>) -> CResult<String> {
+fn grab_url(url: &String, proxy: Option<String>) -> CResult<Grabbed> {
     let mut client_builder = reqwest::blocking::Client::builder();
     if let Some(proxy_cfg) = proxy {
         client_builder = client_builder.proxy(reqwest::Proxy::http(format!("http://{}", proxy_cfg).as_str())?);
@@ -127,9 +343,11 @@ fn grab_url(url: &String, proxy: Option<String>) -> CResult<String> {
         //.header("Accept-Encoding", "")
         .header("Cache-Control", "max-age=0")
         .build()?;
-    let html = client.execute(req)
+    let grabbed = client.execute(req)
         .map_err(|e| { From::from(e) })
         .and_then(|mut res| {
+            let final_url = String::from(res.url().as_str());
+            let status = res.status().as_u16();
             let mut out = String::new();
             let str_res = res.read_to_string(&mut out)
                 .map_err(|e| From::from(e))
@@ -144,22 +362,117 @@ fn grab_url(url: &String, proxy: Option<String>) -> CResult<String> {
                 };
                 return Err(CError::Http(res.status(), errstr));
             }
-            str_res.map(move |x| x)
+            str_res.map(move |x| Grabbed { html: x, final_url: final_url, status: status })
         })?;
-    Ok(html)
+    Ok(grabbed)
+}
+
+/// A helpful function to parse CSS selectors and convert them to CResult
+/// objects. we can't really implement From::from() for selector errors
+/// since the error objects are just (), so we localize the conversion here.
+fn parse_selector(sel: &str) -> CResult<Selector> {
+    Selector::parse(sel)
+        .map_err(|_| CError::Selector(format!("cannot parse selector {}", sel)))
+}
+
+/// Elements that hold page furniture, not article content
+const NOISE_ELEMENTS: [&'static str; 9] = ["nav", "header", "footer", "aside", "menu", "form", "script", "style", "noscript"];
+
+/// Pull the main article text out of a page, readability-style. Each paragraph
+/// that isn't stuck inside of page furniture (nav, footer, etc) gives its text
+/// length to its parent element, and the parent with the most text wins. We
+/// return the winner's paragraphs separated by blank lines.
+fn extract_text(doc: &Html) -> CResult<Option<String>> {
+    fn is_noise(el: &ElementRef) -> bool {
+        el.ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|x| NOISE_ELEMENTS.contains(&x.value().name()))
+    }
+    fn para_text(el: &ElementRef) -> String {
+        el.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    // (parent, score), in document order
+    let mut scores = Vec::new();
+    for para in doc.select(&parse_selector("p")?) {
+        if is_noise(&para) { continue; }
+        let parent = match para.parent() {
+            Some(x) => x,
+            None => continue,
+        };
+        let len = para_text(&para).len();
+        match scores.iter().position(|&(node, _)| node == parent) {
+            Some(idx) => scores[idx].1 += len,
+            None => scores.push((parent, len)),
+        }
+    }
+    let best = match scores.into_iter().filter(|&(_, score)| score > 0).max_by_key(|&(_, score)| score) {
+        Some((node, _)) => node,
+        None => return Ok(None),
+    };
+    let paras = best.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|x| x.value().name() == "p")
+        .map(|x| para_text(&x))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    Ok(Some(paras.join("\n\n")))
+}
+
+/// Pull every link out of a page: each `<a href>`, made absolute (relative to
+/// `base`), minus its fragment, and deduplicated (in document order). Links
+/// that don't go anywhere we can follow (`javascript:`, `mailto:`, fragment
+/// only, etc) are skipped.
+fn extract_links(doc: &Html, base: &Url) -> CResult<Vec<String>> {
+    let mut links: Vec<String> = Vec::new();
+    for el in doc.select(&parse_selector("a[href]")?) {
+        let href = el.value().attr("href").unwrap_or("").trim();
+        if href.is_empty() || href.starts_with("#") { continue; }
+        let mut link = match base.join(href) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        if link.scheme() != "http" && link.scheme() != "https" { continue; }
+        link.set_fragment(None);
+        let link = String::from(link.as_str());
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    Ok(links)
 }
 
 /// Given a url, scrape the HTML of the page and try to determine the page
-/// title, description, and main image.
-pub fn clip(url: &String, parsers: &Vec<CustomParser>, proxy: Option<String>) -> CResult<ClipResult> {
-    let html = grab_url(url, proxy)?;
+/// title, description, and main image. Depending on `options`, we can also
+/// pull out the page's main body text and links, or return a cached result.
+///
+/// URLs on domains our policy refuses (see `set_policy()`) return an error.
+pub fn clip(url: &String, parsers: &Vec<CustomParser>, options: &ClipOptions) -> CResult<ClipResult> {
+    POLICY.lock().expect("clippo::clip() -- failed to grab policy lock").check_url(url)?;
+    if options.use_cache {
+        let cached = CACHE.lock().expect("clippo::clip() -- failed to grab cache lock").get(url, options.extract_text, options.extract_links);
+        if let Some(res) = cached {
+            return Ok(res);
+        }
+    }
+    let res = clip_uncached(url, parsers, options)?;
+    // even if we skipped the cache on the way in, store the fresh result
+    CACHE.lock().expect("clippo::clip() -- failed to grab cache lock").put(url, &res, options.extract_text, options.extract_links);
+    Ok(res)
+}
 
-    /// A helpful function to parse CSS selectors and convert them to CResult
-    /// objects. we can't really implement From::from() for selector errors
-    /// since the error objects are just (), so we localize the conversion here.
-    fn parse_selector(sel: &str) -> CResult<Selector> {
-        Selector::parse(sel)
-            .map_err(|_| CError::Selector(format!("cannot parse selector {}", sel)))
+/// Does the actual clipping for `clip()`
+fn clip_uncached(url: &String, parsers: &Vec<CustomParser>, options: &ClipOptions) -> CResult<ClipResult> {
+    let Grabbed { html, final_url, status } = grab_url(url, options.proxy.clone())?;
+    let respect_noindex = {
+        let policy = POLICY.lock().expect("clippo::clip() -- failed to grab policy lock");
+        // a redirect doesn't get us around the domain rules
+        policy.check_url(&final_url)?;
+        policy.respect_noindex
+    };
+    let doc = Html::parse_document(html.as_str());
+    if respect_noindex && is_noindex(&doc)? {
+        return Ok(ClipResult::noindex(final_url, status));
     }
 
     // set up our final return objects
@@ -302,7 +615,6 @@ pub fn clip(url: &String, parsers: &Vec<CustomParser>, proxy: Option<String>) ->
     selector_img.push(parse_selector("meta[property=\"og:image\"]")?);
     selector_img.push(parse_selector("meta[property=\"twitter:image\"]")?);
 
-    let doc = Html::parse_document(html.as_str());
     for sel_title in selector_title {
         if title.is_some() { break; }
         for el in doc.select(&sel_title) {
@@ -340,22 +652,251 @@ pub fn clip(url: &String, parsers: &Vec<CustomParser>, proxy: Option<String>) ->
         }
     }
 
-    Ok(ClipResult::new(title, desc, img))
+    let mut canonical_url = None;
+    for el in doc.select(&parse_selector("link[rel=\"canonical\"]")?) {
+        if let Some(href) = el.value().attr("href") {
+            canonical_url = Some(String::from(href.trim()));
+            break;
+        }
+    }
+
+    let text = if options.extract_text { extract_text(&doc)? } else { None };
+    let links = if options.extract_links {
+        extract_links(&doc, &Url::parse(final_url.as_str())?)?
+    } else {
+        Vec::new()
+    };
+
+    Ok(ClipResult {
+        title: title,
+        description: desc,
+        image_url: img,
+        final_url: final_url,
+        status: status,
+        canonical_url: canonical_url,
+        text: text,
+        links: links,
+        noindex: false,
+    })
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ::std::io::{BufRead, BufReader, Write};
+    use ::std::net::TcpListener;
+    use ::std::thread;
+
+    /// Serve pages on localhost for the rest of the test run. `respond` gets
+    /// each request line and hands back the full HTTP response to send.
+    /// Returns the base url.
+    fn serve<F>(mut respond: F) -> String
+        where F: FnMut(&str) -> String + Send + 'static
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut reqline = String::new();
+                reader.read_line(&mut reqline).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() { break; }
+                }
+                stream.write_all(respond(reqline.as_str()).as_bytes()).unwrap();
+            }
+        });
+        base
+    }
+
+    /// Wrap a page up in a 200 response
+    fn html_response(body: &str) -> String {
+        format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn tracks_redirects() {
+        let base = serve(|reqline| {
+            if reqline.starts_with("GET /old-page ") {
+                String::from("HTTP/1.1 301 Moved Permanently\r\nLocation: /new-page\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            } else {
+                html_response(r#"<html><head><title>new page</title><link rel="canonical" href="https://turtlapp.com/new-page"></head><body></body></html>"#)
+            }
+        });
+        let url = format!("{}/old-page", base);
+        let res = clip(&url, &vec![], &Default::default()).unwrap();
+        assert_eq!(res.status, 200);
+        assert!(res.final_url != url);
+        assert_eq!(res.final_url, format!("{}/new-page", base));
+        assert_eq!(res.canonical_url, Some(String::from("https://turtlapp.com/new-page")));
+        assert_eq!(res.title, Some(String::from("new page")));
+        assert_eq!(res.text, None);
+    }
+
+    #[test]
+    fn respects_noindex() {
+        let base = serve(|_| {
+            html_response(r#"<html><head><title>secret page</title><meta name="Robots" content="nofollow, NOINDEX"><meta name="description" content="shh"></head><body><p>please do not look at this</p></body></html>"#)
+        });
+        let url = format!("{}/secret", base);
+
+        // by default, we clip it anyway
+        let res = clip(&url, &vec![], &Default::default()).unwrap();
+        assert_eq!(res.title, Some(String::from("secret page")));
+        assert!(!res.noindex);
+
+        set_policy(true, vec![], vec![]);
+        let res = clip(&url, &vec![], &ClipOptions { extract_text: true, ..Default::default() }).unwrap();
+        set_policy(false, vec![], vec![]);
+        assert!(res.noindex);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.final_url, url);
+        assert_eq!(res.title, None);
+        assert_eq!(res.description, None);
+        assert_eq!(res.text, None);
+    }
+
+    #[test]
+    fn extracts_links() {
+        let base = serve(|_| {
+            html_response(r##"<html><head><title>links</title></head><body>
+                <a href="/about">about</a>
+                <a href="docs/intro.html#setup">intro</a>
+                <a href="https://turtlapp.com/download">download</a>
+                <a href="/about#team">about (again)</a>
+                <a href="#top">top</a>
+                <a href="mailto:info@turtlapp.com">email</a>
+                <a href="javascript:void(0)">nothing</a>
+                <a>no href</a>
+            </body></html>"##)
+        });
+        let url = format!("{}/pages/index.html", base);
+
+        let res = clip(&url, &vec![], &Default::default()).unwrap();
+        assert_eq!(res.links.len(), 0);
+
+        let res = clip(&url, &vec![], &ClipOptions { extract_links: true, ..Default::default() }).unwrap();
+        assert_eq!(res.links, vec![
+            format!("{}/about", base),
+            format!("{}/pages/docs/intro.html", base),
+            String::from("https://turtlapp.com/download"),
+        ]);
+    }
+
+    #[test]
+    fn checks_domain_lists() {
+        let policy = ClipPolicy::new(false, vec![], vec![String::from("Evil.com")]);
+        assert!(!policy.allows("evil.com"));
+        assert!(!policy.allows("www.EVIL.com"));
+        assert!(policy.allows("notevil.com"));
+        assert!(policy.allows("turtlapp.com"));
+        match policy.check_url(&String::from("https://blog.evil.com/post")) {
+            Err(CError::Disallowed(url)) => assert_eq!(url, "https://blog.evil.com/post"),
+            _ => panic!("evil.com should be denied"),
+        }
+
+        let policy = ClipPolicy::new(false, vec![String::from("turtlapp.com"), String::from(".lyonbros.com")], vec![String::from("bad.turtlapp.com")]);
+        assert!(policy.allows("turtlapp.com"));
+        assert!(policy.allows("api.turtlapp.com"));
+        assert!(policy.allows("lyonbros.com"));
+        assert!(!policy.allows("bad.turtlapp.com"));
+        assert!(!policy.allows("amazon.com"));
+    }
+
+    #[test]
+    fn caches_clips() {
+        let hits = ::std::sync::Arc::new(::std::sync::atomic::AtomicUsize::new(0));
+        let hits2 = hits.clone();
+        let base = serve(move |_| {
+            let count = hits2.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst) + 1;
+            html_response(format!("<html><head><title>visit {}</title></head><body></body></html>", count).as_str())
+        });
+        let hits = move || hits.load(::std::sync::atomic::Ordering::SeqCst);
+
+        set_cache(10, Duration::from_secs(300));
+        let url = format!("{}/cached", base);
+        let res = clip(&url, &vec![], &ClipOptions { use_cache: true, ..Default::default() }).unwrap();
+        assert_eq!(res.title, Some(String::from("visit 1")));
+        let res = clip(&url, &vec![], &ClipOptions { use_cache: true, ..Default::default() }).unwrap();
+        assert_eq!(res.title, Some(String::from("visit 1")));
+        assert_eq!(hits(), 1);
+
+        // skipping the cache hits the network (and refreshes the cache)
+        let res = clip(&url, &vec![], &Default::default()).unwrap();
+        assert_eq!(res.title, Some(String::from("visit 2")));
+        let res = clip(&url, &vec![], &ClipOptions { use_cache: true, ..Default::default() }).unwrap();
+        assert_eq!(res.title, Some(String::from("visit 2")));
+        assert_eq!(hits(), 2);
+
+        // a result without text doesn't satisfy a request for text
+        clip(&url, &vec![], &ClipOptions { extract_text: true, use_cache: true, ..Default::default() }).unwrap();
+        assert_eq!(hits(), 3);
+
+        // expired entries get re-clipped
+        set_cache(10, Duration::from_millis(50));
+        clip(&url, &vec![], &ClipOptions { use_cache: true, ..Default::default() }).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        clip(&url, &vec![], &ClipOptions { use_cache: true, ..Default::default() }).unwrap();
+        assert_eq!(hits(), 5);
+        set_cache(0, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let res = |title: &str| ClipResult::new(Some(String::from(title)), None, None);
+        let mut cache = ClipCache::new(2, Duration::from_secs(300));
+        let (url1, url2, url3) = (String::from("http://turtl.it/1"), String::from("http://turtl.it/2"), String::from("http://turtl.it/3"));
+        cache.put(&url1, &res("one"), false, false);
+        cache.put(&url2, &res("two"), false, false);
+        // touch 1 so 2 is the one that gets the boot
+        assert!(cache.get(&url1, false, false).is_some());
+        cache.put(&url3, &res("three"), false, false);
+        assert_eq!(cache.get(&url1, false, false).unwrap().title, Some(String::from("one")));
+        assert!(cache.get(&url2, false, false).is_none());
+        assert_eq!(cache.get(&url3, false, false).unwrap().title, Some(String::from("three")));
+
+        let mut disabled = ClipCache::new(0, Duration::from_secs(300));
+        disabled.put(&url1, &res("one"), false, false);
+        assert!(disabled.get(&url1, false, false).is_none());
+    }
+
+    #[test]
+    fn extracts_article_text() {
+        let html = r#"<html><head><title>huge ships</title></head><body>
+            <header><p>Welcome to the Ship Avoidance Society. Members get a free whistle.</p></header>
+            <nav><ul><li><a href="/">home</a></li><li><a href="/about">about</a></li></ul><p>Check out our other articles about boats, ships, barges, and other large floating objects.</p></nav>
+            <div class="content">
+                <article>
+                    <h1>How to avoid huge ships</h1>
+                    <p>The first rule of avoiding huge ships is to be aware that they exist.</p>
+                    <p>Huge ships are <b>very</b> large and cannot stop quickly, so stay well out of their way.</p>
+                    <div class="ad"><p>Buy a boat!</p></div>
+                    <p>When in doubt, go the other direction.</p>
+                </article>
+                <aside><p>Related: how to avoid medium ships, small ships, and ships of indeterminate size.</p></aside>
+            </div>
+            <footer><p>Copyright the Ship Avoidance Society. All rights reserved. Please do not reproduce.</p></footer>
+        </body></html>"#;
+        let doc = Html::parse_document(html);
+        let text = extract_text(&doc).unwrap().unwrap();
+        assert_eq!(text, "The first rule of avoiding huge ships is to be aware that they exist.\n\nHuge ships are very large and cannot stop quickly, so stay well out of their way.\n\nWhen in doubt, go the other direction.");
+
+        let doc = Html::parse_document("<html><body><nav><p>just a menu</p></nav></body></html>");
+        assert_eq!(extract_text(&doc).unwrap(), None);
+    }
 
     #[test]
     fn clips_stuff() {
-        let res = clip(&String::from("https://www.amazon.com/Avoid-Huge-Ships-John-Trimmer/dp/0870334336/ref=pd_lpo_sbs_241_img_2?_encoding=UTF8&psc=1&refRID=SZKJN64CTAYQ44WPNN09"), &vec![], None).unwrap();
+        let res = clip(&String::from("https://www.amazon.com/Avoid-Huge-Ships-John-Trimmer/dp/0870334336/ref=pd_lpo_sbs_241_img_2?_encoding=UTF8&psc=1&refRID=SZKJN64CTAYQ44WPNN09"), &vec![], &Default::default()).unwrap();
         assert_eq!(res.title, Some(String::from("How to Avoid Huge Ships: John W. Trimmer: 9780870334337: Amazon.com: Books")));
         assert_eq!(res.description, Some(String::from("Book by Trimmer, John W.")));
         //assert_eq!(res.image_url, Some(String::from("https://images-na.ssl-images-amazon.com/images/I/714PH4X5FRL._SY344_BO1,204,203,200_.gif")));
 
-        let res = clip(&String::from("https://www.youtube.com/watch?v=1KfaQ6pmv18"), &vec![], None).unwrap();
+        let res = clip(&String::from("https://www.youtube.com/watch?v=1KfaQ6pmv18"), &vec![], &Default::default()).unwrap();
         assert_eq!(res.title, Some(String::from("King Gizzard & The Lizard Wizard- I’m In Your Mind Fuzz full album")));
         assert_eq!(res.description, Some(String::from("1.I\'m In Your Mind ")));
         assert_eq!(res.image_url, Some(String::from("https://img.youtube.com/vi/1KfaQ6pmv18/hqdefault.jpg")));
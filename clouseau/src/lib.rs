@@ -11,7 +11,8 @@ extern crate rusqlite;
 use ::std::error::Error;
 use ::std::mem;
 
-use ::rusqlite::{Connection, NO_PARAMS};
+use ::rusqlite::{Connection, DatabaseName, NO_PARAMS};
+use ::rusqlite::backup::Progress;
 
 //                          ....~?=:::~M8.+$??Z$DON??=Z+,+=~.....               
 //           ...           ....~?IZO==+:=$+:+:?.$8=I.$~::+:=~....               
@@ -110,6 +111,23 @@ impl Clouseau {
         })
     }
 
+    /// Load a previously saved index (see `save_to`) from a file into a fresh
+    /// in-memory Clouseau. The file itself is not touched after this returns.
+    pub fn load_from(path: &str) -> CResult<Clouseau> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        Ok(Clouseau {
+            conn: conn,
+        })
+    }
+
+    /// Save a snapshot of the in-memory index to the given file (overwriting
+    /// whatever is there), which can later be loaded via `load_from`.
+    pub fn save_to(&self, path: &str) -> CResult<()> {
+        self.conn.backup(DatabaseName::Main, path, None)?;
+        Ok(())
+    }
+
     /// Index an object
     pub fn index(&self, id: &String, body: &String) -> CResult<()> {
         self.conn.execute("INSERT OR REPLACE INTO objects (id, content) VALUES (?, ?)", &[id, body])?;
@@ -133,6 +151,20 @@ impl Clouseau {
         Ok(ids)
     }
 
+    /// Merge the index's internal b-trees together, which makes searches
+    /// faster and frees up space left behind by unindexed objects.
+    pub fn optimize(&self) -> CResult<()> {
+        self.conn.execute("INSERT INTO objects (objects) VALUES ('optimize')", NO_PARAMS)?;
+        Ok(())
+    }
+
+    /// Get the size of the index (in bytes), going by how many pages it uses
+    pub fn size(&self) -> CResult<i64> {
+        let pages: i64 = self.conn.query_row("PRAGMA page_count", NO_PARAMS, |row| row.get(0))?;
+        let page_size: i64 = self.conn.query_row("PRAGMA page_size", NO_PARAMS, |row| row.get(0))?;
+        Ok(pages * page_size)
+    }
+
     /// Close this Clouseau instance
     pub fn close(&mut self) -> CResult<()> {
         let mut conn = Connection::open_in_memory()?;
@@ -171,6 +203,39 @@ mod tests {
         assert_eq!(search.find(&String::from("some say")).unwrap().len(), 0);
     }
 
+    #[test]
+    fn saves_and_loads() {
+        let path = ::std::env::temp_dir().join(format!("clouseau-test-{}.sqlite", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let search = Clouseau::new().unwrap();
+        search.index(&String::from("1234"), &String::from("some say your nose")).unwrap();
+        search.index(&String::from("2222"), &String::from("some say your toes")).unwrap();
+        search.save_to(path).unwrap();
+        drop(search);
+
+        let search = Clouseau::load_from(path).unwrap();
+        ::std::fs::remove_file(path).unwrap();
+        assert_eq!(search.find(&String::from("some say")).unwrap(), vec![String::from("1234"), String::from("2222")]);
+        assert_eq!(search.find(&String::from("toes")).unwrap(), vec![String::from("2222")]);
+        search.index(&String::from("3333"), &String::from("i think it's your toes")).unwrap();
+        assert_eq!(search.find(&String::from("toes")).unwrap(), vec![String::from("2222"), String::from("3333")]);
+    }
+
+    #[test]
+    fn optimizes() {
+        let search = Clouseau::new().unwrap();
+        for i in 0..200 {
+            search.index(&format!("{}", i), &format!("some say your nose, some say your toes. this is number {}", i)).unwrap();
+        }
+        for i in 0..100 {
+            search.unindex(&format!("{}", i)).unwrap();
+        }
+        assert!(search.size().unwrap() > 0);
+        search.optimize().unwrap();
+        assert_eq!(search.find(&String::from("toes")).unwrap().len(), 100);
+        assert_eq!(search.find(&String::from("150")).unwrap(), vec![String::from("150")]);
+    }
+
     #[test]
     fn index_large_document() {
         let search = Clouseau::new().unwrap();
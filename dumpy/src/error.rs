@@ -2,6 +2,7 @@
 
 use ::std::error::Error;
 use ::std::convert::From;
+use ::std::io;
 
 use ::rusqlite::Error as SqlError;
 use ::jedi::JSONError;
@@ -22,11 +23,20 @@ quick_error! {
             description(err.to_string())
             display("SQL error: {}", err.to_string())
         }
+        UniqueViolation(table: String, index: String, vals: String) {
+            description("unique index violation")
+            display("unique index violation: {}.{} already has an object with values `{}`", table, index, vals)
+        }
         JSON(err: JSONError) {
             cause(err)
             description("JSON error")
             display("JSON error: {}", err)
         }
+        Io(err: io::Error) {
+            cause(err)
+            description("IO error")
+            display("IO error: {}", err)
+        }
     }
 }
 
@@ -42,5 +52,11 @@ impl From<JSONError> for DError {
     }
 }
 
+impl From<io::Error> for DError {
+    fn from(err: io::Error) -> DError {
+        DError::Io(err)
+    }
+}
+
 pub type DResult<T> = Result<T, DError>;
 
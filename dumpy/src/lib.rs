@@ -14,11 +14,22 @@
 //!      in the index table for each value, and point each one to your target
 //!      object.
 //!
+//! Indexes can also be marked `"unique": true` in the schema, in which case no
+//! two objects in the table can share the same values for that index (objects
+//! with a blank value in any of the index's fields are exempt, much like NULLs
+//! in a regular SQL unique index).
+//!
+//! Tables marked `"compress": true` in the schema store their object bodies
+//! zlib-compressed. Objects stored before compression was turned on (or after
+//! it's turned off) are still read normally, so flipping the flag doesn't
+//! require migrating anything.
+//!
 //! All that said, unless this use-case fits yours perfectly, don't use this
 //! library. It's interface could be thought of as a crude IndexedDB. It was
 //! made specifically for the Turtl app and probably won't ever do the things
 //! you want it to.
 
+extern crate flate2;
 extern crate jedi;
 extern crate libc;
 #[macro_use]
@@ -26,10 +37,16 @@ extern crate quick_error;
 extern crate rusqlite;
 extern crate serde_json;
 
+use ::std::io::{Read, Write};
+use ::std::time::{SystemTime, UNIX_EPOCH};
+use ::flate2::Compression;
+use ::flate2::read::ZlibDecoder;
+use ::flate2::write::ZlibEncoder;
 use ::rusqlite::{Connection, NO_PARAMS};
 use ::rusqlite::types::Value as SqlValue;
 use ::rusqlite::types::{ToSql, ToSqlOutput};
 use ::rusqlite::Error as SqlError;
+use ::rusqlite::ErrorCode as SqlErrorCode;
 use ::jedi::{Value, JSONError};
 
 pub mod error;
@@ -62,6 +79,11 @@ impl ToSql for SearchVal {
     }
 }
 
+/// The first byte of a compressed `data` value. Compressed objects are stored
+/// as blobs (uncompressed objects are stored as text), and the marker leaves
+/// room for switching compression formats later on.
+const COMPRESSED_ZLIB: u8 = 1;
+
 /// The Dumpy struct stores our schema and acts as a namespace for our public
 /// functions.
 pub struct Dumpy {
@@ -85,18 +107,92 @@ impl Dumpy {
         conn.execute("CREATE INDEX IF NOT EXISTS dumpy_idx_index ON dumpy_index (table_name, index_name, vals)", NO_PARAMS)?;
         conn.execute("CREATE INDEX IF NOT EXISTS dumpy_idx_index_obj ON dumpy_index (table_name, object_id)", NO_PARAMS)?;
         conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS dumpy_idx_kv ON dumpy_kv (key)", NO_PARAMS)?;
+
+        // older databases won't have updated_at on their objects, so add it in
+        // (existing objects just get 0 until they're next saved)
+        if !self.has_column(conn, "dumpy_objects", "updated_at")? {
+            conn.execute("ALTER TABLE dumpy_objects ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0", NO_PARAMS)?;
+        }
+        conn.execute("CREATE INDEX IF NOT EXISTS dumpy_idx_objects_updated ON dumpy_objects (table_name, updated_at)", NO_PARAMS)?;
+        // same deal for the numeric index column
+        if !self.has_column(conn, "dumpy_index", "vals_num")? {
+            conn.execute("ALTER TABLE dumpy_index ADD COLUMN vals_num REAL", NO_PARAMS)?;
+        }
+        // rows for unique indexes copy their vals into vals_unique, which has a
+        // real unique index on it. everything else leaves it NULL, which sqlite
+        // never considers a duplicate.
+        if !self.has_column(conn, "dumpy_index", "vals_unique")? {
+            conn.execute("ALTER TABLE dumpy_index ADD COLUMN vals_unique VARCHAR(256)", NO_PARAMS)?;
+        }
+        conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS dumpy_idx_index_unique ON dumpy_index (table_name, index_name, vals_unique)", NO_PARAMS)?;
         Ok(())
     }
 
+    /// Check if the given table has a column
+    fn has_column(&self, conn: &Connection, table: &str, column: &str) -> DResult<bool> {
+        let mut query = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let rows = query.query_map(NO_PARAMS, |row| -> rusqlite::Result<String> {
+            row.get("name")
+        })?;
+        for name in rows {
+            if name? == column { return Ok(true); }
+        }
+        Ok(false)
+    }
+
+    /// Get the name of the field objects in the given table are keyed by. This
+    /// is set per-table in the schema via `id_field` and defaults to "id".
+    fn id_field(&self, table: &String) -> String {
+        jedi::get_opt::<String>(&[table, "id_field"], &self.schema)
+            .unwrap_or_else(|| String::from("id"))
+    }
+
+    /// Whether or not objects in the given table get compressed when stored
+    fn compressed(&self, table: &String) -> bool {
+        jedi::get_opt::<bool>(&[table, "compress"], &self.schema).unwrap_or(false)
+    }
+
+    /// Turn an object's JSON into whatever we put in the `data` column: either
+    /// the JSON as-is, or for compressed tables, a blob holding a marker byte
+    /// followed by the compressed JSON.
+    fn encode_data(&self, table: &String, json: String) -> DResult<SqlValue> {
+        if !self.compressed(table) {
+            return Ok(SqlValue::Text(json));
+        }
+        let mut encoder = ZlibEncoder::new(vec![COMPRESSED_ZLIB], Compression::default());
+        encoder.write_all(json.as_bytes())?;
+        Ok(SqlValue::Blob(encoder.finish()?))
+    }
+
     /// Store an object!
+    ///
+    /// The object and all of its indexes are written inside of a savepoint, so
+    /// if any part of the store fails we roll back to whatever was there before
+    /// instead of leaving an object with half its indexes lying around.
     pub fn store(&self, conn: &Connection, table: &String, obj: &Value) -> DResult<()> {
-        let id: String = match jedi::get_opt(&["id"], obj) {
+        conn.execute_batch("SAVEPOINT dumpy_store")?;
+        match self.store_inner(conn, table, obj) {
+            Ok(_) => {
+                conn.execute_batch("RELEASE SAVEPOINT dumpy_store")?;
+                Ok(())
+            }
+            Err(e) => {
+                conn.execute_batch("ROLLBACK TO SAVEPOINT dumpy_store; RELEASE SAVEPOINT dumpy_store")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Does the actual work of storing an object (see `store()`)
+    fn store_inner(&self, conn: &Connection, table: &String, obj: &Value) -> DResult<()> {
+        let id_field = self.id_field(table);
+        let id: String = match jedi::get_opt(&[&id_field], obj) {
             Some(id) => id,
-            None => return Err(DError::Msg(format!("Dumpy.store() -- object being saved to table `{}` is missing `id` field", table))),
+            None => return Err(DError::Msg(format!("Dumpy.store() -- object being saved to table `{}` is missing `{}` field", table, id_field))),
         };
-        let json = jedi::stringify(obj)?;
+        let data = self.encode_data(table, jedi::stringify(obj)?)?;
         // "upsert" the object
-        conn.execute("INSERT OR REPLACE INTO dumpy_objects (id, table_name, data) VALUES ($1, $2, $3)", &[&id, table, &json])?;
+        conn.execute("INSERT OR REPLACE INTO dumpy_objects (id, table_name, data, updated_at) VALUES ($1, $2, $3, $4)", &[&id as &dyn ToSql, table, &data, &now()])?;
         // wipte out all indexes for this object
         conn.execute("DELETE FROM dumpy_index WHERE table_name = $1 AND object_id = $2", &[table, &id])?;
 
@@ -124,6 +220,7 @@ impl Dumpy {
                     _ => return Err(From::from(e)),
                 }
             };
+            let unique = jedi::get_opt::<bool>(&["unique"], index).unwrap_or(false);
             let mut val_vec: Vec<Vec<String>> = Vec::new();
             let blankval = String::from("");
 
@@ -192,13 +289,41 @@ impl Dumpy {
             }
             let mut vals: Vec<String> = Vec::new();
             combine(String::from(""), &val_vec, &mut vals);
+
+            // if the last field in the index is numeric, we also store it as a
+            // number so find_sorted() can order by it properly (otherwise we'd
+            // get "10" < "9")
+            let last_numeric = match jedi::walk(&[&fields[fields.len() - 1]], &obj) {
+                Ok(&Value::Number(_)) => true,
+                Ok(&Value::Array(ref x)) => x.len() > 0 && x.iter().all(|x| x.is_number()),
+                _ => false,
+            };
             for val in &vals {
-                conn.execute("INSERT INTO dumpy_index (table_name, index_name, vals, object_id) VALUES ($1, $2, $3, $4)", &[
-                    table,
+                let val_num: Option<f64> = if last_numeric {
+                    val.rsplit('|').next().and_then(|x| x.parse::<f64>().ok())
+                } else {
+                    None
+                };
+                let val_unique: Option<&String> = if unique && !val.split('|').any(|x| x.is_empty()) {
+                    Some(val)
+                } else {
+                    None
+                };
+                let res = conn.execute("INSERT INTO dumpy_index (table_name, index_name, vals, object_id, vals_num, vals_unique) VALUES ($1, $2, $3, $4, $5, $6)", &[
+                    table as &dyn ToSql,
                     &idx_name,
                     val,
                     &id,
-                ])?;
+                    &val_num,
+                    &val_unique,
+                ]);
+                match res {
+                    Ok(_) => {}
+                    Err(SqlError::SqliteFailure(ref e, _)) if val_unique.is_some() && e.code == SqlErrorCode::ConstraintViolation => {
+                        return Err(DError::UniqueViolation(table.clone(), idx_name.clone(), val.clone()));
+                    }
+                    Err(e) => return Err(From::from(e)),
+                }
             }
         }
         Ok(())
@@ -211,15 +336,46 @@ impl Dumpy {
         Ok(())
     }
 
+    /// Bump an object's `updated_at` to now without touching its data or its
+    /// indexes. Much cheaper than `store()` when the timestamp is all that
+    /// changed. Errors if the object doesn't exist.
+    pub fn touch(&self, conn: &Connection, table: &String, id: &String) -> DResult<()> {
+        let updated = conn.execute("UPDATE dumpy_objects SET updated_at = $1 WHERE table_name = $2 AND id = $3", &[&now() as &dyn ToSql, table, id])?;
+        if updated == 0 {
+            return Err(DError::Msg(format!("Dumpy.touch() -- {}: object {} not found", table, id)));
+        }
+        Ok(())
+    }
+
+    /// Remove every object (and its indexes) in a table, returning how many
+    /// objects were removed. Like `store()`, this runs inside a savepoint so
+    /// we never end up with objects missing their indexes (or vice versa).
+    pub fn clear_table(&self, conn: &Connection, table: &String) -> DResult<usize> {
+        conn.execute_batch("SAVEPOINT dumpy_clear_table")?;
+        let res = conn.execute("DELETE FROM dumpy_objects WHERE table_name = $1", &[table])
+            .and_then(|removed| {
+                conn.execute("DELETE FROM dumpy_index WHERE table_name = $1", &[table])?;
+                Ok(removed)
+            });
+        match res {
+            Ok(removed) => {
+                conn.execute_batch("RELEASE SAVEPOINT dumpy_clear_table")?;
+                Ok(removed)
+            }
+            Err(e) => {
+                conn.execute_batch("ROLLBACK TO SAVEPOINT dumpy_clear_table; RELEASE SAVEPOINT dumpy_clear_table")?;
+                Err(From::from(e))
+            }
+        }
+    }
+
     /// Get an object from dumpy's store
     pub fn get(&self, conn: &Connection, table: &String, id: &String) -> DResult<Option<Value>> {
         let query = "SELECT data FROM dumpy_objects WHERE id = $1 AND table_name = $2";
         let res = conn.query_row_and_then(query, &[id, table], |row| -> DResult<Value> {
             let data: SqlValue = row.get("data")?;
             match data {
-                SqlValue::Text(ref x) => {
-                    Ok(jedi::parse(x)?)
-                },
+                SqlValue::Text(_) | SqlValue::Blob(_) => decode_data(data),
                 _ => Err(DError::Msg(format!("dumpy: {}: {}: `data` field is not a string", table, id))),
             }
         });
@@ -235,9 +391,10 @@ impl Dumpy {
         }
     }
 
-    /// Find objects using a given index/values
-    pub fn find(&self, conn: &Connection, table: &String, index: &String, vals: &Vec<String>) -> DResult<Vec<Value>> {
-        let mut query = conn.prepare("SELECT object_id FROM dumpy_index WHERE table_name = $1 AND index_name = $2 AND vals LIKE $3")?;
+    /// Find the ids of objects matching the given index/values (sorted by id),
+    /// without loading the objects themselves
+    pub fn find_ids(&self, conn: &Connection, table: &String, index: &String, vals: &Vec<String>) -> DResult<Vec<String>> {
+        let mut query = conn.prepare("SELECT DISTINCT object_id FROM dumpy_index WHERE table_name = $1 AND index_name = $2 AND vals LIKE $3 ORDER BY object_id ASC")?;
         let vals_str = vals
             .into_iter()
             .fold(String::new(), |acc, x| {
@@ -255,7 +412,12 @@ impl Dumpy {
         for oid in rows {
             ids.push(oid?);
         }
+        Ok(ids)
+    }
 
+    /// Find objects using a given index/values
+    pub fn find(&self, conn: &Connection, table: &String, index: &String, vals: &Vec<String>) -> DResult<Vec<Value>> {
+        let ids = self.find_ids(conn, table, index, vals)?;
         let oids = ids.into_iter().fold(String::new(), |acc, x| {
             if acc == "" {
                 format!("'{}'", x)
@@ -270,7 +432,122 @@ impl Dumpy {
         })?;
         let mut objects: Vec<Value> = Vec::new();
         for data in rows {
-            objects.push(jedi::parse(&data?)?);
+            objects.push(decode_data(data?)?);
+        }
+        Ok(objects)
+    }
+
+    /// Like `find()`, but instead of ordering by id, orders objects by the value
+    /// of the index itself. If `numeric` is true, we sort on the numeric value
+    /// of the index's *last* field, so an index on `["space_id", "mod"]` can be
+    /// searched by space_id and sorted by mod. Otherwise we sort on the index's
+    /// string value.
+    pub fn find_sorted(&self, conn: &Connection, table: &String, index: &String, vals: &Vec<String>, numeric: bool, desc: bool) -> DResult<Vec<Value>> {
+        let vals_str = vals
+            .into_iter()
+            .fold(String::new(), |acc, x| {
+                if acc == "" {
+                    format!("{}", x)
+                } else {
+                    format!("{}|{}", acc, x)
+                }
+            });
+        let vals_str = format!("{}%", vals_str);
+        let sort_col = if numeric { "i.vals_num" } else { "i.vals" };
+        // an object can have more than one entry for an index (array fields),
+        // so sort each object by its biggest (or smallest) entry
+        let (agg, direction) = if desc { ("MAX", "DESC") } else { ("MIN", "ASC") };
+        let qry = format!("SELECT o.data AS data, {}({}) AS sort_val FROM dumpy_index i INNER JOIN dumpy_objects o ON o.id = i.object_id WHERE i.table_name = $1 AND i.index_name = $2 AND i.vals LIKE $3 GROUP BY o.id ORDER BY sort_val {}, o.id ASC", agg, sort_col, direction);
+        let mut query = conn.prepare(qry.as_str())?;
+        let rows = query.query_map(&[table, index, &vals_str], |row| {
+            row.get("data")
+        })?;
+        let mut objects: Vec<Value> = Vec::new();
+        for data in rows {
+            objects.push(decode_data(data?)?);
+        }
+        Ok(objects)
+    }
+
+    /// Get the most recently updated objects across a set of tables, newest
+    /// first. Returns (table, id, updated_at) for each object, leaving it up to
+    /// the caller to load the objects themselves however they see fit.
+    pub fn recent(&self, conn: &Connection, tables: &Vec<String>, limit: Option<i32>) -> DResult<Vec<(String, String, i64)>> {
+        if tables.len() == 0 {
+            return Ok(Vec::new());
+        }
+        let mut qry_vals: Vec<SearchVal> = Vec::with_capacity(tables.len() + 1);
+        let placeholders = tables.iter()
+            .map(|x| {
+                qry_vals.push(SearchVal::String(x.clone()));
+                "?"
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        // rowid breaks ties between objects saved within the same ms, since
+        // INSERT OR REPLACE hands out a fresh rowid on every save
+        let mut qry = format!("SELECT table_name, id, updated_at FROM dumpy_objects WHERE table_name IN ({}) ORDER BY updated_at DESC, rowid DESC", placeholders);
+        if let Some(lim) = limit {
+            qry.push_str(" LIMIT ?");
+            qry_vals.push(SearchVal::Int(lim));
+        }
+        let mut query = conn.prepare(qry.as_str())?;
+        let values: Vec<&dyn ToSql> = qry_vals.iter()
+            .map(|x| {
+                let ts: &dyn ToSql = x;
+                ts
+            })
+            .collect::<Vec<_>>();
+        let rows = query.query_map(values.as_slice(), |row| {
+            Ok((row.get("table_name")?, row.get("id")?, row.get("updated_at")?))
+        })?;
+        let mut recent = Vec::new();
+        for row in rows {
+            recent.push(row?);
+        }
+        Ok(recent)
+    }
+
+    /// Find objects in a table by a field in their data, even if that field
+    /// isn't indexed. `json_path` is a JSON1 path (eg `$.title` or
+    /// `$.file.name`) and the value found there is compared as text, so
+    /// numbers match their string form. This scans the whole table, so if you
+    /// do it a lot, add an index instead. Doesn't work on compressed tables.
+    pub fn find_json(&self, conn: &Connection, table: &String, json_path: &str, value: &str) -> DResult<Vec<Value>> {
+        if self.compressed(table) {
+            return Err(DError::Msg(format!("dumpy::find_json() -- can't search inside compressed table `{}`", table)));
+        }
+        if !json_path.starts_with("$") {
+            return Err(DError::Msg(format!("dumpy::find_json() -- bad json path (must start with `$`): {}", json_path)));
+        }
+        let mut query = conn.prepare("SELECT data FROM dumpy_objects WHERE table_name = $1 AND CAST(json_extract(data, $2) AS TEXT) = $3 ORDER BY id ASC")?;
+        let rows = query.query_map(&[table.as_str(), json_path, value], |row| row.get("data"))?;
+        let mut objects: Vec<Value> = Vec::new();
+        for data in rows {
+            objects.push(decode_data(data?)?);
+        }
+        Ok(objects)
+    }
+
+    /// Find objects in a table whose data contains the given substring
+    /// anywhere (keys included, since we match against the raw JSON). This is
+    /// a crude full-table scan using `LIKE` (so it's case-insensitive for
+    /// ASCII): fine for debugging or tiny tables, but use clouseau for real
+    /// searching. Doesn't work on compressed tables.
+    pub fn search_contains(&self, conn: &Connection, table: &String, substring: &str) -> DResult<Vec<Value>> {
+        if self.compressed(table) {
+            return Err(DError::Msg(format!("dumpy::search_contains() -- can't search inside compressed table `{}`", table)));
+        }
+        let escaped = substring
+            .replace("\\", "\\\\")
+            .replace("%", "\\%")
+            .replace("_", "\\_");
+        let pattern = format!("%{}%", escaped);
+        let mut query = conn.prepare("SELECT data FROM dumpy_objects WHERE table_name = $1 AND data LIKE $2 ESCAPE '\\' ORDER BY id ASC")?;
+        let rows = query.query_map(&[table.as_str(), pattern.as_str()], |row| row.get("data"))?;
+        let mut objects: Vec<Value> = Vec::new();
+        for data in rows {
+            objects.push(decode_data(data?)?);
         }
         Ok(objects)
     }
@@ -298,7 +575,7 @@ impl Dumpy {
         let rows = query.query_map(values.as_slice(), |row| row.get("data"))?;
         let mut objects: Vec<Value> = Vec::new();
         for data in rows {
-            objects.push(jedi::parse(&data?)?);
+            objects.push(decode_data(data?)?);
         }
         Ok(objects)
     }
@@ -308,6 +585,20 @@ impl Dumpy {
         self.all_limit(conn, table, None)
     }
 
+    /// Run every object in a table (ordered by id ASC) through a callback, one
+    /// at a time, instead of loading them all into a Vec like `all()` does.
+    /// Stops at (and returns) the first error the callback gives back.
+    pub fn for_each<F>(&self, conn: &Connection, table: &String, mut cb: F) -> DResult<()>
+        where F: FnMut(Value) -> DResult<()>
+    {
+        let mut query = conn.prepare("SELECT data FROM dumpy_objects WHERE table_name = $1 ORDER BY id ASC")?;
+        let rows = query.query_map(&[table], |row| row.get("data"))?;
+        for data in rows {
+            cb(decode_data(data?)?)?;
+        }
+        Ok(())
+    }
+
     /// Get ALL objects in a table with the given IDs
     pub fn by_id(&self, conn: &Connection, table: &String, ids: &Vec<String>) -> DResult<Vec<Value>> {
         let mut qry_parts: Vec<&str> = Vec::with_capacity(ids.len() + 2);
@@ -333,7 +624,7 @@ impl Dumpy {
         let rows = prepared_qry.query_map(values.as_slice(), |row| row.get("data"))?;
         let mut objects: Vec<Value> = Vec::new();
         for data in rows {
-            objects.push(jedi::parse(&data?)?);
+            objects.push(decode_data(data?)?);
         }
         Ok(objects)
     }
@@ -373,8 +664,58 @@ impl Dumpy {
         conn.execute("DELETE FROM dumpy_kv WHERE key = $1", &[&key])?;
         Ok(())
     }
+
+    /// Checkpoint the WAL (if the db is in WAL mode) and truncate it back to
+    /// zero bytes. Handy after big batches of writes so the `-wal` file doesn't
+    /// grow forever. Errors if another connection kept us from finishing.
+    pub fn checkpoint(&self, conn: &Connection) -> DResult<()> {
+        let busy = conn.query_row_and_then("PRAGMA wal_checkpoint(TRUNCATE)", NO_PARAMS, |row| -> DResult<i64> {
+            Ok(row.get(0)?)
+        })?;
+        if busy != 0 {
+            return Err(DError::Msg(String::from("Dumpy.checkpoint() -- checkpoint was blocked by another connection")));
+        }
+        Ok(())
+    }
+
+    /// Rebuild the db file, reclaiming the space left behind by deleted
+    /// objects. Can't be run inside of a transaction.
+    pub fn vacuum(&self, conn: &Connection) -> DResult<()> {
+        conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Get the size of the db (in bytes), going by how many pages it uses
+    pub fn size(&self, conn: &Connection) -> DResult<i64> {
+        let pages: i64 = conn.query_row("PRAGMA page_count", NO_PARAMS, |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", NO_PARAMS, |row| row.get(0))?;
+        Ok(pages * page_size)
+    }
 }
 
+/// Parse the contents of a `data` column back into an object. Text is always
+/// plain JSON (which is how objects stored before compression was turned on for
+/// a table will look), and blobs are compressed JSON.
+fn decode_data(data: SqlValue) -> DResult<Value> {
+    match data {
+        SqlValue::Text(ref x) => Ok(jedi::parse(x)?),
+        SqlValue::Blob(ref x) if x.len() > 0 && x[0] == COMPRESSED_ZLIB => {
+            let mut json = String::new();
+            ZlibDecoder::new(&x[1..]).read_to_string(&mut json)?;
+            Ok(jedi::parse(&json)?)
+        }
+        SqlValue::Blob(_) => Err(DError::Msg(String::from("dumpy: `data` blob has an unknown compression marker"))),
+        _ => Err(DError::Msg(String::from("dumpy: `data` field is not a string or blob"))),
+    }
+}
+
+/// Get the current time (ms since epoch) for stamping objects
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(x) => (x.as_secs() as i64 * 1000) + (x.subsec_millis() as i64),
+        Err(_) => 0,
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -392,7 +733,7 @@ mod tests {
     }
 
     fn index_count(conn: &Connection) -> i64 {
-        conn.query_row_and_then("SELECT COUNT(*) AS count FROM dumpy_index", &[], |row| -> DResult<i64> {
+        conn.query_row_and_then("SELECT COUNT(*) AS count FROM dumpy_index", NO_PARAMS, |row| -> DResult<i64> {
             let data: SqlValue = row.get("count")?;
             match data {
                 SqlValue::Integer(ref x) => Ok(x.clone()),
@@ -420,6 +761,33 @@ mod tests {
         assert_eq!(jedi::get::<String>(&["body"], &note).unwrap(), "this is my note lol");
     }
 
+    #[test]
+    fn compresses_stuff() {
+        let conn = Connection::open_in_memory().unwrap();
+        let dumpy = Dumpy::new(jedi::parse(&String::from(r#"{"notes":{"compress":true,"indexes":[{"fields":["user_id"]}]}}"#)).unwrap());
+        dumpy.init(&conn).unwrap();
+        let table = String::from("notes");
+        // an object stored before we turned compression on
+        let plain = Dumpy::new(jedi::parse(&String::from(r#"{"notes":{"indexes":[{"fields":["user_id"]}]}}"#)).unwrap());
+        plain.store(&conn, &table, &jedi::parse(&String::from(r#"{"id":"abc122","user_id":"andrew123","body":"old"}"#)).unwrap()).unwrap();
+
+        let mut note: Value = jedi::parse(&String::from(r#"{"id":"abc123","user_id":"andrew123"}"#)).unwrap();
+        jedi::set(&["body"], &mut note, &"this is my note lol ".repeat(100)).unwrap();
+        dumpy.store(&conn, &table, &note).unwrap();
+        let (ty, len): (String, i64) = conn.query_row("SELECT typeof(data), length(data) FROM dumpy_objects WHERE id = 'abc123'", NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(ty, "blob");
+        assert!((len as usize) < jedi::stringify(&note).unwrap().len() / 4);
+
+        assert_eq!(dumpy.get(&conn, &table, &String::from("abc123")).unwrap().unwrap(), note);
+        let found = dumpy.find(&conn, &table, &String::from("user_id"), &vec![String::from("andrew123")]).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(jedi::get::<String>(&["body"], &found[0]).unwrap(), "old");
+        assert_eq!(found[1], note);
+        assert_eq!(dumpy.all(&conn, &table).unwrap().len(), 2);
+        assert!(dumpy.find_json(&conn, &table, "$.body", "old").is_err());
+        assert!(dumpy.search_contains(&conn, &table, "old").is_err());
+    }
+
     #[test]
     fn upserts() {
         let (conn, dumpy) = pre_test();
@@ -455,6 +823,129 @@ mod tests {
         assert_eq!(index_count(&conn), 4);
     }
 
+    #[test]
+    fn touches() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        let id = String::from("abc123");
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"abc123","user_id":"andrew123","boards":["1234","5678"],"body":"this is my note lol"}"#)).unwrap()).unwrap();
+        let snapshot = |conn: &Connection| -> (String, i64, Vec<(i64, String)>) {
+            let (data, updated_at) = conn.query_row("SELECT data, updated_at FROM dumpy_objects WHERE id = $1", &[&id], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+            let mut qry = conn.prepare("SELECT id, vals FROM dumpy_index WHERE object_id = $1 ORDER BY id ASC").unwrap();
+            let indexes = qry.query_map(&[&id], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+                .map(|x| x.unwrap())
+                .collect::<Vec<_>>();
+            (data, updated_at, indexes)
+        };
+        let (data1, updated1, indexes1) = snapshot(&conn);
+        assert_eq!(indexes1.len(), 4);
+
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+        dumpy.touch(&conn, &notes, &id).unwrap();
+        let (data2, updated2, indexes2) = snapshot(&conn);
+        assert!(updated2 > updated1);
+        assert_eq!(data2, data1);
+        // same rows (same rowids even), so nothing got rebuilt
+        assert_eq!(indexes2, indexes1);
+
+        assert!(dumpy.touch(&conn, &notes, &String::from("lolwut")).is_err());
+        assert!(dumpy.touch(&conn, &String::from("boards"), &id).is_err());
+    }
+
+    #[test]
+    fn finds_by_json() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n0mnm","user_id":"3443","boards":["1234"],"title":"huge ships","meta":{"views":12}}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"6tuns","user_id":"9823","boards":["1234"],"title":"small boats","meta":{"views":3}}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"zzzz","user_id":"9823","title":"huge ships"}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &String::from("boards"), &jedi::parse(&String::from(r#"{"id":"1234","title":"huge ships"}"#)).unwrap()).unwrap();
+
+        // title isn't indexed
+        let found = dumpy.find_json(&conn, &notes, "$.title", "huge ships").unwrap();
+        let ids = found.iter().map(|x| jedi::get::<String>(&["id"], x).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["n0mnm", "zzzz"]);
+
+        let found = dumpy.find_json(&conn, &notes, "$.meta.views", "3").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(jedi::get::<String>(&["id"], &found[0]).unwrap(), "6tuns");
+
+        assert_eq!(dumpy.find_json(&conn, &notes, "$.title", "medium ships").unwrap().len(), 0);
+        assert!(dumpy.find_json(&conn, &notes, "title", "huge ships").is_err());
+    }
+
+    #[test]
+    fn searches_contents() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n0mnm","body":"how to avoid HUGE ships"}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"6tuns","body":"100% of small boats"}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"zzzz","body":"huge_ships.txt"}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &String::from("boards"), &jedi::parse(&String::from(r#"{"id":"1234","title":"huge ships"}"#)).unwrap()).unwrap();
+
+        let ids = |found: Vec<Value>| found.iter().map(|x| jedi::get::<String>(&["id"], x).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids(dumpy.search_contains(&conn, &notes, "huge ships").unwrap()), vec!["n0mnm"]);
+        assert_eq!(ids(dumpy.search_contains(&conn, &notes, "huge").unwrap()), vec!["n0mnm", "zzzz"]);
+        // wildcards are matched literally
+        assert_eq!(ids(dumpy.search_contains(&conn, &notes, "0%").unwrap()), vec!["6tuns"]);
+        assert_eq!(ids(dumpy.search_contains(&conn, &notes, "e_s").unwrap()), vec!["zzzz"]);
+        assert_eq!(dumpy.search_contains(&conn, &notes, "medium ships").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn streams_objects() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        let table = String::from("notes");
+        for id in &["n1", "n3", "n2"] {
+            let note = jedi::parse(&format!(r#"{{"id":"{}","user_id":"andrew123","boards":[]}}"#, id)).unwrap();
+            dumpy.store(&conn, &table, &note).unwrap();
+        }
+        dumpy.store(&conn, &String::from("boards"), &jedi::parse(&String::from(r#"{"id":"b1"}"#)).unwrap()).unwrap();
+
+        let mut ids: Vec<String> = Vec::new();
+        dumpy.for_each(&conn, &table, |obj| {
+            ids.push(jedi::get(&["id"], &obj)?);
+            Ok(())
+        }).unwrap();
+        assert_eq!(ids, vec!["n1", "n2", "n3"]);
+
+        let mut count = 0;
+        dumpy.for_each(&conn, &String::from("spaces"), |_| { count += 1; Ok(()) }).unwrap();
+        assert_eq!(count, 0);
+
+        // errors from the callback stop the stream
+        let mut count = 0;
+        let res = dumpy.for_each(&conn, &table, |_| {
+            count += 1;
+            if count == 2 { return Err(DError::Msg(String::from("that's enough"))); }
+            Ok(())
+        });
+        assert!(res.is_err());
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn clears_tables() {
+        let (conn, dumpy) = pre_test();
+        let note1 = jedi::parse(&String::from(r#"{"id":"n0mnm","user_id":"3443","boards":["1234","5678"],"body":"this is my note lol"}"#)).unwrap();
+        let note2 = jedi::parse(&String::from(r#"{"id":"6tuns","user_id":"9823","boards":["1234","2222"],"body":"this is my note lol"}"#)).unwrap();
+        let board = jedi::parse(&String::from(r#"{"id":"1234","title":"get a job"}"#)).unwrap();
+        dumpy.init(&conn).unwrap();
+        dumpy.store(&conn, &String::from("notes"), &note1).unwrap();
+        dumpy.store(&conn, &String::from("notes"), &note2).unwrap();
+        dumpy.store(&conn, &String::from("boards"), &board).unwrap();
+        assert_eq!(index_count(&conn), 8);
+        assert_eq!(dumpy.clear_table(&conn, &String::from("notes")).unwrap(), 2);
+        assert_eq!(index_count(&conn), 0);
+        assert_eq!(dumpy.all(&conn, &String::from("notes")).unwrap().len(), 0);
+        assert!(dumpy.get(&conn, &String::from("boards"), &String::from("1234")).unwrap().is_some());
+        assert_eq!(dumpy.clear_table(&conn, &String::from("notes")).unwrap(), 0);
+    }
+
     #[test]
     fn indexes_and_searches() {
         let (conn, dumpy) = pre_test();
@@ -493,6 +984,14 @@ mod tests {
         assert_eq!(jedi::get::<String>(&["id"], &notes[1]).unwrap(), "h4iry");
         assert_eq!(jedi::get::<String>(&["id"], &notes[2]).unwrap(), "n0mnm");
 
+        let ids = dumpy.find_ids(&conn, &String::from("notes"), &String::from("boards"), &vec![String::from("1234")]).unwrap();
+        let found_ids = notes.iter().map(|x| jedi::get::<String>(&["id"], x).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, found_ids);
+        let ids = dumpy.find_ids(&conn, &String::from("notes"), &String::from("user_boards"), &vec![String::from("9823")]).unwrap();
+        assert_eq!(ids, vec!["6tuns", "p00pz"]);
+        let ids = dumpy.find_ids(&conn, &String::from("notes"), &String::from("boards"), &vec![String::from("0000")]).unwrap();
+        assert_eq!(ids.len(), 0);
+
         let all_records = dumpy.all(&conn, &String::from("notes")).unwrap();
         assert_eq!(all_records.len(), 7);
 
@@ -503,6 +1002,192 @@ mod tests {
         assert_eq!(by_ids.len(), 3);
     }
 
+    #[test]
+    fn custom_id_field() {
+        let conn = Connection::open_in_memory().unwrap();
+        let schema = jedi::parse(&String::from(r#"{"devices":{"id_field":"uuid","indexes":[{"fields":["user_id"]}]}}"#)).unwrap();
+        let dumpy = Dumpy::new(schema);
+        dumpy.init(&conn).unwrap();
+        let device1 = jedi::parse(&String::from(r#"{"uuid":"d3v1c3","user_id":"4187","name":"phone"}"#)).unwrap();
+        let device2 = jedi::parse(&String::from(r#"{"id":"n0tmyid","user_id":"4187","name":"laptop"}"#)).unwrap();
+        dumpy.store(&conn, &String::from("devices"), &device1).unwrap();
+        assert!(dumpy.store(&conn, &String::from("devices"), &device2).is_err());
+        assert_eq!(index_count(&conn), 1);
+
+        let device = dumpy.get(&conn, &String::from("devices"), &String::from("d3v1c3")).unwrap().unwrap();
+        assert_eq!(jedi::get::<String>(&["name"], &device).unwrap(), "phone");
+        let devices = dumpy.find(&conn, &String::from("devices"), &String::from("user_id"), &vec![String::from("4187")]).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(jedi::get::<String>(&["uuid"], &devices[0]).unwrap(), "d3v1c3");
+    }
+
+    #[test]
+    fn store_rolls_back_on_index_failure() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        conn.execute("CREATE TRIGGER fail_index BEFORE INSERT ON dumpy_index WHEN NEW.vals = '5678' BEGIN SELECT RAISE(ABORT, 'index insert failed'); END", NO_PARAMS).unwrap();
+
+        let note = jedi::parse(&String::from(r#"{"id":"abc123","user_id":"andrew123","boards":["1234","5678"],"body":"this is my note lol"}"#)).unwrap();
+        assert!(dumpy.store(&conn, &String::from("notes"), &note).is_err());
+        assert_eq!(dumpy.get(&conn, &String::from("notes"), &String::from("abc123")).unwrap(), None);
+        assert_eq!(index_count(&conn), 0);
+
+        // an existing object (and its indexes) survive a failed update
+        let note = jedi::parse(&String::from(r#"{"id":"abc123","user_id":"andrew123","boards":["1234"],"body":"this is my note lol"}"#)).unwrap();
+        dumpy.store(&conn, &String::from("notes"), &note).unwrap();
+        assert_eq!(index_count(&conn), 2);
+        let note2 = jedi::parse(&String::from(r#"{"id":"abc123","user_id":"andrew123","boards":["1234","5678"],"body":"i changed my mind"}"#)).unwrap();
+        assert!(dumpy.store(&conn, &String::from("notes"), &note2).is_err());
+        let stored = dumpy.get(&conn, &String::from("notes"), &String::from("abc123")).unwrap().unwrap();
+        assert_eq!(jedi::get::<String>(&["body"], &stored).unwrap(), "this is my note lol");
+        assert_eq!(index_count(&conn), 2);
+
+        // and once the problem goes away, stores work again
+        conn.execute("DROP TRIGGER fail_index", NO_PARAMS).unwrap();
+        dumpy.store(&conn, &String::from("notes"), &note2).unwrap();
+        assert_eq!(index_count(&conn), 4);
+    }
+
+    #[test]
+    fn unique_indexes() {
+        let conn = Connection::open_in_memory().unwrap();
+        let schema = jedi::parse(&String::from(r#"{"notes":{"indexes":[{"fields":["space_id"]},{"name":"space_title","fields":["space_id","title"],"unique":true}]}}"#)).unwrap();
+        let dumpy = Dumpy::new(schema);
+        dumpy.init(&conn).unwrap();
+        let table = String::from("notes");
+        let note1 = jedi::parse(&String::from(r#"{"id":"n0mnm","space_id":"1234","title":"get a job"}"#)).unwrap();
+        let note2 = jedi::parse(&String::from(r#"{"id":"6tuns","space_id":"1234","title":"sit on a couch"}"#)).unwrap();
+        let note3 = jedi::parse(&String::from(r#"{"id":"p00pz","space_id":"5678","title":"get a job"}"#)).unwrap();
+        dumpy.store(&conn, &table, &note1).unwrap();
+        dumpy.store(&conn, &table, &note2).unwrap();
+        dumpy.store(&conn, &table, &note3).unwrap();
+        // re-saving an object doesn't conflict with itself
+        dumpy.store(&conn, &table, &note1).unwrap();
+        assert_eq!(index_count(&conn), 6);
+
+        let dupe = jedi::parse(&String::from(r#"{"id":"h4iry","space_id":"1234","title":"get a job"}"#)).unwrap();
+        match dumpy.store(&conn, &table, &dupe) {
+            Err(DError::UniqueViolation(table, index, vals)) => {
+                assert_eq!(table, "notes");
+                assert_eq!(index, "space_title");
+                assert_eq!(vals, "1234|get a job");
+            }
+            x => panic!("expected a unique violation, got {:?}", x),
+        }
+        // the failed store left nothing behind
+        assert!(dumpy.get(&conn, &table, &String::from("h4iry")).unwrap().is_none());
+        assert_eq!(index_count(&conn), 6);
+
+        // blank values don't count as duplicates
+        let untitled1 = jedi::parse(&String::from(r#"{"id":"scl0c","space_id":"1234"}"#)).unwrap();
+        let untitled2 = jedi::parse(&String::from(r#"{"id":"gr1my","space_id":"1234","title":null}"#)).unwrap();
+        dumpy.store(&conn, &table, &untitled1).unwrap();
+        dumpy.store(&conn, &table, &untitled2).unwrap();
+
+        // once the original is gone, the values are up for grabs
+        dumpy.delete(&conn, &table, &String::from("n0mnm")).unwrap();
+        dumpy.store(&conn, &table, &dupe).unwrap();
+    }
+
+    #[test]
+    fn finds_sorted() {
+        let conn = Connection::open_in_memory().unwrap();
+        let schema = jedi::parse(&String::from(r#"{"notes":{"indexes":[{"name":"space_mod","fields":["space_id","mod"]}]}}"#)).unwrap();
+        let dumpy = Dumpy::new(schema);
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n1","space_id":"s1","mod":10}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n2","space_id":"s1","mod":200}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n3","space_id":"s1","mod":3}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n4","space_id":"s2","mod":9999}"#)).unwrap()).unwrap();
+
+        let ids = |objs: Vec<Value>| -> Vec<String> {
+            objs.iter().map(|x| jedi::get::<String>(&["id"], x).unwrap()).collect()
+        };
+        let index = String::from("space_mod");
+        let s1 = vec![String::from("s1")];
+        assert_eq!(ids(dumpy.find_sorted(&conn, &notes, &index, &s1, true, true).unwrap()), vec!["n2", "n1", "n3"]);
+        assert_eq!(ids(dumpy.find_sorted(&conn, &notes, &index, &s1, true, false).unwrap()), vec!["n3", "n1", "n2"]);
+        // string sorting gets it "wrong," which is why we have numeric sorting
+        assert_eq!(ids(dumpy.find_sorted(&conn, &notes, &index, &s1, false, true).unwrap()), vec!["n3", "n2", "n1"]);
+        assert_eq!(ids(dumpy.find_sorted(&conn, &notes, &index, &vec![], true, true).unwrap()), vec!["n4", "n2", "n1", "n3"]);
+    }
+
+    #[test]
+    fn lists_recent() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        // running init again on an existing db is fine
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        let boards = String::from("boards");
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n1","boards":[]}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &boards, &jedi::parse(&String::from(r#"{"id":"b1"}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n2","boards":[]}"#)).unwrap()).unwrap();
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"n1","boards":[],"title":"edited"}"#)).unwrap()).unwrap();
+
+        let ids = |recent: Vec<(String, String, i64)>| -> Vec<String> {
+            recent.into_iter().map(|(table, id, _)| format!("{}:{}", table, id)).collect()
+        };
+        assert_eq!(ids(dumpy.recent(&conn, &vec![notes.clone(), boards.clone()], None).unwrap()), vec!["notes:n1", "notes:n2", "boards:b1"]);
+        assert_eq!(ids(dumpy.recent(&conn, &vec![notes.clone(), boards.clone()], Some(2)).unwrap()), vec!["notes:n1", "notes:n2"]);
+        assert_eq!(ids(dumpy.recent(&conn, &vec![boards.clone()], None).unwrap()), vec!["boards:b1"]);
+        assert_eq!(dumpy.recent(&conn, &vec![], None).unwrap().len(), 0);
+        let recent = dumpy.recent(&conn, &vec![notes.clone()], None).unwrap();
+        assert!(recent[0].2 >= recent[1].2);
+        assert!(recent[1].2 > 0);
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut path = ::std::env::temp_dir();
+        path.push(format!("dumpy-checkpoint-test-{}.sqlite", now()));
+        let wal_path = format!("{}-wal", path.to_str().unwrap());
+        {
+            let conn = Connection::open(&path).unwrap();
+            let mode: String = conn.query_row("PRAGMA journal_mode=WAL", NO_PARAMS, |row| row.get(0)).unwrap();
+            assert_eq!(mode, "wal");
+            let dumpy = Dumpy::new(jedi::parse(&String::from(r#"{"notes":{"indexes":[{"fields":["user_id"]}]}}"#)).unwrap());
+            dumpy.init(&conn).unwrap();
+            for i in 0..500 {
+                let note = jedi::parse(&format!(r#"{{"id":"note{}","user_id":"user{}","body":"this is my note lol"}}"#, i, i % 10)).unwrap();
+                dumpy.store(&conn, &String::from("notes"), &note).unwrap();
+            }
+            assert!(::std::fs::metadata(&wal_path).unwrap().len() > 0);
+            dumpy.checkpoint(&conn).unwrap();
+            assert_eq!(::std::fs::metadata(&wal_path).unwrap().len(), 0);
+            assert_eq!(dumpy.all(&conn, &String::from("notes")).unwrap().len(), 500);
+
+            // no WAL, no problem
+            let (conn2, dumpy2) = pre_test();
+            dumpy2.checkpoint(&conn2).unwrap();
+        }
+        let _ = ::std::fs::remove_file(&wal_path);
+        let _ = ::std::fs::remove_file(format!("{}-shm", path.to_str().unwrap()));
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn vacuums() {
+        let (conn, dumpy) = pre_test();
+        dumpy.init(&conn).unwrap();
+        let notes = String::from("notes");
+        for i in 0..500 {
+            let note = jedi::parse(&format!(r#"{{"id":"note{}","user_id":"user{}","body":"this is my note lol. it is long enough to take up some room in the db, hopefully."}}"#, i, i % 10)).unwrap();
+            dumpy.store(&conn, &notes, &note).unwrap();
+        }
+        let full = dumpy.size(&conn).unwrap();
+        assert!(full > 0);
+        dumpy.clear_table(&conn, &notes).unwrap();
+        // deleting doesn't give the space back...
+        assert_eq!(dumpy.size(&conn).unwrap(), full);
+        // ...but vacuuming does
+        dumpy.vacuum(&conn).unwrap();
+        assert!(dumpy.size(&conn).unwrap() < full);
+        dumpy.store(&conn, &notes, &jedi::parse(&String::from(r#"{"id":"note1","user_id":"user1","body":"still works"}"#)).unwrap()).unwrap();
+        assert_eq!(dumpy.all(&conn, &notes).unwrap().len(), 1);
+    }
+
     #[test]
     fn kv_set_get() {
         let (conn, dumpy) = pre_test();
@@ -0,0 +1,31 @@
+include!("../src/util.rs");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::config;
+
+    #[test]
+    fn offline() {
+        let handle = init();
+        let username: String = config::get(&["integration_tests", "login", "username"]).unwrap();
+        let password: String = config::get(&["integration_tests", "login", "password"]).unwrap();
+
+        dispatch_ass(json!(["app:wipe-app-data"]));
+        dispatch_ass(json!(["app:api:set-config", {"offline": true}]));
+        let res = dispatch(json!(["user:login", username, password]));
+        assert_eq!(res.e, 1);
+        assert_eq!(jedi::get::<String>(&["type"], &res.d).unwrap(), "offline");
+        let res = dispatch(json!(["user:find-by-email", "slippyslappy@turtlapp.com"]));
+        assert_eq!(res.e, 1);
+        assert_eq!(jedi::get::<String>(&["type"], &res.d).unwrap(), "offline");
+
+        dispatch_ass(json!(["app:api:set-config", {"offline": false}]));
+        dispatch_ass(json!(["user:login", username, password]));
+        wait_on("user:login");
+        dispatch_ass(json!(["user:logout"]));
+        dispatch_ass(json!(["app:wipe-app-data"]));
+        end(handle);
+    }
+}
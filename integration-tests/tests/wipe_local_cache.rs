@@ -0,0 +1,60 @@
+include!("../src/util.rs");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::config;
+
+    #[test]
+    fn wipe_local_cache() {
+        let handle = init();
+        let username: String = config::get(&["integration_tests", "login", "username"]).unwrap();
+        let password: String = config::get(&["integration_tests", "login", "password"]).unwrap();
+
+        dispatch_ass(json!(["app:wipe-app-data"]));
+        dispatch_ass(json!(["user:login", username, password]));
+        wait_on("user:login");
+        dispatch_ass(json!(["sync:start"]));
+        wait_on("profile:loaded");
+        wait_on("profile:indexed");
+
+        // wait for sync to complete
+        sleep(1000);
+
+        let data = dispatch_ass(json!(["profile:load"]));
+        let user_id: String = jedi::get(&["user", "id"], &data).unwrap();
+        let spaces: Vec<Value> = jedi::get(&["spaces"], &data).unwrap();
+        let boards: Vec<Value> = jedi::get(&["boards"], &data).unwrap();
+        assert_eq!(spaces.len(), 3);
+        assert_eq!(boards.len(), 3);
+
+        // wipe everything local and make sure we get it all back from the
+        // server without having to log in again
+        dispatch_ass(json!(["app:wipe-local-cache"]));
+        wait_on("profile:loaded");
+        wait_on("profile:indexed");
+        sleep(1000);
+
+        let data = dispatch_ass(json!(["profile:load"]));
+        let user_id2: String = jedi::get(&["user", "id"], &data).unwrap();
+        let spaces: Vec<Value> = jedi::get(&["spaces"], &data).unwrap();
+        let boards: Vec<Value> = jedi::get(&["boards"], &data).unwrap();
+        assert_eq!(user_id2, user_id);
+        assert_eq!(spaces.len(), 3);
+        assert_eq!(boards.len(), 3);
+
+        let noteval = dispatch_ass(json!([
+            "profile:find-notes", {
+                "space_id": "015bac22440a4944baee41b88207731eaeb7e2cc5c955fb8a05b028c1409aaf55024f5d26fa3001e",
+                "sort_direction": "asc",
+            }
+        ]));
+        let notes: Vec<Value> = jedi::get(&["notes"], &noteval).unwrap();
+        assert_eq!(notes.len(), 3);
+
+        dispatch_ass(json!(["user:logout"]));
+        dispatch_ass(json!(["app:wipe-app-data"]));
+        end(handle);
+    }
+}
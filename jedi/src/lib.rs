@@ -10,6 +10,10 @@ extern crate serde_yaml;
 
 use ::std::error::Error;
 use ::std::convert::From;
+use ::std::fmt;
+use ::std::io::Read;
+use ::std::marker::PhantomData;
+use ::std::cmp::Ordering;
 
 use ::serde_json::Error as SerdeJsonError;
 use ::serde_yaml::Error as SerdeYamlError;
@@ -17,6 +21,7 @@ pub use ::serde_json::Value;
 pub use ::serde_json::Map;
 pub use ::serde::de::{Deserialize, DeserializeOwned};
 pub use ::serde::ser::Serialize;
+use ::serde::de::{Deserializer, Visitor, SeqAccess};
 
 quick_error! {
     #[derive(Debug)]
@@ -30,6 +35,11 @@ quick_error! {
             description("parse error")
             display("json: parse error: {}", err)
         }
+        Deserialize(err: serde_json::Error) {
+            cause(err)
+            description("deserialize error")
+            display("json: deserialize error: {}", err)
+        }
         Stringify(err: serde_json::Error) {
             cause(err)
             description("stringify error")
@@ -47,6 +57,14 @@ quick_error! {
             description("invalid key")
             display("json: invalid key for object: {}", key)
         }
+        LimitExceeded(msg: String) {
+            description("limit exceeded")
+            display("json: limit exceeded: {}", msg)
+        }
+        Invalid(errors: Vec<String>) {
+            description("validation failed")
+            display("json: validation failed: {}", errors.join("; "))
+        }
     }
 }
 
@@ -72,6 +90,155 @@ pub fn parse<T: DeserializeOwned>(string: &String) -> JResult<T> {
     serde_json::from_str(string).map_err(JSONError::Parse)
 }
 
+/// Like `parse`, but on failure also hands back the line and column (both
+/// starting at 1) where things went wrong, so editors can point at the exact
+/// spot.
+pub fn parse_with_location<T: DeserializeOwned>(string: &str) -> Result<T, (JSONError, usize, usize)> {
+    serde_json::from_str(string).map_err(|e| {
+        let line = e.line();
+        let column = e.column();
+        (JSONError::Parse(e), line, column)
+    })
+}
+
+/// Parse a JSON string into a `T` in one go, but unlike `parse` (which lumps
+/// everything together as a parse error) we tell apart JSON that's malformed
+/// (`JSONError::Parse`) from JSON that's fine but doesn't fit into a `T`
+/// (`JSONError::Deserialize`).
+pub fn parse_into<T: DeserializeOwned>(string: &str) -> JResult<T> {
+    let val: Value = serde_json::from_str(string).map_err(JSONError::Parse)?;
+    serde_json::from_value(val).map_err(JSONError::Deserialize)
+}
+
+/// Hands each element of a JSON array to a callback as it's deserialized.
+struct ArrayStreamVisitor<'a, T, F: 'a> {
+    cb: &'a mut F,
+    _item: PhantomData<T>,
+}
+
+impl<'de, 'a, T, F> Visitor<'de> for ArrayStreamVisitor<'a, T, F>
+    where T: DeserializeOwned,
+          F: FnMut(T)
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(item) = seq.next_element::<T>()? {
+            (self.cb)(item);
+        }
+        Ok(())
+    }
+}
+
+/// Parse a JSON array from a reader, passing each element to `cb` as soon as
+/// it's parsed instead of building the whole array in memory first. Handy for
+/// enormous payloads (like a full sync) where we only need to look at one
+/// record at a time.
+///
+/// Note that the reader is read in small chunks, so wrapping it in a
+/// `BufReader` is a good idea for anything that isn't already buffered.
+pub fn parse_array_stream<T, R>(reader: R, mut cb: impl FnMut(T)) -> JResult<()>
+    where T: DeserializeOwned,
+          R: Read
+{
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let visitor = ArrayStreamVisitor { cb: &mut cb, _item: PhantomData };
+    (&mut de).deserialize_seq(visitor).map_err(JSONError::Parse)?;
+    de.end().map_err(JSONError::Parse)
+}
+
+/// Like `parse`, but refuses (before doing any real parsing) input that is
+/// longer than `max_bytes` or that nests objects/arrays deeper than
+/// `max_depth`. Useful for data coming from places we don't fully trust.
+pub fn parse_limited<T: DeserializeOwned>(string: &String, max_depth: usize, max_bytes: usize) -> JResult<T> {
+    if string.len() > max_bytes {
+        return Err(JSONError::LimitExceeded(format!("input is {} bytes (max {})", string.len(), max_bytes)));
+    }
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in string.bytes() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match byte {
+            b'"' => { in_string = true; }
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(JSONError::LimitExceeded(format!("input nests deeper than {} levels", max_depth)));
+                }
+            }
+            // if these don't balance, the parser will let us know
+            b']' | b'}' => { depth = depth.saturating_sub(1); }
+            _ => {}
+        }
+    }
+    parse(string)
+}
+
+/// Like `parse`, except integers too big for an i64/u64 come back as strings
+/// holding their exact digits instead of being rounded off into an f64. This
+/// way giant ids/timestamps survive a parse/stringify round trip intact.
+///
+/// NOTE: we do this by quoting the big numbers before parsing rather than with
+/// serde_json's `arbitrary_precision` feature, since that feature changes how
+/// *every* number is handled by every crate linking serde_json.
+pub fn parse_exact<T: DeserializeOwned>(string: &String) -> JResult<T> {
+    let mut quoted = String::with_capacity(string.len());
+    let mut number = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    fn flush_number(number: &mut String, quoted: &mut String) {
+        if number.is_empty() { return; }
+        let is_int = number.trim_start_matches('-').chars().all(|c| c.is_ascii_digit());
+        if is_int && number.parse::<i64>().is_err() && number.parse::<u64>().is_err() {
+            quoted.push('"');
+            quoted.push_str(number);
+            quoted.push('"');
+        } else {
+            quoted.push_str(number);
+        }
+        number.clear();
+    }
+    for chr in string.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if chr == '\\' {
+                escaped = true;
+            } else if chr == '"' {
+                in_string = false;
+            }
+            quoted.push(chr);
+            continue;
+        }
+        match chr {
+            '0'..='9' | '-' => { number.push(chr); }
+            // only part of a number if we're already in one (`true` has an e)
+            '+' | '.' | 'e' | 'E' if !number.is_empty() => { number.push(chr); }
+            _ => {
+                flush_number(&mut number, &mut quoted);
+                if chr == '"' { in_string = true; }
+                quoted.push(chr);
+            }
+        }
+    }
+    flush_number(&mut number, &mut quoted);
+    parse(&quoted)
+}
+
 /// Parse a JSON byte array and return a Result<Value>
 pub fn parse_bytes<T: DeserializeOwned>(bytes: &[u8]) -> JResult<T> {
     serde_json::from_slice(bytes).map_err(JSONError::Parse)
@@ -98,6 +265,12 @@ pub fn from_val<T: DeserializeOwned>(val: Value) -> JResult<T> {
     serde_json::from_value(val).map_err(|e| JSONError::Parse(e))
 }
 
+/// Like `from_val()`, except any conversion error becomes a None value. The
+/// value-converting cousin of `get_opt()`.
+pub fn from_val_opt<T: DeserializeOwned>(val: Value) -> Option<T> {
+    from_val(val).ok()
+}
+
 /// Walk a JSON structure, given a key path. Traverses both objects and arrays,
 /// returning a reference to the found value, if any.
 ///
@@ -207,10 +380,84 @@ pub fn get_opt<T: DeserializeOwned>(keys: &[&str], value: &Value) -> Option<T> {
     }
 }
 
+/// Check whether a key path exists in a JSON structure. Works like
+/// `get_opt::<Value>(...).is_some()` but uses `walk()` so nothing gets cloned.
+/// Note that a path leading to a `null` still counts as existing.
+pub fn contains_path(keys: &[&str], value: &Value) -> bool {
+    walk(keys, value).is_ok()
+}
+
+/// Like `get()` for arrays, except if the value at the key path is a single
+/// (non-array) value, it's wrapped in a one-element Vec instead of failing.
+/// Null is treated as an empty array. Handy for data that's not consistent
+/// about its shape (`"tags": "work"` vs `"tags": ["work"]`).
+pub fn get_array_coerce<T: DeserializeOwned>(keys: &[&str], value: &Value) -> JResult<Vec<T>> {
+    let val = walk(keys, value)?;
+    let res = match *val {
+        Value::Array(_) => serde_json::from_value(val.clone()),
+        Value::Null => Ok(Vec::new()),
+        _ => serde_json::from_value(val.clone()).map(|x| vec![x]),
+    };
+    res.map_err(|e| JSONError::NotFound(format!("get_array_coerce: {:?}: {}", keys, e)))
+}
+
+/// Like `walk`, but a `*` in the key path fans out across every element of the
+/// array at that point, so `["notes", "*", "id"]` returns the id of each note.
+/// Elements that don't have the rest of the path are skipped, but the parts of
+/// the path leading up to a wildcard must exist.
+pub fn walk_all<'a>(keys: &[&str], data: &'a Value) -> JResult<Vec<&'a Value>> {
+    let mut found = Vec::new();
+    walk_all_into(keys, data, &mut found)?;
+    Ok(found)
+}
+
+fn walk_all_into<'a>(keys: &[&str], data: &'a Value, found: &mut Vec<&'a Value>) -> JResult<()> {
+    let wildcard = keys.iter().position(|x| *x == "*");
+    let idx = match wildcard {
+        Some(x) => x,
+        None => {
+            found.push(walk(keys, data)?);
+            return Ok(());
+        }
+    };
+    match *walk(&keys[0..idx], data)? {
+        Value::Array(ref arr) => {
+            for item in arr {
+                match walk_all_into(&keys[(idx + 1)..], item, found) {
+                    Ok(_) => {}
+                    Err(JSONError::NotFound(_)) | Err(JSONError::DeadEnd) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+        _ => Err(JSONError::DeadEnd),
+    }
+}
+
+/// Like `get()`, but supports `*` wildcards in the key path (see `walk_all()`)
+/// and returns every value found along the way.
+///
+/// # Examples
+///
+/// ```
+/// let parsed = json!({"notes": [{"id": "1"}, {"id": "2"}]});
+/// let ids: Vec<String> = get_all(&["notes", "*", "id"], &parsed).unwrap();
+/// ```
+pub fn get_all<T: DeserializeOwned>(keys: &[&str], value: &Value) -> JResult<Vec<T>> {
+    walk_all(keys, value)?
+        .into_iter()
+        .map(|x| {
+            serde_json::from_value(x.clone())
+                .map_err(|e| JSONError::NotFound(format!("get_all: {:?}: {}", keys, e)))
+        })
+        .collect()
+}
+
 /// Set a field into a mutable JSON Value
 pub fn set<T: Serialize>(keys: &[&str], container: &mut Value, to: &T) -> JResult<()> {
     if keys.len() == 0 {
-        return Err(JSONError::InvalidKey(format!("set: no keys given")));
+        return Err(JSONError::InvalidKey(String::from("set: no keys given")));
     }
 
     let butlast = &keys[0..(keys.len() - 1)];
@@ -234,6 +481,92 @@ pub fn set<T: Serialize>(keys: &[&str], container: &mut Value, to: &T) -> JResul
     }
 }
 
+/// Like `set`, except any objects missing along the key path are created for
+/// us (nulls along the path are also replaced with objects).
+pub fn set_create<T: Serialize>(keys: &[&str], container: &mut Value, to: &T) -> JResult<()> {
+    if keys.is_empty() {
+        return Err(JSONError::InvalidKey(String::from("set_create: no keys given")));
+    }
+    let mut cur = container;
+    for key in &keys[0..(keys.len() - 1)] {
+        if cur.is_null() {
+            *cur = Value::Object(Map::new());
+        }
+        cur = match *cur {
+            Value::Object(ref mut x) => {
+                x.entry(String::from(*key)).or_insert(Value::Null)
+            }
+            Value::Array(ref mut x) => {
+                let ukey = match key.parse::<usize>() {
+                    Ok(x) => x,
+                    Err(..) => return Err(JSONError::InvalidKey((*key).to_owned())),
+                };
+                match x.get_mut(ukey) {
+                    Some(x) => x,
+                    None => return Err(JSONError::NotFound((*key).to_owned())),
+                }
+            }
+            _ => return Err(JSONError::DeadEnd),
+        };
+    }
+    if cur.is_null() {
+        *cur = Value::Object(Map::new());
+    }
+    set(&keys[(keys.len() - 1)..], cur, to)
+}
+
+/// Add `by` to the integer at the given path, writing it back and returning the
+/// new value. A missing (or null) value counts as 0, and any objects missing
+/// along the path get created (see `set_create`). Errors if the existing value
+/// isn't an integer.
+pub fn increment(keys: &[&str], container: &mut Value, by: i64) -> JResult<i64> {
+    let current = match walk(keys, container) {
+        Ok(&Value::Null) => 0,
+        Ok(&Value::Number(ref x)) => {
+            match x.as_i64() {
+                Some(x) => x,
+                None => return Err(JSONError::InvalidKey(format!("increment() -- {:?}: {} is not an integer", keys, x))),
+            }
+        }
+        Ok(_) => return Err(JSONError::InvalidKey(format!("increment() -- {:?}: value is not a number", keys))),
+        Err(JSONError::NotFound(_)) | Err(JSONError::DeadEnd) => 0,
+        Err(e) => return Err(e),
+    };
+    let new_val = match current.checked_add(by) {
+        Some(x) => x,
+        None => return Err(JSONError::LimitExceeded(format!("increment() -- {:?}: {} + {} overflows", keys, current, by))),
+    };
+    set_create(keys, container, &new_val)?;
+    Ok(new_val)
+}
+
+/// Apply a series of `set_create` calls to a base Value, returning the result.
+/// Saves a lot of `json!` + `jedi::set` boilerplate when building up responses.
+///
+/// # Examples
+///
+/// ```
+/// let val = path_set!(json!({}),
+///     ["user", "name"] => "barky",
+///     ["user", "age"] => 7,
+/// ).unwrap();
+/// ```
+#[macro_export]
+macro_rules! path_set {
+    ($base:expr, $( [ $($key:expr),* ] => $val:expr ),* $(,)*) => {
+        {
+            let mut base: $crate::Value = $base;
+            let mut res: $crate::JResult<()> = Ok(());
+            $(
+                if res.is_ok() {
+                    res = $crate::set_create(&[$($key),*], &mut base, &$val);
+                }
+            )*
+            res.map(|_| base)
+        }
+    }
+}
+
 /// Remove a value from a JSON object.
 pub fn remove(keys: &[&str], container: &mut Value) -> JResult<()> {
     let keys = Vec::from(keys);
@@ -267,6 +600,320 @@ pub fn remove(keys: &[&str], container: &mut Value) -> JResult<()> {
     }
 }
 
+/// Remove a value from a JSON object and hand it back, saving us a clone over
+/// doing `get()` then `remove()`. Errors if there's nothing at the given path.
+pub fn take(keys: &[&str], container: &mut Value) -> JResult<Value> {
+    if keys.is_empty() {
+        return Err(JSONError::DeadEnd);
+    }
+    let butlast = &keys[0..(keys.len() - 1)];
+    let key = keys[keys.len() - 1];
+    match *walk_mut(butlast, container)? {
+        Value::Object(ref mut x) => {
+            match x.remove(key) {
+                Some(val) => Ok(val),
+                None => Err(JSONError::NotFound(key.to_owned())),
+            }
+        }
+        Value::Array(ref mut x) => {
+            let idx: usize = match key.parse() {
+                Ok(i) => i,
+                Err(_) => return Err(JSONError::InvalidKey(key.to_owned())),
+            };
+            if idx < x.len() {
+                Ok(x.remove(idx))
+            } else {
+                Err(JSONError::NotFound(key.to_owned()))
+            }
+        }
+        _ => Err(JSONError::DeadEnd),
+    }
+}
+
+/// Merge an array of objects into another, matching up elements by the value
+/// of their `key` field (an id, say). Elements in `src` that match one in
+/// `dst` get their fields copied over the matching element, and anything that
+/// doesn't match (including elements missing `key`) gets appended to `dst`.
+pub fn merge_arrays_by_key(dst: &mut Value, src: &Value, key: &str) -> JResult<()> {
+    let src_arr = match src {
+        Value::Array(ref x) => x,
+        _ => return Err(JSONError::InvalidKey(String::from("merge_arrays_by_key() -- src is not an array"))),
+    };
+    let dst_arr = match dst {
+        Value::Array(ref mut x) => x,
+        _ => return Err(JSONError::InvalidKey(String::from("merge_arrays_by_key() -- dst is not an array"))),
+    };
+    for src_val in src_arr {
+        let matched = match src_val.get(key) {
+            Some(id) => dst_arr.iter_mut().find(|x| x.get(key) == Some(id)),
+            None => None,
+        };
+        match (matched, src_val) {
+            (Some(&mut Value::Object(ref mut dst_obj)), &Value::Object(ref src_obj)) => {
+                for (k, v) in src_obj {
+                    dst_obj.insert(k.clone(), v.clone());
+                }
+            }
+            (Some(dst_val), _) => *dst_val = src_val.clone(),
+            (None, _) => dst_arr.push(src_val.clone()),
+        }
+    }
+    Ok(())
+}
+
+/// Sort a list of values in place by whatever lives at `keys` in each of them
+/// (say, `&["body", "mod"]` for a list of notes). Numbers, strings and bools
+/// sort among their own kind (numbers first, then strings, then bools), and
+/// anything else (missing, null, arrays, objects) goes to the end no matter
+/// the direction. The sort is stable, so ties keep their original order.
+pub fn sort_by_path(items: &mut Vec<Value>, keys: &[&str], descending: bool) {
+    fn rank(value: Option<&Value>) -> Option<u8> {
+        match value {
+            Some(&Value::Number(_)) => Some(0),
+            Some(&Value::String(_)) => Some(1),
+            Some(&Value::Bool(_)) => Some(2),
+            _ => None,
+        }
+    }
+    items.sort_by(|a, b| {
+        let val_a = walk(keys, a).ok();
+        let val_b = walk(keys, b).ok();
+        let (rank_a, rank_b) = match (rank(val_a), rank(val_b)) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+        };
+        let ord = match (val_a, val_b) {
+            _ if rank_a != rank_b => rank_a.cmp(&rank_b),
+            (Some(&Value::Number(ref x)), Some(&Value::Number(ref y))) => {
+                let x = x.as_f64().unwrap_or(0.0);
+                let y = y.as_f64().unwrap_or(0.0);
+                x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+            }
+            (Some(&Value::String(ref x)), Some(&Value::String(ref y))) => x.cmp(y),
+            (Some(&Value::Bool(ref x)), Some(&Value::Bool(ref y))) => x.cmp(y),
+            _ => Ordering::Equal,
+        };
+        if descending { ord.reverse() } else { ord }
+    });
+}
+
+/// Blank out the values of any keys matching `keys_to_redact` (replacing them
+/// with "<redacted>") anywhere in the given object, no matter how deep. Handy
+/// for logging payloads without spilling their secrets.
+pub fn redact(value: &mut Value, keys_to_redact: &[&str]) {
+    match value {
+        Value::Object(ref mut x) => {
+            for (key, val) in x.iter_mut() {
+                if keys_to_redact.contains(&key.as_str()) {
+                    *val = Value::String(String::from("<redacted>"));
+                } else {
+                    redact(val, keys_to_redact);
+                }
+            }
+        }
+        Value::Array(ref mut x) => {
+            for val in x.iter_mut() {
+                redact(val, keys_to_redact);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collect the values of every key named `key`, anywhere in the tree (depth
+/// first, so parents come before their children). Values that don't convert
+/// to `T` are skipped. Handy for poking around nested payloads when you know
+/// what a key is called but not where it lives.
+pub fn deep_find<T: DeserializeOwned>(key: &str, value: &Value) -> Vec<T> {
+    let mut found = Vec::new();
+    deep_find_into(key, value, &mut found);
+    found
+}
+
+/// Does the actual recursing for `deep_find()`
+fn deep_find_into<T: DeserializeOwned>(key: &str, value: &Value, found: &mut Vec<T>) {
+    match value {
+        Value::Object(ref x) => {
+            for (k, val) in x {
+                if k == key {
+                    if let Ok(converted) = from_val(val.clone()) {
+                        found.push(converted);
+                    }
+                }
+                deep_find_into(key, val, found);
+            }
+        }
+        Value::Array(ref x) => {
+            for val in x {
+                deep_find_into(key, val, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Percent-encode a string for use in a URL query string. Everything but the
+/// unreserved characters (RFC 3986) gets encoded.
+fn percent_encode(string: &str) -> String {
+    let mut out = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Encode a flat object of scalars (strings, numbers, bools) into a URL query
+/// string, eg `{"q":"huge ships","page":2}` -> `page=2&q=huge%20ships`. Keys
+/// with null values are skipped, and nested objects/arrays are an error.
+pub fn to_query_string(value: &Value) -> JResult<String> {
+    let obj = match value {
+        Value::Object(ref x) => x,
+        _ => return Err(JSONError::InvalidKey(String::from("to_query_string() -- can only encode objects"))),
+    };
+    let mut pairs = Vec::with_capacity(obj.len());
+    for (key, val) in obj {
+        let val = match val {
+            Value::Null => continue,
+            Value::String(ref x) => x.clone(),
+            Value::Number(ref x) => x.to_string(),
+            Value::Bool(x) => x.to_string(),
+            Value::Array(_) | Value::Object(_) => {
+                return Err(JSONError::InvalidKey(format!("to_query_string() -- {}: can't encode nested values", key)));
+            }
+        };
+        pairs.push(format!("{}={}", percent_encode(key), percent_encode(&val)));
+    }
+    Ok(pairs.join("&"))
+}
+
+/// Grab the schema name of a Value's type (see `validate()`)
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(ref x) if x.is_i64() || x.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Check an object against a (very) simple schema, eg:
+///
+///     {"required": ["title", "space_id"], "types": {"title": "string", "tags": "array", "mod": ["integer", "null"]}}
+///
+/// `required` lists keys that must be present (and not null), and `types` maps
+/// keys to the type (or list of types) their values must have if present. The
+/// types are null, bool, number, integer, string, array, and object (integers
+/// count as numbers). Rather than bailing on the first problem, we return a
+/// `JSONError::Invalid` listing everything that's wrong.
+pub fn validate(value: &Value, schema: &Value) -> JResult<()> {
+    let obj = match value {
+        Value::Object(ref x) => x,
+        _ => return Err(JSONError::Invalid(vec![format!("expected object, got {}", type_name(value))])),
+    };
+    let required: Vec<String> = match schema.get("required") {
+        Some(x) => from_val(x.clone())?,
+        None => vec![],
+    };
+    let types: Map<String, Value> = match schema.get("types") {
+        Some(x) => from_val(x.clone())?,
+        None => Map::new(),
+    };
+
+    let mut errors = Vec::new();
+    for key in &required {
+        match obj.get(key) {
+            None | Some(&Value::Null) => errors.push(format!("{}: missing required field", key)),
+            _ => {}
+        }
+    }
+    for (key, expected) in &types {
+        let expected: Vec<String> = match expected {
+            Value::String(ref x) => vec![x.clone()],
+            _ => from_val(expected.clone())?,
+        };
+        let val = match obj.get(key) {
+            Some(x) => x,
+            None => continue,
+        };
+        // a null in a required field was already reported above
+        if val.is_null() && required.contains(key) { continue; }
+        let actual = type_name(val);
+        let matches = expected.iter().any(|x| {
+            x == actual || (x == "number" && actual == "integer")
+        });
+        if !matches {
+            errors.push(format!("{}: expected {}, got {}", key, expected.join(" or "), actual));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(JSONError::Invalid(errors))
+    }
+}
+
+/// Roughly estimate how many bytes a Value takes up in memory, without having
+/// to serialize it. Not exact by any means, but good enough for enforcing size
+/// limits: bigger structures always come out bigger.
+pub fn estimate_size(value: &Value) -> usize {
+    let base = ::std::mem::size_of::<Value>();
+    match value {
+        Value::Null | Value::Bool(_) | Value::Number(_) => base,
+        Value::String(ref x) => base + x.len(),
+        Value::Array(ref x) => {
+            base + x.iter().map(estimate_size).sum::<usize>()
+        }
+        Value::Object(ref x) => {
+            base + x.iter()
+                .map(|(k, v)| ::std::mem::size_of::<String>() + k.len() + estimate_size(v))
+                .sum::<usize>()
+        }
+    }
+}
+
+/// Cut a string down to `max_len` chars, adding "..." if anything was lost
+fn truncate_chars(string: &str, max_len: usize) -> String {
+    match string.char_indices().nth(max_len) {
+        Some((idx, _)) => format!("{}...", &string[0..idx]),
+        None => String::from(string),
+    }
+}
+
+/// Turn a Value into a short, human-friendly string for logging. Scalars come
+/// out as-is (strings unquoted), strings longer than `max_len` chars get cut
+/// off with "...", and arrays/objects are rendered as compact JSON, cut off
+/// the same way but with a count of their items/keys tacked on so you know
+/// what you're missing, eg `[1,2,3,4,5... (10 items)`.
+pub fn to_debug_string(value: &Value, max_len: usize) -> String {
+    match value {
+        Value::Null => String::from("null"),
+        Value::Bool(x) => x.to_string(),
+        Value::Number(ref x) => x.to_string(),
+        Value::String(ref x) => truncate_chars(x, max_len),
+        Value::Array(_) | Value::Object(_) => {
+            let full = match stringify(value) {
+                Ok(x) => x,
+                Err(_) => String::from("<unserializable>"),
+            };
+            if full.chars().count() <= max_len { return full; }
+            let summary = match value {
+                Value::Array(ref x) => format!("{} items", x.len()),
+                Value::Object(ref x) => format!("{} keys", x.len()),
+                _ => unreachable!(),
+            };
+            format!("{} ({})", truncate_chars(&full, max_len), summary)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -303,6 +950,331 @@ mod tests {
         assert_eq!(val_str2, None);
     }
 
+    #[test]
+    fn checks_paths() {
+        let parsed = get_parsed();
+        assert!(contains_path(&["1", "name"], &parsed));
+        assert!(contains_path(&["3"], &parsed));
+        assert!(contains_path(&[], &parsed));
+        assert!(!contains_path(&["1", "location"], &parsed));
+        assert!(!contains_path(&["4"], &parsed));
+        assert!(!contains_path(&["0", "name"], &parsed));
+        assert!(!contains_path(&["name"], &parsed));
+        assert!(contains_path(&["empty"], &json!({"empty": null})));
+    }
+
+    #[test]
+    fn converts_vals_lazily() {
+        let age: Option<i64> = from_val_opt(json!(17));
+        let name: Option<i64> = from_val_opt(json!("slappy"));
+        let names: Option<Vec<String>> = from_val_opt(json!(["slappy", "larry"]));
+        assert_eq!(age, Some(17));
+        assert_eq!(name, None);
+        assert_eq!(names, Some(vec![String::from("slappy"), String::from("larry")]));
+    }
+
+    #[test]
+    fn parses_with_locations() {
+        let val: Value = parse_with_location("{\"name\": \"barky\"}").unwrap();
+        assert_eq!(val, json!({"name": "barky"}));
+
+        let malformed = "{\n  \"name\": \"barky\",\n  \"age\": 12,,\n}";
+        match parse_with_location::<Value>(malformed) {
+            Err((JSONError::Parse(_), line, column)) => {
+                assert_eq!(line, 3);
+                assert_eq!(column, 13);
+            }
+            _ => panic!("expected a parse error"),
+        }
+        // type mismatches get a location too
+        match parse_with_location::<Vec<String>>("[\"get\",\n 4]") {
+            Err((_, line, column)) => assert_eq!((line, column), (2, 2)),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn parses_within_limits() {
+        let val: Value = parse_limited(&get_json(), 2, 1024).unwrap();
+        assert_eq!(val, get_parsed());
+        // brackets inside of strings don't count toward depth
+        let val: Value = parse_limited(&String::from(r#"{"name":"[[[{{\"]]]"}"#), 1, 1024).unwrap();
+        assert_eq!(get::<String>(&["name"], &val).unwrap(), r#"[[[{{"]]]"#);
+    }
+
+    #[test]
+    fn parses_big_numbers_exactly() {
+        let json = String::from(r#"{"id":123456789012345678901234567890,"neg":-98765432109876543210,"max":18446744073709551615,"small":17,"float":3.885,"exp":1e3,"str":"99999999999999999999999","arr":[170141183460469231731687303715884105727]}"#);
+        let val: Value = parse_exact(&json).unwrap();
+        assert_eq!(val["id"], json!("123456789012345678901234567890"));
+        assert_eq!(val["neg"], json!("-98765432109876543210"));
+        assert_eq!(val["max"], json!(u64::max_value()));
+        assert_eq!(val["small"], json!(17));
+        assert_eq!(val["float"], json!(3.885));
+        assert_eq!(val["exp"], json!(1000.0));
+        assert_eq!(val["str"], json!("99999999999999999999999"));
+        assert_eq!(val["arr"][0], json!("170141183460469231731687303715884105727"));
+        let id: String = get(&["id"], &val).unwrap();
+        assert_eq!(id, "123456789012345678901234567890");
+        // round trip
+        let val2: Value = parse_exact(&stringify(&val).unwrap()).unwrap();
+        assert_eq!(val, val2);
+
+        // regular parse loses the digits
+        let lossy: Value = parse(&json).unwrap();
+        assert!(lossy["id"].is_f64());
+    }
+
+    #[test]
+    fn rejects_deep_payloads() {
+        let deep = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        match parse_limited::<Value>(&deep, 32, 1024) {
+            Err(JSONError::LimitExceeded(_)) => {}
+            x => panic!("expected LimitExceeded, got {:?}", x),
+        }
+        let val: Value = parse_limited(&deep, 100, 1024).unwrap();
+        assert!(val.is_array());
+    }
+
+    #[test]
+    fn rejects_large_payloads() {
+        let large = format!(r#"{{"body":"{}"}}"#, "a".repeat(4096));
+        match parse_limited::<Value>(&large, 32, 1024) {
+            Err(JSONError::LimitExceeded(_)) => {}
+            x => panic!("expected LimitExceeded, got {:?}", x),
+        }
+        let val: Value = parse_limited(&large, 32, 8192).unwrap();
+        assert_eq!(get::<String>(&["body"], &val).unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn path_sets_stuff() {
+        let val = path_set!(json!({"type": "dog"}),
+            ["deets", "name"] => "wookie",
+            ["deets", "noise", "loud"] => "NARRyarryghgahhgg",
+            ["deets", "age"] => 7,
+            ["friends"] => vec!["timmy", "lucy"],
+            ["friends", "1"] => "larry",
+        ).unwrap();
+        assert_eq!(stringify(&val).unwrap(), r#"{"deets":{"age":7,"name":"wookie","noise":{"loud":"NARRyarryghgahhgg"}},"friends":["timmy","larry"],"type":"dog"}"#);
+
+        let res = path_set!(json!({"type": "dog"}),
+            ["type", "name"] => "wookie",
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn streams_arrays() {
+        let mut json = String::from("[");
+        for i in 0..50000 {
+            if i > 0 { json.push_str(","); }
+            json.push_str(&format!(r#"{{"id":"{}","body":"note number {}"}}"#, i, i));
+        }
+        json.push_str("]");
+
+        let mut count = 0;
+        let mut last_id = String::new();
+        parse_array_stream(json.as_bytes(), |item: Value| {
+            count += 1;
+            last_id = get(&["id"], &item).unwrap();
+        }).unwrap();
+        assert_eq!(count, 50000);
+        assert_eq!(last_id, "49999");
+
+        let mut nums: Vec<u32> = Vec::new();
+        parse_array_stream(" [1, 2, 3] ".as_bytes(), |x: u32| nums.push(x)).unwrap();
+        assert_eq!(nums, vec![1, 2, 3]);
+
+        assert!(parse_array_stream(r#"{"not":"an array"}"#.as_bytes(), |_: Value| {}).is_err());
+        assert!(parse_array_stream("[1, 2, 3".as_bytes(), |_: Value| {}).is_err());
+        assert!(parse_array_stream("[1, 2, 3] [4]".as_bytes(), |_: Value| {}).is_err());
+    }
+
+    #[test]
+    fn coerces_arrays() {
+        let val = json!({
+            "tags": "work",
+            "tags_arr": ["work", "play"],
+            "nums": 42,
+            "nothing": null,
+        });
+        assert_eq!(get_array_coerce::<String>(&["tags"], &val).unwrap(), vec!["work"]);
+        assert_eq!(get_array_coerce::<String>(&["tags_arr"], &val).unwrap(), vec!["work", "play"]);
+        assert_eq!(get_array_coerce::<u32>(&["nums"], &val).unwrap(), vec![42]);
+        assert_eq!(get_array_coerce::<String>(&["nothing"], &val).unwrap().len(), 0);
+        assert!(get_array_coerce::<u32>(&["tags"], &val).is_err());
+        assert!(get_array_coerce::<String>(&["missing"], &val).is_err());
+    }
+
+    #[test]
+    fn takes_stuff() {
+        let mut obj = json!({
+            "user": {
+                "name": "slappy",
+                "settings": {"theme": "dark", "tags": ["one", "two"]},
+            },
+        });
+        let settings = take(&["user", "settings"], &mut obj).unwrap();
+        assert_eq!(settings, json!({"theme": "dark", "tags": ["one", "two"]}));
+        assert_eq!(obj, json!({"user": {"name": "slappy"}}));
+
+        let mut settings = settings;
+        assert_eq!(take(&["tags", "0"], &mut settings).unwrap(), json!("one"));
+        assert_eq!(settings, json!({"theme": "dark", "tags": ["two"]}));
+
+        assert!(take(&["user", "settings"], &mut obj).is_err());
+        assert!(take(&["user", "name", "first"], &mut obj).is_err());
+        assert!(take(&["tags", "5"], &mut settings).is_err());
+        assert!(take(&["tags", "lol"], &mut settings).is_err());
+        assert!(take(&[], &mut obj).is_err());
+        assert_eq!(obj, json!({"user": {"name": "slappy"}}));
+    }
+
+    #[test]
+    fn redacts_stuff() {
+        let mut obj = json!({
+            "id": "1234",
+            "body": "AAYBAAzlol",
+            "keys": [{"s": "5678", "k": "AAYBAAzomg"}],
+            "notes": [
+                {"id": "4321", "body": "AAYBAAzwtf", "file": {"body": "AAYBAAzhai"}},
+                {"id": "8765", "title": "body"},
+            ],
+            "data": {"user": {"keys": null}},
+        });
+        redact(&mut obj, &["body", "keys"]);
+        assert_eq!(obj, json!({
+            "id": "1234",
+            "body": "<redacted>",
+            "keys": "<redacted>",
+            "notes": [
+                {"id": "4321", "body": "<redacted>", "file": {"body": "<redacted>"}},
+                {"id": "8765", "title": "body"},
+            ],
+            "data": {"user": {"keys": "<redacted>"}},
+        }));
+        let mut arr = json!(["body", {"body": 1}]);
+        redact(&mut arr, &[]);
+        assert_eq!(arr, json!(["body", {"body": 1}]));
+    }
+
+    #[test]
+    fn finds_keys_deeply() {
+        let payload = json!({
+            "id": "1234",
+            "space": {"id": "5678", "members": [{"id": 12, "user": {"id": "91"}}, {"role": "admin"}]},
+            "notes": [{"id": "abcd", "file": {"id": null}}],
+        });
+        let ids: Vec<Value> = deep_find("id", &payload);
+        assert_eq!(ids, vec![json!("1234"), json!(null), json!("abcd"), json!("5678"), json!(12), json!("91")]);
+        // values that don't fit the type are skipped
+        let ids: Vec<String> = deep_find("id", &payload);
+        assert_eq!(ids, vec!["1234", "abcd", "5678", "91"]);
+        let roles: Vec<String> = deep_find("role", &payload);
+        assert_eq!(roles, vec!["admin"]);
+        assert_eq!(deep_find::<Value>("title", &payload).len(), 0);
+        assert_eq!(deep_find::<Value>("id", &json!("id")).len(), 0);
+    }
+
+    #[test]
+    fn parses_into() {
+        let ids: Vec<u64> = parse_into("[1, 2, 3]").unwrap();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        match parse_into::<Vec<u64>>("[1, 2, 3") {
+            Err(JSONError::Parse(_)) => {}
+            x => panic!("expected a parse error, got {:?}", x),
+        }
+        match parse_into::<Vec<u64>>(r#"[1, "two", 3]"#) {
+            Err(e @ JSONError::Deserialize(_)) => {
+                assert!(format!("{}", e).starts_with("json: deserialize error: "));
+            }
+            x => panic!("expected a deserialize error, got {:?}", x),
+        }
+        match parse_into::<Vec<u64>>(r#"{"ids": [1, 2, 3]}"#) {
+            Err(JSONError::Deserialize(_)) => {}
+            x => panic!("expected a deserialize error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn sorts_by_path() {
+        let mut notes = vec![
+            json!({"id": "a", "body": {"mod": 300}}),
+            json!({"id": "b", "body": {"mod": 100}}),
+            json!({"id": "c", "body": {}}),
+            json!({"id": "d", "body": {"mod": 200.5}}),
+            json!({"id": "e", "body": {"mod": "yesterday"}}),
+            json!({"id": "f", "body": {"mod": 100}}),
+            json!({"id": "g", "body": {"mod": null}}),
+        ];
+        let ids = |notes: &Vec<Value>| -> Vec<String> {
+            notes.iter().map(|x| x["id"].as_str().unwrap().to_owned()).collect()
+        };
+        sort_by_path(&mut notes, &["body", "mod"], false);
+        assert_eq!(ids(&notes), vec!["b", "f", "d", "a", "e", "c", "g"]);
+        sort_by_path(&mut notes, &["body", "mod"], true);
+        assert_eq!(ids(&notes), vec!["e", "a", "d", "b", "f", "c", "g"]);
+        // nothing to sort by leaves things be
+        sort_by_path(&mut notes, &["title"], false);
+        assert_eq!(ids(&notes), vec!["e", "a", "d", "b", "f", "c", "g"]);
+    }
+
+    #[test]
+    fn gets_with_wildcards() {
+        let payload = json!({
+            "notes": [
+                {"id": "1234", "tags": ["work"]},
+                {"id": "5678", "tags": ["home", "fun"]},
+                {"title": "no id"},
+                "not an object",
+            ],
+        });
+        let ids: Vec<String> = get_all(&["notes", "*", "id"], &payload).unwrap();
+        assert_eq!(ids, vec!["1234", "5678"]);
+        let tags: Vec<String> = get_all(&["notes", "*", "tags", "*"], &payload).unwrap();
+        assert_eq!(tags, vec!["work", "home", "fun"]);
+        let first_tags: Vec<String> = get_all(&["notes", "*", "tags", "0"], &payload).unwrap();
+        assert_eq!(first_tags, vec!["work", "home"]);
+        // no wildcard works just like get()
+        let id: Vec<String> = get_all(&["notes", "1", "id"], &payload).unwrap();
+        assert_eq!(id, vec!["5678"]);
+        assert_eq!(walk_all(&["notes", "*"], &payload).unwrap().len(), 4);
+        assert_eq!(walk_all(&["notes", "*", "body"], &payload).unwrap().len(), 0);
+        assert!(get_all::<String>(&["boards", "*", "id"], &payload).is_err());
+        assert!(get_all::<String>(&["notes", "0", "id", "*"], &payload).is_err());
+        assert!(get_all::<u64>(&["notes", "*", "id"], &payload).is_err());
+    }
+
+    #[test]
+    fn estimates_sizes() {
+        let null = estimate_size(&Value::Null);
+        assert!(null > 0);
+        assert_eq!(estimate_size(&json!(42)), null);
+        assert!(estimate_size(&json!("get a job")) > estimate_size(&json!("job")));
+        assert!(estimate_size(&json!([1, 2, 3])) > estimate_size(&json!([1, 2])));
+        assert!(estimate_size(&json!([])) > 0);
+        let small = json!({"name": "slappy"});
+        let big = json!({"name": "slappy", "friends": [{"name": "timmy"}, {"name": "jimmy"}]});
+        assert!(estimate_size(&big) > estimate_size(&small));
+        assert!(estimate_size(&json!({"a": {"b": {"c": 1}}})) > estimate_size(&json!({"a": {"b": 1}})));
+        assert!(estimate_size(&get_parsed()) > estimate_size(&json!(["test"])));
+    }
+
+    #[test]
+    fn stringifies_for_debugging() {
+        assert_eq!(to_debug_string(&Value::Null, 10), "null");
+        assert_eq!(to_debug_string(&json!(true), 10), "true");
+        assert_eq!(to_debug_string(&json!(-42.5), 10), "-42.5");
+        assert_eq!(to_debug_string(&json!("get a job"), 10), "get a job");
+        assert_eq!(to_debug_string(&json!("how to avoid huge ships"), 10), "how to avo...");
+        assert_eq!(to_debug_string(&json!("ümläüts"), 3), "üml...");
+        assert_eq!(to_debug_string(&json!([1, 2, 3]), 10), "[1,2,3]");
+        assert_eq!(to_debug_string(&json!([1, 2, 3, 4, 5, 6, 7, 8, 9, 10]), 10), "[1,2,3,4,5... (10 items)");
+        assert_eq!(to_debug_string(&json!({"name": "slappy", "age": 12}), 12), "{\"age\":12,\"n... (2 keys)");
+    }
+
     #[test]
     fn removes_stuff() {
         let mut obj = json!({
@@ -320,5 +1292,104 @@ mod tests {
         remove(&["friends", "0"], &mut obj).unwrap();
         assert_eq!(stringify(&obj).unwrap(), r#"{"friends":["lucy"],"type":"dog"}"#);
     }
+
+    #[test]
+    fn merges_arrays_by_key() {
+        let mut local = json!([
+            {"item_id": "1111", "type": "space", "k": "abc"},
+            {"item_id": "2222", "type": "board", "k": "def"},
+        ]);
+        let remote = json!([
+            {"item_id": "2222", "k": "xyz", "user_id": 69},
+            {"item_id": "3333", "type": "note", "k": "ghi"},
+            {"type": "mystery"},
+        ]);
+        merge_arrays_by_key(&mut local, &remote, "item_id").unwrap();
+        assert_eq!(local, json!([
+            {"item_id": "1111", "type": "space", "k": "abc"},
+            {"item_id": "2222", "type": "board", "k": "xyz", "user_id": 69},
+            {"item_id": "3333", "type": "note", "k": "ghi"},
+            {"type": "mystery"},
+        ]));
+        // merging the same thing twice doesn't duplicate anything (well, except
+        // the entry with no key)
+        merge_arrays_by_key(&mut local, &remote, "item_id").unwrap();
+        assert_eq!(local.as_array().unwrap().len(), 5);
+
+        assert!(merge_arrays_by_key(&mut json!({}), &remote, "item_id").is_err());
+        assert!(merge_arrays_by_key(&mut local, &json!("lol"), "item_id").is_err());
+    }
+
+    #[test]
+    fn increments() {
+        let mut val = json!({"sync": {"errcount": 2, "name": "sync"}});
+        assert_eq!(increment(&["sync", "errcount"], &mut val, 1).unwrap(), 3);
+        assert_eq!(increment(&["sync", "errcount"], &mut val, -5).unwrap(), -2);
+        assert_eq!(get::<i64>(&["sync", "errcount"], &val).unwrap(), -2);
+
+        // absent counters start at 0 (making objects as needed)
+        assert_eq!(increment(&["sync", "retries"], &mut val, 1).unwrap(), 1);
+        assert_eq!(increment(&["stats", "syncs", "count"], &mut val, 4).unwrap(), 4);
+        assert_eq!(get::<i64>(&["stats", "syncs", "count"], &val).unwrap(), 4);
+        let mut val2 = json!({"errcount": null});
+        assert_eq!(increment(&["errcount"], &mut val2, 1).unwrap(), 1);
+
+        assert!(increment(&["sync", "name"], &mut val, 1).is_err());
+        assert!(increment(&["sync"], &mut val, 1).is_err());
+        let mut val3 = json!({"ratio": 1.5, "big": i64::max_value()});
+        assert!(increment(&["ratio"], &mut val3, 1).is_err());
+        assert!(increment(&["big"], &mut val3, 1).is_err());
+        assert_eq!(get::<f64>(&["ratio"], &val3).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn validates() {
+        let schema = json!({
+            "required": ["title", "space_id"],
+            "types": {
+                "title": "string",
+                "space_id": "string",
+                "tags": "array",
+                "mod": ["integer", "null"],
+                "score": "number",
+            },
+        });
+        let good = json!({"title": "how to avoid huge ships", "space_id": "1234", "tags": ["ships"], "mod": null, "score": 4});
+        validate(&good, &schema).unwrap();
+        validate(&json!({"title": "t", "space_id": "s", "score": 4.5, "extra": {}}), &schema).unwrap();
+
+        let bad = json!({"title": 42, "space_id": null, "tags": "ships", "mod": 1.5});
+        match validate(&bad, &schema) {
+            Err(JSONError::Invalid(errors)) => {
+                assert_eq!(errors, vec![
+                    "space_id: missing required field",
+                    "mod: expected integer or null, got number",
+                    "tags: expected array, got string",
+                    "title: expected string, got integer",
+                ]);
+            }
+            x => panic!("expected validation errors, got {:?}", x),
+        }
+        match validate(&json!([1, 2]), &schema) {
+            Err(JSONError::Invalid(errors)) => assert_eq!(errors, vec!["expected object, got array"]),
+            x => panic!("expected validation errors, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn builds_query_strings() {
+        let params = json!({
+            "q": "huge ships & small boats",
+            "page": 2,
+            "all": true,
+            "skip": null,
+            "tag=ünïcode": "50%/100%?",
+        });
+        assert_eq!(to_query_string(&params).unwrap(), "all=true&page=2&q=huge%20ships%20%26%20small%20boats&tag%3D%C3%BCn%C3%AFcode=50%25%2F100%25%3F");
+        assert_eq!(to_query_string(&json!({})).unwrap(), "");
+        assert!(to_query_string(&json!({"q": "ships", "deep": {"lol": 1}})).is_err());
+        assert!(to_query_string(&json!({"tags": ["ships"]})).is_err());
+        assert!(to_query_string(&json!(["ships"])).is_err());
+    }
 }
 
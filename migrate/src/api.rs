@@ -80,19 +80,34 @@ impl CallInfo {
     }
 }
 
-/// Our Api object. Responsible for making outbound calls to our Turtl server.
+/// Grab the old (v0.6) server endpoint from the config (`api.v6.endpoint`).
+/// Used when the caller doesn't have a specific server in mind.
+pub fn default_endpoint() -> MResult<String> {
+    Ok(config::get::<String>(&["api", "v6", "endpoint"])?)
+}
+
+/// Our Api object. Responsible for making outbound calls to an old Turtl
+/// server.
 pub struct Api {
     config: ApiConfig,
+    /// The base url of the server we're talking to
+    endpoint: String,
 }
 
 impl Api {
-    /// Create an Api
-    pub fn new() -> Api {
+    /// Create an Api that talks to the given (v0.6) server
+    pub fn new(endpoint: &str) -> Api {
         Api {
             config: ApiConfig::new(),
+            endpoint: String::from(endpoint),
         }
     }
 
+    /// Get the endpoint this Api talks to
+    pub fn endpoint(&self) -> &String {
+        &self.endpoint
+    }
+
     /// Set the API's authentication
     pub fn set_auth(&mut self, auth: String) -> MResult<()> {
         let auth_str = String::from("user:") + &auth;
@@ -122,7 +137,7 @@ impl Api {
 
     /// Build a full URL given a resource
     fn build_url(&self, resource: &str) -> MResult<String> {
-        let endpoint = config::get::<String>(&["api", "v6", "endpoint"])?;
+        let endpoint = &self.endpoint;
         let mut url = String::with_capacity(endpoint.len() + resource.len());
         url.push_str(endpoint.trim_end_matches('/'));
         url.push_str(resource);
@@ -202,3 +217,41 @@ impl Api {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::io::{BufRead, BufReader, Write};
+    use ::std::net::TcpListener;
+    use ::std::sync::mpsc;
+    use ::std::thread;
+
+    #[test]
+    fn calls_custom_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://127.0.0.1:{}/api/", listener.local_addr().unwrap().port());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut reqline = String::new();
+            reader.read_line(&mut reqline).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() { break; }
+            }
+            tx.send(reqline).unwrap();
+            let body = r#""6969""#;
+            let res = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(res.as_bytes()).unwrap();
+        });
+
+        let api = Api::new(endpoint.as_str());
+        assert_eq!(api.endpoint(), &endpoint);
+        let user_id: String = api.post("/auth", ApiReq::new()).unwrap();
+        assert_eq!(user_id, "6969");
+        let reqline = rx.recv().unwrap();
+        assert!(reqline.starts_with("POST /api/auth "));
+    }
+}
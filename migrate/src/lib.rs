@@ -33,8 +33,9 @@ mod crypto;
 pub mod user;
 mod util;
 
-use ::std::io::{Read, Write};
+use ::std::io::{self, Read, Write};
 use ::api::{Api, ApiReq};
+pub use ::api::default_endpoint;
 use ::error::{MError, MResult};
 use ::jedi::Value;
 pub use crypto::Key;
@@ -59,7 +60,16 @@ pub struct File {
     path: Option<PathBuf>,
 }
 
+/// Size of the chunks we base64-encode when writing decoded files to disk. This
+/// must be a multiple of 3 so the encoded chunks join into valid base64.
+const FILE_BASE64_CHUNK: usize = 3 * 16384;
+
 /// Holds the result of a profile migration.
+///
+/// Notes with files don't carry their file data inline. Instead the decoded
+/// (base64) file is written to disk and the note gets a
+/// `file.filedata.path` pointing at it, which the importer is responsible for
+/// loading (and removing) when it saves the note.
 #[derive(Default, Debug)]
 pub struct MigrateResult {
     pub boards: Vec<Value>,
@@ -106,8 +116,7 @@ fn download_file(note_id: &String, api: &Api, tries: u8) -> MResult<Vec<u8>> {
     }
     let client = client_builder.build()?;
     let mut req = client.request(reqwest::Method::GET, reqwest::Url::parse(url.as_str())?);
-    let api_endpoint = config::get::<String>(&["api", "v6", "endpoint"])?;
-    if url.contains(api_endpoint.as_str()) {
+    if url.contains(api.endpoint().as_str()) {
         let auth_header = api.get_auth().expect("migrate::download_file() -- failed to get auth header");
         req = req.header("Authorization", auth_header);
     }
@@ -155,10 +164,30 @@ fn load_file(note_id: &String) -> MResult<Vec<u8>> {
     Ok(contents)
 }
 
-fn get_profile<F>(user_id: &String, auth: &String, evfn: &mut F) -> MResult<Profile>
+/// Decrypt a downloaded note file and write it to disk as base64, returning the
+/// path of the decoded file. The base64 is written out in chunks so we never
+/// hold the decrypted file *and* its (larger) base64 encoding in memory at the
+/// same time.
+fn decode_file(note_id: &String, note_key: &Key) -> MResult<PathBuf> {
+    let filedec = {
+        let encdata = load_file(note_id)?;
+        crypto::decrypt(note_key, &encdata)?
+    };
+    let mut filepath = PathBuf::from(util::decoded_folder()?);
+    util::create_dir(&filepath)?;
+    filepath.push(note_id.clone());
+    let mut fs_file = io::BufWriter::new(fs::File::create(&filepath)?);
+    for chunk in filedec.chunks(FILE_BASE64_CHUNK) {
+        fs_file.write_all(crypto::to_base64(&Vec::from(chunk))?.as_bytes())?;
+    }
+    fs_file.flush()?;
+    Ok(filepath)
+}
+
+fn get_profile<F>(endpoint: &String, user_id: &String, auth: &String, evfn: &mut F) -> MResult<Profile>
     where F: FnMut(&str, &Value)
 {
-    let mut api = Api::new();
+    let mut api = Api::new(endpoint);
     api.set_auth(auth.clone())?;
     #[derive(Deserialize, Debug)]
     struct SyncResponse {
@@ -284,9 +313,9 @@ impl ::std::fmt::Debug for Login {
     }
 }
 
-/// Check if an account exists on the old server
-pub fn check_login(username: &String, password: &String) -> MResult<Option<Login>> {
-    let mut api = Api::new();
+/// Check if an account exists on the old server at `endpoint`
+pub fn check_login(endpoint: &String, username: &String, password: &String) -> MResult<Option<Login>> {
+    let mut api = Api::new(endpoint);
     let (key1, auth1) = user::generate_auth(username, password, 1)?;
     api.set_auth(auth1.clone())?;
     match api.post::<String>("/auth", ApiReq::new()) {
@@ -317,11 +346,12 @@ pub fn check_login(username: &String, password: &String) -> MResult<Option<Login
     Ok(None)
 }
 
-/// Migrate a v6 account to a v7 account. We do this by creating sync items
-pub fn migrate<F>(v6_login: Login, mut evfn: F) -> MResult<MigrateResult>
+/// Migrate a v6 account (on the server at `endpoint`) to a v7 account. We do
+/// this by creating sync items
+pub fn migrate<F>(endpoint: &String, v6_login: Login, mut evfn: F) -> MResult<MigrateResult>
     where F: FnMut(&str, &Value)
 {
-    let profile = get_profile(&v6_login.user_id, &v6_login.auth, &mut evfn)?;
+    let profile = get_profile(endpoint, &v6_login.user_id, &v6_login.auth, &mut evfn)?;
     let decrypted = decrypt_profile(&v6_login.key, profile, &mut evfn)?;
     // cleanup
     fs::remove_dir_all(util::file_folder()?)?;
@@ -645,28 +675,22 @@ fn decrypt_profile<F>(user_key: &Key, profile: Profile, evfn: &mut F) -> MResult
                 }
                 debug!("migrate::decrypt_profile() -- decrypted note {}", note_id);
                 evfn("decrypt-item", &json!("note"));
-                fn get_file(note_id: &String, note_key: &Key, notedata: &Value) -> Option<String> {
+                fn get_file(note_id: &String, note_key: &Key, notedata: &Value) -> Option<PathBuf> {
                     if jedi::get_opt::<Value>(&["file"], &notedata).is_none() { return None; }
-                    let encdata = match load_file(note_id) {
-                        Ok(x) => x,
-                        Err(_) => { return None; }
-                    };
-                    let filedec = match crypto::decrypt(note_key, &encdata) {
-                        Ok(x) => x,
-                        Err(_) => { return None; }
-                    };
-                    let filedec_base64 = match crypto::to_base64(&filedec) {
-                        Ok(x) => x,
-                        Err(_) => { return None; }
-                    };
-                    Some(filedec_base64)
+                    match decode_file(note_id, note_key) {
+                        Ok(x) => Some(x),
+                        Err(e) => {
+                            debug!("migrate::decrypt_profile() -- cannot decode file {}: {}", note_id, e);
+                            None
+                        }
+                    }
                 }
                 let mut merged_note = deep_merge(&mut note.clone(), &dec)?;
                 trace!("migrate::decrypt_profile() -- checking file for note {}", note_id);
-                if let Some(filebase64) = get_file(&note_id, &note_key, note) {
-                    match jedi::set(&["file", "filedata"], &mut merged_note, &json!({"data": filebase64})) {
+                if let Some(filepath) = get_file(&note_id, &note_key, note) {
+                    match jedi::set(&["file", "filedata"], &mut merged_note, &json!({"path": filepath.to_string_lossy()})) {
                         Ok(_) => {
-                            debug!("migrate::decrypt_profile() -- decrypted file {} (len {})", note_id, filebase64.len());
+                            debug!("migrate::decrypt_profile() -- decrypted file {} ({:?})", note_id, filepath);
                             evfn("decrypt-item", &json!("file"));
                         },
                         Err(e) => {
@@ -730,6 +754,7 @@ fn deep_merge(val1: &mut Value, val2: &Value) -> MResult<Value> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ::config;
     use ::jedi;
 
     #[test]
@@ -777,6 +802,26 @@ mod tests {
         deep_merge(&mut obj1, &obj2).unwrap();
     }
 
+    #[test]
+    fn decodes_files_to_disk() {
+        config::merge(&json!({"integration_tests": {"data_folder": "/tmp/turtl/integration"}})).unwrap();
+        util::create_dir(util::file_folder().unwrap()).unwrap();
+        let key = Key::new(crypto::from_base64(&String::from("2gtrzmvEQkfK9Lq+0eGqLjDrmlKBabp7T212Zdv35T0=")).unwrap());
+        // big enough (and not a multiple of 3) to span a bunch of chunks
+        let plain = (0..((FILE_BASE64_CHUNK * 5) + 1)).map(|x| (x % 251) as u8).collect::<Vec<u8>>();
+        let op = crypto::CryptoOp::new("aes", "gcm").unwrap();
+        let enc = crypto::encrypt(&key, plain.clone(), op).unwrap();
+        let note_id = String::from("migrate-decode-test");
+        save_file(&note_id, enc).unwrap();
+
+        let path = decode_file(&note_id, &key).unwrap();
+        let mut base64 = String::new();
+        fs::File::open(&path).unwrap().read_to_string(&mut base64).unwrap();
+        assert_eq!(base64, crypto::to_base64(&plain).unwrap());
+        assert_eq!(crypto::from_base64(&base64).unwrap(), plain);
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn can_decode_properly() {
         let decode_me = String::from(r#"{"name":"larry","says":"alright, shutup, parker, thank you. parker, shut up. thank you. parker...next to me. sko. right now. nobody thinks you're funny, parker. right now."}"#);
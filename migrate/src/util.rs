@@ -37,3 +37,9 @@ pub fn file_folder() -> MResult<String> {
     Ok(file_folder)
 }
 
+/// Grab the folder decoded migration files are written to. This is separate
+/// from `file_folder()` because that gets wiped when the migration finishes,
+/// and the decoded files need to stick around until they're imported.
+pub fn decoded_folder() -> MResult<String> {
+    Ok(format!("{}-decoded", file_folder()?))
+}
@@ -15,9 +15,10 @@ mod tests {
         init();
         let username = config::get::<String>(&["integration_tests", "v6_login", "username"]).unwrap();
         let password = config::get::<String>(&["integration_tests", "v6_login", "password"]).unwrap();
-        let login = migrate::check_login(&username, &password).unwrap();
+        let endpoint = migrate::default_endpoint().unwrap();
+        let login = migrate::check_login(&endpoint, &username, &password).unwrap();
         assert_eq!(login.is_some(), true);
-        migrate::migrate(login.unwrap(), |ev, args| {
+        migrate::migrate(&endpoint, login.unwrap(), |ev, args| {
             println!("migrate: event: {} -- {}", ev, args);
         }).unwrap();
     }
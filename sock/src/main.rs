@@ -6,13 +6,16 @@ extern crate time;
 extern crate tungstenite;
 
 mod logger;
+mod router;
 
 use ::std::thread;
 use ::std::time::Duration;
 use ::std::env;
-use ::std::sync::{Arc, RwLock};
+use ::std::sync::{Arc, Mutex};
+use ::std::sync::mpsc::Receiver;
 use ::std::net::TcpListener;
 use ::tungstenite::Message;
+use ::router::Router;
 
 
 /// Go to sleeeeep
@@ -29,6 +32,23 @@ fn drain_channels() {
     }
 }
 
+/// Tell core to forget about whatever the last UI was doing. We tag these with
+/// the new client's id and wait for both responses so we know core is in a
+/// fresh state before telling the client we're ready.
+fn reset_core(client_id: u32, rx: &Receiver<String>) {
+    cwrap::send(Router::tag_request(client_id, r#"["0","sync:shutdown",false]"#).as_str());
+    cwrap::send(Router::tag_request(client_id, r#"["0","user:logout",false]"#).as_str());
+    let mut responses = 0;
+    while responses < 2 {
+        match rx.recv() {
+            Ok(msg) => {
+                if msg.starts_with(r#"{"id":"0""#) { responses += 1; }
+            }
+            Err(_) => break,
+        }
+    }
+}
+
 pub fn main() {
     logger::setup_logger();
 
@@ -38,157 +58,49 @@ pub fn main() {
     let handle = cwrap::init(r#"{"messaging":{"reqres_append_mid":false}}"#);
     let server = TcpListener::bind("127.0.0.1:7472").expect("sock::main() -- failed to bind server");
     info!("* sock server bound, listening");
-    let conn_id: Arc<RwLock<u32>> = Arc::new(RwLock::new(0));
-    macro_rules! inc_conn_id {
-        ($conn:expr) => {
-            {
-                let mut guard = $conn.write().expect("sock::main() -- failed to grab conn write lock");
-                *guard += 1;
-                *guard
+    drain_channels();
+    let router: Arc<Mutex<Router>> = Arc::new(Mutex::new(Router::new()));
+
+    // one thread listens to core and hands everything it says to the router,
+    // which figures out which client(s) should get it
+    let core_router = router.clone();
+    thread::spawn(move || {
+        loop {
+            let mut idle = true;
+            if let Some(x) = cwrap::recv_nb("") {
+                info!("* core -> ui (res: {})", x.len());
+                core_router.lock().expect("sock::main() -- failed to grab router lock").route_response(x.as_str());
+                idle = false;
             }
-        }
-    }
-    macro_rules! get_conn_id {
-        ($conn:expr) => {
-            {
-                let guard = $conn.read().expect("sock::main() -- failed to grab conn read lock");
-                *guard
+            if let Some(x) = cwrap::recv_event_nb() {
+                info!("* core -> ui (ev: {})", x.len());
+                core_router.lock().expect("sock::main() -- failed to grab router lock").broadcast_event(x.as_str());
+                idle = false;
             }
+            if idle { sleep(10); }
         }
-    }
+    });
+
     for stream in server.incoming() {
-        let cid = conn_id.clone();
-        let this_conn_id = inc_conn_id!(cid);
+        let router = router.clone();
         thread::spawn(move || {
-            info!("* new connection! {}", get_conn_id!(cid));
+            let (client_id, rx, first_client) = {
+                let mut guard = router.lock().expect("sock::main() -- failed to grab router lock");
+                let (client_id, rx) = guard.register();
+                (client_id, rx, guard.num_clients() == 1)
+            };
+            info!("* new connection! {}", client_id);
             let stream = stream.unwrap();
             stream.set_nonblocking(true).expect("sock::main() -- failed to set sock to nonblocking lol");
             let mut client = tungstenite::server::accept(stream).unwrap();
-            drain_channels();
-            cwrap::send(r#"["0","sync:shutdown",false]"#);
-            cwrap::send(r#"["0","user:logout",false]"#);
-            cwrap::recv("");
-            cwrap::recv("");
-            drain_channels();
-            client.write_message(Message::text(r#"{"e":"messaging:ready","d":true}"#)).expect("sock::main() -- failed to send ready msg to client");
-            loop {
-                // make sure that if our stupid lazy connection has been left
-                // behind that it is forgotten forever and ever and ever and
-                // ever and ever.
-                if this_conn_id != get_conn_id!(cid) { break; }
-
-                match client.read_message() {
-                    Ok(msg) => {
-                        match msg {
-                            Message::Close(_) => { break; }
-                            Message::Binary(x) => {
-                                info!("* ui -> core ({})", x.len());
-                                let msg_str = String::from_utf8(x).expect("sock::main() -- do you see what happens, larry? do you see what happens when you pass non-utf8 data, larry? this is what happens, larry.");
-                                cwrap::send(msg_str.as_str());
-                            }
-                            Message::Text(x) => {
-                                info!("* ui -> core ({})", x.len());
-                                cwrap::send(x.as_str());
-                            }
-                            _ => {}
-                        }
-                    }
-                    Err(_) => {
-                    }
-                }
-
-                let msg_turtl = cwrap::recv_nb("");
-                match msg_turtl {
-                    Some(x) => {
-                        info!("* core -> ui (ev: {})", x.len());
-                        //println!("---\n{}", x);
-                        client.write_message(Message::text(x)).expect("sock::main() -- failed to send message to stinkin' client");
-                    }
-                    None => {}
-                }
-
-                let msg_turtl = cwrap::recv_event_nb();
-                match msg_turtl {
-                    Some(x) => {
-                        info!("* core -> ui (res: {})", x.len());
-                        //println!("---\n{}", x);
-                        client.write_message(Message::text(x)).expect("sock::main() -- failed to send event to stinkin' client");
-                    }
-                    None => {}
-                }
-                sleep(10);
-
-            }
-        });
-    }
-
-    /*
-    for connection in server.filter_map(Result::ok) {
-        let cid = conn_id.clone();
-        let this_conn_id = inc_conn_id!(cid);
-        thread::spawn(move || {
-            info!("* new connection! {}", get_conn_id!(cid));
-            let mut client = connection.accept().expect("sock::main() -- failed to accept connection");
-            client.set_nonblocking(true).expect("sock::main() -- failed to set sock to nonblocking lol");
-            drain_channels();
-            cwrap::send(r#"["0","sync:shutdown",false]"#);
-            cwrap::send(r#"["0","user:logout",false]"#);
-            cwrap::recv("");
-            cwrap::recv("");
-            drain_channels();
-            client.send_message(&Message::text(r#"{"e":"messaging:ready","d":true}"#)).expect("sock::main() -- failed to send ready msg to client");
-            loop {
-                // make sure that if our stupid lazy connection has been left
-                // behind that it is forgotten forever and ever and ever and
-                // ever and ever.
-                if this_conn_id != get_conn_id!(cid) { break; }
-
-                let msg_res = client.recv_message();
-                match msg_res {
-                    Ok(msg) => {
-                        match msg {
-                            OwnedMessage::Close(_) => { break; }
-                            OwnedMessage::Binary(x) => {
-                                info!("* ui -> core ({})", x.len());
-                                let msg_str = String::from_utf8(x).expect("sock::main() -- do you see what happens, larry? do you see what happens when you pass non-utf8 data, larry? this is what happens, larry.");
-                                cwrap::send(msg_str.as_str());
-                            }
-                            OwnedMessage::Text(x) => {
-                                info!("* ui -> core ({})", x.len());
-                                cwrap::send(x.as_str());
-                            }
-                            _ => {}
-                        }
-                    }
-                    Err(_) => {
-                    }
-                }
-
-                let msg_turtl = cwrap::recv_nb("");
-                match msg_turtl {
-                    Some(x) => {
-                        info!("* core -> ui (ev: {})", x.len());
-                        //println!("---\n{}", x);
-                        client.send_message(&Message::text(x)).expect("sock::main() -- failed to send message to stinkin' client");
-                    }
-                    None => {}
-                }
-
-                let msg_turtl = cwrap::recv_event_nb();
-                match msg_turtl {
-                    Some(x) => {
-                        info!("* core -> ui (res: {})", x.len());
-                        //println!("---\n{}", x);
-                        client.send_message(&Message::text(x)).expect("sock::main() -- failed to send event to stinkin' client");
-                    }
-                    None => {}
-                }
-                sleep(10);
+            // only start core over fresh if nobody else is using it
+            if first_client {
+                reset_core(client_id, &rx);
             }
-            info!("* connection ended! {}", this_conn_id);
+            client.write_message(Message::text(r#"{"e":"messaging:ready","d":true}"#)).expect("sock::main() -- failed to send ready msg to client");
+            router::pump_client(&router, client_id, &rx, &mut client, |msg| cwrap::send(msg));
+            info!("* connection ended! {}", client_id);
         });
     }
-    */
     handle.join().expect("sock::main() -- failed to join thread");
 }
-
@@ -0,0 +1,253 @@
+//! The router lets any number of websocket clients share the one core we have
+//! running. Each client gets an id, and every request a client sends has its
+//! message id prefixed with `<client id>:` before being handed to core. When
+//! core responds, we look at the response's id to figure out which client it
+//! belongs to, strip our prefix back off, and hand it to that client. Events
+//! aren't tied to any request, so every client gets a copy.
+//!
+//! NOTE: we don't parse any JSON here. Requests are always arrays that start
+//! with the message id (`["<mid>", "<cmd>", ...]`) and responses always start
+//! with their id (`{"id":"<mid>",...}`) since we run core with
+//! `reqres_append_mid` off, so some careful string surgery does the trick.
+
+use ::std::collections::HashMap;
+use ::std::thread;
+use ::std::time::Duration;
+use ::std::net::TcpStream;
+use ::std::sync::{Arc, Mutex};
+use ::std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
+use ::tungstenite::{Message, WebSocket};
+
+/// What a core response starts with (when it has an id)
+const RESPONSE_PREFIX: &'static str = r#"{"id":""#;
+
+/// Keeps track of our connected clients and how to reach them.
+pub struct Router {
+    next_id: u32,
+    clients: HashMap<u32, Sender<String>>,
+}
+
+impl Router {
+    /// Make a router with no clients
+    pub fn new() -> Router {
+        Router {
+            next_id: 0,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Add a new client, returning its id and the receiving end of the channel
+    /// its messages from core will be delivered on.
+    pub fn register(&mut self) -> (u32, Receiver<String>) {
+        self.next_id += 1;
+        let (tx, rx) = mpsc::channel();
+        self.clients.insert(self.next_id, tx);
+        (self.next_id, rx)
+    }
+
+    /// Forget a client ever existed
+    pub fn unregister(&mut self, client_id: u32) {
+        self.clients.remove(&client_id);
+    }
+
+    /// How many clients are currently connected
+    pub fn num_clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Namespace a request's message id with the id of the client sending it.
+    /// If the message doesn't look like a request, it's passed through as-is
+    /// and core can tell the client what it thinks about that.
+    pub fn tag_request(client_id: u32, msg: &str) -> String {
+        let trimmed = msg.trim_start();
+        if !trimmed.starts_with("[") {
+            return String::from(msg);
+        }
+        let body = trimmed[1..].trim_start();
+        if !body.starts_with("\"") {
+            return String::from(msg);
+        }
+        format!("[\"{}:{}", client_id, &body[1..])
+    }
+
+    /// Given a response from core, find which client it belongs to and strip
+    /// our tag off of it. Returns None if the response isn't tagged.
+    pub fn untag_response(msg: &str) -> Option<(u32, String)> {
+        if !msg.starts_with(RESPONSE_PREFIX) {
+            return None;
+        }
+        let rest = &msg[RESPONSE_PREFIX.len()..];
+        let sep = rest.find(':')?;
+        let client_id = match rest[0..sep].parse::<u32>() {
+            Ok(x) => x,
+            Err(_) => return None,
+        };
+        Some((client_id, format!("{}{}", RESPONSE_PREFIX, &rest[(sep + 1)..])))
+    }
+
+    /// Send a core response to the client that asked for it. Responses we
+    /// can't place (or whose client has left) are dropped.
+    pub fn route_response(&self, msg: &str) {
+        let (client_id, msg) = match Router::untag_response(msg) {
+            Some(x) => x,
+            None => {
+                warn!("router::route_response() -- dropping untagged response");
+                return;
+            }
+        };
+        match self.clients.get(&client_id) {
+            Some(tx) => {
+                tx.send(msg).unwrap_or_else(|_| warn!("router::route_response() -- client {} hung up", client_id));
+            }
+            None => {
+                debug!("router::route_response() -- client {} is gone, dropping response", client_id);
+            }
+        }
+    }
+
+    /// Send a core event to every client.
+    pub fn broadcast_event(&self, msg: &str) {
+        for (client_id, tx) in &self.clients {
+            tx.send(String::from(msg)).unwrap_or_else(|_| warn!("router::broadcast_event() -- client {} hung up", client_id));
+        }
+    }
+}
+
+/// Shuttle messages between a (non-blocking) websocket client and core until
+/// the client goes away: requests are tagged and handed to `to_core`, and
+/// anything the router queued up for this client gets written back out. The
+/// client is unregistered once we're done.
+pub fn pump_client<F>(router: &Arc<Mutex<Router>>, client_id: u32, rx: &Receiver<String>, client: &mut WebSocket<TcpStream>, to_core: F)
+    where F: Fn(&str)
+{
+    'conn: loop {
+        match client.read_message() {
+            Ok(msg) => {
+                match msg {
+                    Message::Close(_) => { break; }
+                    Message::Binary(x) => {
+                        info!("* ui -> core ({}/{})", client_id, x.len());
+                        let msg_str = String::from_utf8(x).expect("router::pump_client() -- do you see what happens, larry? do you see what happens when you pass non-utf8 data, larry? this is what happens, larry.");
+                        to_core(Router::tag_request(client_id, msg_str.as_str()).as_str());
+                    }
+                    Message::Text(x) => {
+                        info!("* ui -> core ({}/{})", client_id, x.len());
+                        to_core(Router::tag_request(client_id, x.as_str()).as_str());
+                    }
+                    _ => {}
+                }
+            }
+            Err(_) => {
+            }
+        }
+
+        loop {
+            match rx.try_recv() {
+                Ok(x) => {
+                    if client.write_message(Message::text(x)).is_err() {
+                        warn!("* failed to send message to stinkin' client {}", client_id);
+                        break 'conn;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break 'conn,
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    router.lock().expect("router::pump_client() -- failed to grab router lock").unregister(client_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::net::TcpListener;
+
+    #[test]
+    fn tags_and_untags() {
+        assert_eq!(Router::tag_request(3, r#"["12","user:login","a","b"]"#), r#"["3:12","user:login","a","b"]"#);
+        assert_eq!(Router::tag_request(3, r#" [ "12","ping"]"#), r#"["3:12","ping"]"#);
+        assert_eq!(Router::tag_request(3, r#"{"lol":"no"}"#), r#"{"lol":"no"}"#);
+
+        assert_eq!(Router::untag_response(r#"{"id":"3:12","e":0,"d":"pong"}"#), Some((3, String::from(r#"{"id":"12","e":0,"d":"pong"}"#))));
+        assert_eq!(Router::untag_response(r#"{"id":"12","e":0,"d":"pong"}"#), None);
+        assert_eq!(Router::untag_response(r#"{"e":"profile:loaded","d":null}"#), None);
+    }
+
+    #[test]
+    fn routes_to_multiple_clients() {
+        let mut router = Router::new();
+        let (id1, rx1) = router.register();
+        let (id2, rx2) = router.register();
+        assert_eq!(router.num_clients(), 2);
+
+        // pretend to be core: answer each tagged request with its own id
+        let req1 = Router::tag_request(id1, r#"["1","ping"]"#);
+        let req2 = Router::tag_request(id2, r#"["1","ping"]"#);
+        for req in vec![req2, req1] {
+            let mid = &req[2..req.find("\",").unwrap()];
+            router.route_response(&format!(r#"{{"id":"{}","e":0,"d":"pong"}}"#, mid));
+        }
+        router.broadcast_event(r#"{"e":"sync:connected","d":true}"#);
+
+        assert_eq!(rx1.try_recv().unwrap(), r#"{"id":"1","e":0,"d":"pong"}"#);
+        assert_eq!(rx1.try_recv().unwrap(), r#"{"e":"sync:connected","d":true}"#);
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(rx2.try_recv().unwrap(), r#"{"id":"1","e":0,"d":"pong"}"#);
+        assert_eq!(rx2.try_recv().unwrap(), r#"{"e":"sync:connected","d":true}"#);
+        assert!(rx2.try_recv().is_err());
+
+        // once a client leaves, the other keeps going on its own
+        router.unregister(id1);
+        router.route_response(&format!(r#"{{"id":"{}:2","e":0,"d":"pong"}}"#, id1));
+        router.route_response(&format!(r#"{{"id":"{}:2","e":0,"d":"pong"}}"#, id2));
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(rx2.try_recv().unwrap(), r#"{"id":"2","e":0,"d":"pong"}"#);
+        assert_eq!(router.num_clients(), 1);
+    }
+
+    #[test]
+    fn routes_between_websocket_clients() {
+        let router = Arc::new(Mutex::new(Router::new()));
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // pretend to be core: answer every request with a pong under its id
+        let (core_tx, core_rx) = mpsc::channel::<String>();
+        let core_router = router.clone();
+        thread::spawn(move || {
+            for req in core_rx {
+                let mid = String::from(&req[2..req.find("\",").unwrap()]);
+                core_router.lock().unwrap().route_response(&format!(r#"{{"id":"{}","e":0,"d":"pong"}}"#, mid));
+            }
+        });
+        let server_router = router.clone();
+        thread::spawn(move || {
+            for stream in server.incoming().take(2) {
+                let router = server_router.clone();
+                let core_tx = core_tx.clone();
+                thread::spawn(move || {
+                    let (client_id, rx) = router.lock().unwrap().register();
+                    let mut client = ::tungstenite::server::accept(stream.unwrap()).unwrap();
+                    client.get_ref().set_nonblocking(true).unwrap();
+                    pump_client(&router, client_id, &rx, &mut client, |msg| core_tx.send(String::from(msg)).unwrap());
+                });
+            }
+        });
+
+        let url = format!("ws://{}/", addr);
+        let (mut client1, _) = ::tungstenite::client::client(url.as_str(), TcpStream::connect(addr).unwrap()).unwrap();
+        let (mut client2, _) = ::tungstenite::client::client(url.as_str(), TcpStream::connect(addr).unwrap()).unwrap();
+        client1.write_message(Message::text(r#"["a","ping"]"#)).unwrap();
+        client2.write_message(Message::text(r#"["b","ping"]"#)).unwrap();
+        assert_eq!(client1.read_message().unwrap(), Message::text(r#"{"id":"a","e":0,"d":"pong"}"#));
+        assert_eq!(client2.read_message().unwrap(), Message::text(r#"{"id":"b","e":0,"d":"pong"}"#));
+
+        // both responses have been routed by now, so if either one had gone
+        // to the wrong client it'd show up ahead of the event
+        router.lock().unwrap().broadcast_event(r#"{"e":"sync:connected","d":true}"#);
+        assert_eq!(client1.read_message().unwrap(), Message::text(r#"{"e":"sync:connected","d":true}"#));
+        assert_eq!(client2.read_message().unwrap(), Message::text(r#"{"e":"sync:connected","d":true}"#));
+        assert_eq!(router.lock().unwrap().num_clients(), 2);
+    }
+}
@@ -3,24 +3,91 @@
 
 use ::std::sync::{RwLock, Mutex};
 use ::std::io::Read;
-use ::std::time::Duration;
+use ::std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use ::std::collections::HashMap;
 use ::config;
 use ::jedi::{self, Value, DeserializeOwned, Serialize};
 use ::error::{TResult, TError};
 use ::crypto;
 use ::reqwest::{self, blocking::RequestBuilder, blocking::Client, Url, Proxy};
+use ::reqwest::header::{HeaderMap, RETRY_AFTER};
 pub use ::reqwest::Method;
 pub use ::reqwest::StatusCode;
 
 /// Pull out our crate version to send to the api
 const CORE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// How long to back off after a 429 that doesn't tell us how long to wait
+const DEFAULT_RATE_LIMIT_DELAY: u64 = 30;
+
+/// How long (in seconds) to give the server to answer a ping
+const PING_TIMEOUT: u64 = 5;
+
 lazy_static! {
     /// A hash table that holds HTTP clients. we used to just create/destroy
     /// clients on each request, but that exhausts connections so it's better to
     /// cache the clients and let them use their internal connection pool.
     static ref CLIENTS: Mutex<HashMap<String, Client>> = Mutex::new(HashMap::new());
+
+    /// If the server has asked us to slow down, this holds when it's ok to
+    /// start bugging it again.
+    static ref BACKOFF_UNTIL: RwLock<Option<Instant>> = RwLock::new(None);
+}
+
+/// Look at a response's status/headers and figure out how long the server
+/// wants us to wait before calling it again (if at all). We honor, in order:
+///
+/// - `Retry-After` (in seconds)
+/// - `X-RateLimit-Reset` once `X-RateLimit-Remaining` hits 0 (either a unix
+///   timestamp or seconds from now, depending on the server)
+/// - a default delay for a bare `429`
+pub fn parse_rate_limit(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    let header_num = |name: &str| -> Option<u64> {
+        headers.get(name)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.trim().parse::<u64>().ok())
+    };
+    if let Some(secs) = header_num(RETRY_AFTER.as_str()) {
+        return Some(Duration::from_secs(secs));
+    }
+    if header_num("x-ratelimit-remaining") == Some(0) {
+        if let Some(reset) = header_num("x-ratelimit-reset") {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or(0);
+            // anything bigger than a year is a timestamp, not a delay
+            let secs = if reset > 31536000 { reset.saturating_sub(now) } else { reset };
+            return Some(Duration::from_secs(secs));
+        }
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Some(Duration::from_secs(DEFAULT_RATE_LIMIT_DELAY));
+    }
+    None
+}
+
+/// Returns how much longer the server wants us to leave it alone, if it's
+/// rate limiting us. The sync system uses this to stretch its delay between
+/// runs instead of hammering the api with requests that will just get a 429.
+pub fn rate_limit_delay() -> Option<Duration> {
+    let until = (*lockr!((*BACKOFF_UNTIL))).clone()?;
+    let now = Instant::now();
+    if until > now { Some(until - now) } else { None }
+}
+
+/// Record a rate limit delay the server gave us
+fn set_rate_limit(delay: Duration) {
+    warn!("api::set_rate_limit() -- server is rate limiting us, backing off for {}s", delay.as_secs());
+    let mut guard = lockw!((*BACKOFF_UNTIL));
+    *guard = Some(Instant::now() + delay);
+}
+
+/// Returns whether or not we're running in offline mode (`api.offline`), in
+/// which case we don't talk to the outside world at all.
+pub fn is_offline() -> bool {
+    config::get::<Option<bool>>(&["api", "offline"])
+        .unwrap_or(None)
+        .unwrap_or(false)
 }
 
 /// Holds our Api configuration. This consists of any mutable fields the Api
@@ -149,6 +216,9 @@ impl ApiCaller {
         res
             .map_err(|e| { toterr!(e) })
             .and_then(|mut res| {
+                if let Some(delay) = parse_rate_limit(res.status(), res.headers()) {
+                    set_rate_limit(delay);
+                }
                 let mut out = String::new();
                 let str_res = res.read_to_string(&mut out)
                     .map_err(|e| toterr!(e))
@@ -269,6 +339,10 @@ impl Api {
     /// Given a method an url, return a Reqwest RequestBuilder
     pub fn req(&self, method: Method, resource: &str) -> TResult<ApiCaller> {
         debug!("api::req() -- begin: {} {}", method, resource);
+        if is_offline() {
+            debug!("api::req() -- offline, refusing to call {} {}", method, resource);
+            return TErr!(TError::Offline);
+        }
         let url = self.build_url(resource)?;
         let req = Client::builder().build()?.request(method, Url::parse(url.as_str())?);
         trace!("api::req() -- made client, got req: {:?}", req);
@@ -294,5 +368,62 @@ impl Api {
     pub fn delete(&self, resource: &str) -> TResult<ApiCaller> {
         self.req(Method::DELETE, resource)
     }
+
+    /// Check whether our server is reachable, and if so, which version it's
+    /// running and how long it took to answer. Problems reaching the server
+    /// are reported in the result rather than returned as errors.
+    pub fn ping(&self) -> TResult<Value> {
+        let start = Instant::now();
+        let res: TResult<Value> = self.get("/")?.call_opt(ApiReq::new().timeout(PING_TIMEOUT));
+        let elapsed = start.elapsed();
+        let latency = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1000000) as u64;
+        match res {
+            Ok(x) => Ok(json!({
+                "reachable": true,
+                "version": jedi::get_opt::<String>(&["version"], &x),
+                "latency": latency,
+            })),
+            Err(e) => match e.shed() {
+                // the server answered, just not happily
+                TError::Api(..) => Ok(json!({
+                    "reachable": true,
+                    "version": Value::Null,
+                    "latency": latency,
+                })),
+                e => Ok(json!({
+                    "reachable": false,
+                    "version": Value::Null,
+                    "error": e.to_response(false),
+                })),
+            },
+        }
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::reqwest::header::HeaderValue;
+
+    #[test]
+    fn parses_rate_limits() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_rate_limit(StatusCode::OK, &headers), None);
+        assert_eq!(parse_rate_limit(StatusCode::TOO_MANY_REQUESTS, &headers), Some(Duration::from_secs(DEFAULT_RATE_LIMIT_DELAY)));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(parse_rate_limit(StatusCode::TOO_MANY_REQUESTS, &headers), Some(Duration::from_secs(120)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("3"));
+        headers.insert("X-RateLimit-Reset", HeaderValue::from_static("60"));
+        assert_eq!(parse_rate_limit(StatusCode::OK, &headers), None);
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        assert_eq!(parse_rate_limit(StatusCode::OK, &headers), Some(Duration::from_secs(60)));
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 90;
+        headers.insert("X-RateLimit-Reset", HeaderValue::from_str(&format!("{}", reset)).unwrap());
+        let delay = parse_rate_limit(StatusCode::TOO_MANY_REQUESTS, &headers).unwrap();
+        assert!(delay.as_secs() >= 88 && delay.as_secs() <= 90);
+    }
+}
@@ -28,6 +28,10 @@ quick_error! {
             description("not implemented")
             display("crypto: not implemented: {}", str)
         }
+        NonceReuse(str: String) {
+            description("nonce reuse")
+            display("crypto: nonce reuse: {}", str)
+        }
     }
 }
 
@@ -42,6 +46,7 @@ macro_rules! make_boxed_err {
 }
 make_boxed_err!(::hex::FromHexError);
 make_boxed_err!(::base64::DecodeError);
+make_boxed_err!(::std::io::Error);
 
 pub type CResult<T> = Result<T, CryptoError>;
 
@@ -24,6 +24,18 @@ impl Key {
         Ok(Key::new(::crypto::low::chacha20poly1305::random_key()?))
     }
 
+    /// Deterministically derive a key from a seed. Meant for tests/benches that
+    /// need reproducible keys without hardcoding base64 blobs everywhere.
+    ///
+    /// NOT for production use: the key is just a hash of the seed, so anyone who
+    /// knows (or guesses) the seed has the key.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn from_seed(seed: &[u8]) -> Key {
+        let data = ::crypto::low::sha256(seed)
+            .expect("Key::from_seed() -- sha256 failed");
+        Key::new(data)
+    }
+
     /// Return a ref to this key's data
     pub fn data<'a>(&'a self) -> &'a Vec<u8> {
         &self.data
@@ -31,8 +43,8 @@ impl Key {
 
     /// Consume this Key and convert it into its underlying data
     #[allow(dead_code)]
-    pub fn into_data(self) -> Vec<u8> {
-        self.data
+    pub fn into_data(mut self) -> Vec<u8> {
+        ::std::mem::replace(&mut self.data, Vec::new())
     }
 
     /// Return this key's data length
@@ -48,6 +60,18 @@ impl Clone for Key {
     }
 }
 
+/// Zero out our key data when we're done with it so it doesn't linger in
+/// memory after being freed.
+impl Drop for Key {
+    fn drop(&mut self) {
+        for byte in self.data.iter_mut() {
+            // volatile so the compiler doesn't optimize away a write to memory
+            // that's about to be freed
+            unsafe { ::std::ptr::write_volatile(byte, 0); }
+        }
+    }
+}
+
 impl PartialEq for Key {
     fn eq(&self, other: &Key) -> bool {
         self.data() == other.data()
@@ -96,5 +120,15 @@ mod tests {
         let ser_key = jedi::stringify(&key).unwrap();
         assert_eq!(ser_key, String::from(r#""XExP/+h80Fm06fEqKsKoE5GwaDRY88pObH+y6YCTWzQ=""#));
     }
+
+    #[test]
+    fn seeded_keys_are_deterministic() {
+        let key1 = Key::from_seed(b"get a job");
+        let key2 = Key::from_seed(b"get a job");
+        let key3 = Key::from_seed(b"sit on a couch");
+        assert_eq!(key1, key2);
+        assert!(key1 != key3);
+        assert_eq!(key1.len(), ::crypto::keylen());
+    }
 }
 
@@ -1,5 +1,6 @@
 //! Low-level crypto primitives/modules.
 
+use ::std::io::{Read, ErrorKind};
 use ::hex;
 use ::base64;
 use ::sodiumoxide;
@@ -11,6 +12,8 @@ use ::crypto::error::{CResult, CryptoError};
 /// Abstract the size of hmac keys
 #[allow(dead_code)]
 pub const HMAC_KEYLEN: usize = sodium_auth::KEYBYTES;
+/// How much data `hmac_stream()` reads at a time
+const HMAC_STREAM_CHUNK: usize = 64 * 1024;
 /// Abstract the size of salts in our KDF
 pub const KEYGEN_SALT_LEN: usize = 32;
 /// Abstract the ops limit for key generation (524288)
@@ -62,6 +65,57 @@ pub fn hmac(key: &[u8], data: &[u8]) -> CResult<Vec<u8>> {
     Ok(tag.0.to_vec())
 }
 
+/// Like `hmac()`, but reads the data from a reader in chunks so we can MAC big
+/// things (files, say) without having them in memory all at once. Gives the
+/// same result as `hmac()` over the same data.
+pub fn hmac_stream<R: Read>(key: &[u8], reader: &mut R) -> CResult<Vec<u8>> {
+    if key.len() != HMAC_KEYLEN {
+        return Err(CryptoError::BadData(format!("crypto::low::hmac_stream() -- invalid hmac key supplied")));
+    }
+    let mut state = sodium_auth::State::init(key);
+    let mut chunk = vec![0; HMAC_STREAM_CHUNK];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(x) => x,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(From::from(e)),
+        };
+        state.update(&chunk[0..read]);
+    }
+    Ok(state.finalize().0.to_vec())
+}
+
+/// HKDF (RFC 5869) built on top of our HMAC (HMAC-SHA512256). Derives `len`
+/// bytes of key material from `ikm`, with `info` binding the output to a
+/// specific purpose. If `salt` is empty, we use a zeroed salt like the RFC
+/// says to, otherwise it must be HMAC_KEYLEN bytes.
+pub fn hkdf(ikm: &[u8], salt: &[u8], info: &[u8], len: usize) -> CResult<Vec<u8>> {
+    let hashlen = sodium_auth::TAGBYTES;
+    if len == 0 || len > 255 * hashlen {
+        return Err(CryptoError::BadData(format!("crypto::low::hkdf() -- invalid output length: {}", len)));
+    }
+    let zero_salt = vec![0; HMAC_KEYLEN];
+    let salt = if salt.len() == 0 { &zero_salt[..] } else { salt };
+    // extract
+    let prk = hmac(salt, ikm)?;
+    // expand
+    let mut okm: Vec<u8> = Vec::with_capacity(len + hashlen);
+    let mut block: Vec<u8> = Vec::new();
+    let mut counter: u8 = 1;
+    while okm.len() < len {
+        let mut input: Vec<u8> = Vec::with_capacity(block.len() + info.len() + 1);
+        input.extend_from_slice(&block);
+        input.extend_from_slice(info);
+        input.push(counter);
+        block = hmac(&prk, &input)?;
+        okm.extend_from_slice(&block);
+        counter = counter.wrapping_add(1);
+    }
+    okm.truncate(len);
+    Ok(okm)
+}
+
 /// Do a secure comparison of two byte arrays.
 ///
 /// We do this using the double-hmac method, as opposed to fighting tooth and
@@ -70,7 +124,6 @@ pub fn hmac(key: &[u8], data: &[u8]) -> CResult<Vec<u8>> {
 ///
 /// This takes a bit more legwork, but is able to securely compare two values
 /// without leaking information about either.
-#[allow(dead_code)]
 pub fn secure_compare(arr1: &[u8], arr2: &[u8]) -> CResult<bool> {
     let key = sodium_auth::gen_key().0.to_vec();
     let hash1 = hmac(key.as_slice(), arr1)?;
@@ -321,6 +374,21 @@ mod tests {
         assert_eq!(res, "9308b40116068920c7cea98aa5bbc340cfabdaa27316413804050cfc6a7b4873");
     }
 
+    #[test]
+    fn can_hmac_stream() {
+        let key = sha512(get_string("kill your workforce").as_bytes()).unwrap();
+        let key = &key[0..HMAC_KEYLEN];
+        // big enough to span a bunch of chunks, with a partial one at the end
+        let data = (0..(HMAC_STREAM_CHUNK * 5 + 1234)).map(|x| (x % 251) as u8).collect::<Vec<_>>();
+        let streamed = hmac_stream(key, &mut ::std::io::Cursor::new(&data)).unwrap();
+        assert_eq!(streamed, hmac(key, &data).unwrap());
+        // the chunk size the reader hands back doesn't matter
+        let streamed = hmac_stream(key, &mut ::std::io::BufReader::with_capacity(1000, &data[..])).unwrap();
+        assert_eq!(streamed, hmac(key, &data).unwrap());
+        assert_eq!(hmac_stream(key, &mut ::std::io::Cursor::new(Vec::new())).unwrap(), hmac(key, &[]).unwrap());
+        assert!(hmac_stream(&key[0..10], &mut ::std::io::Cursor::new(&data)).is_err());
+    }
+
     #[test]
     fn random_bytes_works() {
         let bytes = rand_bytes(4).unwrap();
@@ -10,6 +10,7 @@ pub use ::crypto::error::{
     CryptoError,
 };
 pub use ::crypto::low::{
+    hmac_stream,
     sha256,
     sha512,
     to_hex,
@@ -21,10 +22,18 @@ pub use ::crypto::low::{
     KEYGEN_OPS_DEFAULT,
     KEYGEN_MEM_DEFAULT,
     random_salt,
+    secure_compare,
 };
 pub use ::crypto::low::chacha20poly1305::{random_nonce, random_key, noncelen, keylen};
 pub use ::crypto::key::Key;
 
+use ::std::fs::File;
+use ::std::path::Path;
+#[cfg(feature = "nonce-tracking")]
+use ::std::collections::HashMap;
+#[cfg(feature = "nonce-tracking")]
+use ::std::sync::Mutex;
+
 /// Stores our current crypto version. This gets encoded into a header in the
 /// ciphertext and lets the crypto module know how to handle the message.
 const CRYPTO_VERSION: u16 = 6;
@@ -32,6 +41,13 @@ const CRYPTO_VERSION: u16 = 6;
 /// Stores the available algorithms for symmetric crypto.
 const SYM_ALGORITHM: [&'static str; 1] = ["chacha20poly1305"];
 
+#[cfg(feature = "nonce-tracking")]
+lazy_static! {
+    /// Maps each (key fingerprint, nonce) pair we've encrypted with to a hash
+    /// of the plaintext that was encrypted under it (see `track_nonce()`)
+    static ref NONCES_USED: Mutex<HashMap<(Vec<u8>, Vec<u8>), Vec<u8>>> = Mutex::new(HashMap::new());
+}
+
 /// Find the position of a static string in an array of static strings
 fn find_index(arr: &[&'static str], val: &str) -> CResult<usize> {
     for i in 0..arr.len() {
@@ -53,12 +69,33 @@ impl CryptoOp {
         Ok(CryptoOp { algorithm: algorithm, nonce: None })
     }
 
-    /// Create a new crypto op with a algorithm/nonce
+    /// Create a new crypto op with a algorithm/nonce. The nonce must be the
+    /// right length for the algorithm (see `noncelen()`).
     pub fn new_with_nonce(algorithm: &'static str, nonce: Vec<u8>) -> CResult<CryptoOp> {
         let mut op = CryptoOp::new(algorithm)?;
+        let expected = op.noncelen();
+        if nonce.len() != expected {
+            return Err(CryptoError::BadData(format!("CryptoOp::new_with_nonce() -- bad nonce length for {}: expected {} bytes, got {}", algorithm, expected, nonce.len())));
+        }
         op.nonce = Some(nonce);
         Ok(op)
     }
+
+    /// Get the nonce length this op's algorithm expects
+    pub fn noncelen(&self) -> usize {
+        match self.algorithm {
+            "chacha20poly1305" => noncelen(),
+            _ => unreachable!("CryptoOp.noncelen() -- unknown algorithm {}", self.algorithm),
+        }
+    }
+
+    /// Get the key length this op's algorithm expects
+    pub fn keylen(&self) -> usize {
+        match self.algorithm {
+            "chacha20poly1305" => keylen(),
+            _ => unreachable!("CryptoOp.keylen() -- unknown algorithm {}", self.algorithm),
+        }
+    }
 }
 
 /// Describes some meta about our payload. This includes the version
@@ -210,11 +247,19 @@ pub fn serialize(data: &mut CryptoData) -> CResult<Vec<u8>> {
 /// contain *all* the data needed to decrypt the message encoded in a header
 /// (see deserialize() for more info).
 pub fn decrypt(key: &Key, ciphertext: Vec<u8>) -> CResult<Vec<u8>> {
+    decrypt_with_aad(key, ciphertext, &[])
+}
+
+/// Like `decrypt()`, but for messages encrypted with `encrypt_with_aad()`. The
+/// associated data has to match what the message was encrypted with, or the
+/// message fails to authenticate.
+pub fn decrypt_with_aad(key: &Key, ciphertext: Vec<u8>, aad: &[u8]) -> CResult<Vec<u8>> {
     let deserialized = deserialize(ciphertext)?;
     let desc = &deserialized.desc;
     let nonce = &deserialized.nonce;
     let ciphertext = &deserialized.ciphertext;
-    let auth: Vec<u8> = serialize_header(&deserialized)?;
+    let mut auth: Vec<u8> = serialize_header(&deserialized)?;
+    auth.extend_from_slice(aad);
     let decrypted = match SYM_ALGORITHM[desc.algorithm as usize] {
         "chacha20poly1305" => {
             low::chacha20poly1305::decrypt(key.data().as_slice(), nonce.as_slice(), auth.as_slice(), ciphertext.as_slice())?
@@ -235,16 +280,29 @@ pub fn decrypt(key: &Key, ciphertext: Vec<u8>) -> CResult<Vec<u8>> {
 /// more secure or correct than earlier versions, so we just don't allow going
 /// back in time (although decrypt() supports all previous versions).
 pub fn encrypt(key: &Key, plaintext: Vec<u8>, op: CryptoOp) -> CResult<Vec<u8>> {
+    encrypt_with_aad(key, plaintext, op, &[])
+}
+
+/// Like `encrypt()`, but also authenticates the given associated data (along
+/// with the header), binding the ciphertext to some outside context (like the
+/// id of the model it belongs to). The associated data isn't stored in the
+/// ciphertext, so the same data has to be handed to `decrypt_with_aad()`.
+pub fn encrypt_with_aad(key: &Key, plaintext: Vec<u8>, op: CryptoOp, aad: &[u8]) -> CResult<Vec<u8>> {
     let version = CRYPTO_VERSION;
     match op.algorithm {
         "chacha20poly1305" => {
             let nonce = match op.nonce {
-                Some(x) => x,
+                Some(x) => {
+                    #[cfg(feature = "nonce-tracking")]
+                    track_nonce(key, x.as_slice(), plaintext.as_slice(), aad)?;
+                    x
+                }
                 None => low::chacha20poly1305::random_nonce()?,
             };
             let desc = PayloadDescription::new(version, op.algorithm)?;
             let mut data = CryptoData::new(version, desc, nonce, Vec::new());
-            let auth = serialize_header(&data)?;
+            let mut auth = serialize_header(&data)?;
+            auth.extend_from_slice(aad);
             data.ciphertext = low::chacha20poly1305::encrypt(key.data().as_slice(), data.nonce.as_slice(), auth.as_slice(), plaintext.as_slice())?;
             Ok(serialize(&mut data)?)
         }
@@ -254,11 +312,123 @@ pub fn encrypt(key: &Key, plaintext: Vec<u8>, op: CryptoOp) -> CResult<Vec<u8>>
     }
 }
 
+/// Remember that we encrypted `plaintext` (with `aad`) under the given
+/// key/nonce, erroring if that pair was already used to encrypt something else
+/// (which, for an AEAD, gives away the plaintexts and lets an attacker forge
+/// messages). Only keys/plaintexts' hashes are stored. Encrypting the *same*
+/// plaintext/aad again is fine: it comes out identical and leaks nothing, and
+/// our deterministic auth encryption relies on it.
+#[cfg(feature = "nonce-tracking")]
+fn track_nonce(key: &Key, nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> CResult<()> {
+    let pair = (sha256(key.data().as_slice())?, Vec::from(nonce));
+    let mut digest = sha256(plaintext)?;
+    digest.append(&mut sha256(aad)?);
+    let mut used = NONCES_USED.lock().unwrap_or_else(|e| e.into_inner());
+    match used.get(&pair) {
+        Some(prev) if prev != &digest => {
+            error!("crypto::track_nonce() -- a nonce was reused with the same key to encrypt different data");
+            Err(CryptoError::NonceReuse(String::from("this key/nonce pair was already used to encrypt different data")))
+        }
+        Some(_) => Ok(()),
+        None => {
+            used.insert(pair, digest);
+            Ok(())
+        }
+    }
+}
+
 /// Generate a key given a password and a salt
 pub fn gen_key(password: &[u8], salt: &[u8], cpu: usize, mem: usize) -> CResult<Key> {
     Ok(Key::new(low::gen_key(password, salt, cpu, mem)?))
 }
 
+/// Derive a purpose-specific subkey from a master key using HKDF, with the
+/// `context` as the HKDF info. The same master/context always gives the same
+/// key, and different contexts give keys that are independent of each other
+/// (and of the master), so, for instance, a search index key can't be used to
+/// decrypt storage.
+pub fn derive_subkey(master: &Key, context: &str, len: usize) -> CResult<Key> {
+    Ok(Key::new(low::hkdf(master.data().as_slice(), &[], context.as_bytes(), len)?))
+}
+
+/// Check that a file matches a MAC we stored for it earlier (via
+/// `hmac_stream()` with the same key). The file is read in chunks, so this
+/// works on big files without loading them into memory.
+pub fn verify_file_hmac(key: &Key, path: &Path, mac: &[u8]) -> CResult<bool> {
+    let mut file = File::open(path)?;
+    let file_mac = low::hmac_stream(key.data().as_slice(), &mut file)?;
+    low::secure_compare(file_mac.as_slice(), mac)
+}
+
+/// Passwords (or pieces of passwords) that are so common they add next to
+/// nothing to a password's strength.
+const COMMON_PASSWORDS: &'static [&'static str] = &[
+    "password", "passw0rd", "p@ssword", "123456", "12345678", "qwerty",
+    "asdfgh", "zxcvbn", "abc123", "letmein", "monkey", "dragon", "111111",
+    "iloveyou", "admin", "welcome", "login", "princess", "sunshine", "football",
+    "baseball", "master", "shadow", "trustno1", "starwars", "whatever", "turtl",
+];
+
+/// Give a rough estimate of how strong a password is, from 0 (terrible) to 4
+/// (great). This looks at the length, which character classes are used, and
+/// knocks points off for repeated/sequential characters (`aaaa`, `1234`) and
+/// common passwords. It's meant to warn users away from bad passwords, not to
+/// be a precise measure of entropy.
+pub fn estimate_password_strength(password: &str) -> u8 {
+    let lower = password.to_lowercase();
+    let num_chars = password.chars().count();
+    if num_chars == 0 || COMMON_PASSWORDS.contains(&lower.as_str()) {
+        return 0;
+    }
+
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) { pool += 26; }
+    if password.chars().any(|c| c.is_ascii_uppercase()) { pool += 26; }
+    if password.chars().any(|c| c.is_ascii_digit()) { pool += 10; }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') { pool += 33; }
+    if password.chars().any(|c| !c.is_ascii()) { pool += 100; }
+
+    // characters that repeat or continue a sequence from the previous one
+    // don't count for much, so we skip them entirely
+    let mut effective_len: i64 = 0;
+    let mut prev: Option<char> = None;
+    for c in lower.chars() {
+        let continues = match prev {
+            Some(p) => (c as i64 - p as i64).abs() <= 1,
+            None => false,
+        };
+        if !continues { effective_len += 1; }
+        prev = Some(c);
+    }
+    // a common password inside a longer one counts as a single character
+    for common in COMMON_PASSWORDS {
+        if lower.contains(common) {
+            effective_len -= common.chars().count() as i64 - 1;
+        }
+    }
+    let effective_len = ::std::cmp::max(effective_len, 0) as f64;
+    let bits = effective_len * (pool as f64).log2();
+    let score = if bits < 25.0 {
+        0
+    } else if bits < 40.0 {
+        1
+    } else if bits < 60.0 {
+        2
+    } else if bits < 80.0 {
+        3
+    } else {
+        4
+    };
+    // short passwords are weak no matter what's in them
+    if num_chars < 8 { ::std::cmp::min(score, 1) } else { score }
+}
+
+/// Hash some content into a hex string (sha256). Good for telling whether two
+/// pieces of content are the same without holding onto either of them.
+pub fn content_hash(data: &[u8]) -> CResult<String> {
+    low::to_hex(&low::sha256(data)?)
+}
+
 /// Generate a random hex string (64 bytes).
 pub fn random_hash() -> CResult<String> {
     low::to_hex(&low::rand_bytes(32)?)
@@ -310,6 +480,66 @@ pub mod asym {
     }
 }
 
+/// Known-answer vectors for `self_test()`
+const KAT_SYM_KEY: &'static str = "2gtrzmvEQkfK9Lq+0eGqLjDrmlKBabp7T212Zdv35T0=";
+const KAT_SYM_NONCE_SEED: &'static str = "omg wtff";
+const KAT_SYM_PLAINTEXT: &'static str = r#"{"title":"libertarian quotes","body":"Moreover, the institution of child labor is an honorable one, with a long and glorious history of good works. And the villains of the piece are not the employers, but rather those who prohibit the free market in child labor. These do-gooders are responsible for the untold immiseration of those who are thus forced out of employment. Although the harm done was greater in the past, when great poverty made widespread child labor necessary, there are still people in dire straits today. Present prohibitions of child labor are thus an unconscionable interference with their lives.","tags":["moron"],"mod":1468007942,"created":1468007942.493,"keys":[]}"#;
+const KAT_SYM_CIPHERTEXT: &'static str = "AAYBAAzGNuOg4N1zkQ2BlAiBbjNiYibICOs1NW18Jh/QfvdS+fR70+5kMnNCjXUSND05fU3m/FrcFZKPd3yQAl5gsP+4hWqkbWd+6/ip6HISeEz0NPBNTCWedSVgKYiEdnORSoiunl4l61vBmsyzQGnQl8fCYuerTLeGpq6j6Y5fBVmqmjWbmc5zeKqmg+LTfFUq9iNg5HoUPVKfjVm1aYlFG/fjMSk25j5zIgecFHAJOlQqtHXXPPCxwYLBoHBPsZE3kMu8jzE1QO8SAPOPyp2o3pD8fX1OhvqRHL/W34dqQzasmrscgvdvAy69l6nwbByOsjwvNSm2jWiNWGqFqxLgLXLy00r8A3E3hBDtQur4uo6Vs9ZSYn4mfLjEAyhyUsZeaoti8pKK5FVcJA9a//Blztbdmd8SPysXxks/6RvHIjy+aRCVxs/8Bw2Mv+AiSZ59dohNN4OUoVy3hNXk0RfdCDakw5AVq7xocAwmMLZeoWUgUt+Nb8ntt5W8KpfZVGMuxqIQoJoRMG7kf6TEHpL4vBOmosV0MwtLWkXwyXsx+zkP3GRw9mIcCkm5wEWpELYYzrOLmVQs4QHMetWsmyfTFOFlzVFPl7ctKlKuUOfbKETmrafvCNmoeOAWn58CXeEsD06ejrlg9zuPf5Vc3eIMSJ+EKIy8/eMLLFIDEzYkutqOfZoG6LJgevbgivLV7oXnG4kBF5pGVvwnpED4fTUFCFnc+MWATCN9aIJ58aLIdmF7TLYQwwXwNyyo9MvTJn/sEVjsbX/kpYrtknW1pjJ44e11du2Q5GpJXA4630g7BOOxooYTQgumoo/P3pPJnLjt9TJWPw7Q2h5rb2tqJowhltN19upncbOwMl1HPJcCqtOZOmttskMiDZGAjytiGOuD15TnfDUoZu3b97x0O6Nzm3RxGGBg4kQjC0q0RW0700EGGeCaiq9XAfUFIsS5XQ==";
+const KAT_ASYM_PUBKEY: &'static str = "3KhS3n3QlT/w7rE8hwwq/HNnVxlgzkphsqYKRAzbNGg=";
+const KAT_ASYM_PRIVKEY: &'static str = "ZZN2wHM5T7tUugDGUpMbMB6lI/o5S9AVxjntFjdO+/0=";
+const KAT_ASYM_CIPHERTEXT: &'static str = "A3eNneAydRaXiMB0886wo3sTTAxHcyM7JpaLN4z2rqQRyxUPq/eKrWHyF2/1wC9gfmw5t7lQ6KhT+tSbYTAHQb2EJ3NvwGRyeQ5SXId7RYSAeaoizSyT8JfEI91hyRde3sC5C00xYn60LYjt";
+const KAT_ASYM_PLAINTEXT: &'static str = "and if you ever put your god damn hands on my wife again...";
+const KAT_KEYGEN_USERNAME: &'static str = "andrew@thillygooth.com";
+const KAT_KEYGEN_PASSWORD: &'static str = "this is definitely not the password i use for my bank account. no sir.";
+const KAT_KEYGEN_KEY: &'static str = "f36850e9bd90afc3413a89693bf71ebdf347f3727bad9b4487e249bb21ca28f1";
+
+/// Run our known-answer tests against the crypto we were built with, so a
+/// broken build (or a dependency that changed out from under us) gets caught
+/// before it touches anyone's data. Covers symmetric encryption/decryption,
+/// asym crypto, and key derivation, and returns an error naming whatever check
+/// failed.
+pub fn self_test() -> CResult<()> {
+    fn check(name: &str, passed: bool) -> CResult<()> {
+        if passed {
+            Ok(())
+        } else {
+            Err(CryptoError::OperationFailed(format!("crypto::self_test() -- {} check failed", name)))
+        }
+    }
+
+    // symmetric: decrypt a known payload, then re-encrypt it with the same
+    // nonce and make sure we get the exact same bytes back
+    let key = Key::new(from_base64(&String::from(KAT_SYM_KEY))?);
+    let payload = from_base64(&String::from(KAT_SYM_CIPHERTEXT))?;
+    let plain = decrypt(&key, payload.clone())?;
+    check("symmetric decrypt", plain.as_slice() == KAT_SYM_PLAINTEXT.as_bytes())?;
+    let nonce = Vec::from(&sha512(KAT_SYM_NONCE_SEED.as_bytes())?[0..noncelen()]);
+    let op = CryptoOp::new_with_nonce("chacha20poly1305", nonce)?;
+    let enc = encrypt(&key, Vec::from(KAT_SYM_PLAINTEXT.as_bytes()), op)?;
+    check("symmetric encrypt", enc == payload)?;
+    // a flipped bit has to fail authentication
+    let mut tampered = payload;
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    check("symmetric authentication", decrypt(&key, tampered).is_err())?;
+
+    // asym: open a known message, then do a round trip with a fresh keypair
+    let pk = Key::new(from_base64(&String::from(KAT_ASYM_PUBKEY))?);
+    let sk = Key::new(from_base64(&String::from(KAT_ASYM_PRIVKEY))?);
+    let msg = asym::decrypt(&pk, &sk, from_base64(&String::from(KAT_ASYM_CIPHERTEXT))?)?;
+    check("asymmetric decrypt", msg.as_slice() == KAT_ASYM_PLAINTEXT.as_bytes())?;
+    let (her_pk, her_sk) = asym::keygen()?;
+    let encrypted = asym::encrypt(&her_pk, Vec::from(KAT_ASYM_PLAINTEXT.as_bytes()))?;
+    let decrypted = asym::decrypt(&her_pk, &her_sk, encrypted)?;
+    check("asymmetric round trip", decrypted.as_slice() == KAT_ASYM_PLAINTEXT.as_bytes())?;
+
+    // key derivation
+    let salt = Vec::from(&sha512(KAT_KEYGEN_USERNAME.as_bytes())?[0..KEYGEN_SALT_LEN]);
+    let key = gen_key(KAT_KEYGEN_PASSWORD.as_bytes(), salt.as_slice(), KEYGEN_OPS_DEFAULT, KEYGEN_MEM_DEFAULT)?;
+    check("key derivation", to_hex(key.data())? == KAT_KEYGEN_KEY)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     //! Tests for our high-level Crypto module interface.
@@ -329,6 +559,50 @@ mod tests {
         assert_eq!(super::SYM_ALGORITHM[0], "chacha20poly1305");
     }
 
+    #[cfg(feature = "nonce-tracking")]
+    #[test]
+    fn catches_nonce_reuse() {
+        let key = Key::random().unwrap();
+        let nonce = random_nonce().unwrap();
+        let op = || CryptoOp::new_with_nonce("chacha20poly1305", nonce.clone()).unwrap();
+        let enc1 = encrypt(&key, Vec::from("get a job".as_bytes()), op()).unwrap();
+        // same data, same key/nonce: deterministic, so that's fine
+        let enc2 = encrypt(&key, Vec::from("get a job".as_bytes()), op()).unwrap();
+        assert_eq!(enc1, enc2);
+        // different data under the same key/nonce is not
+        match encrypt(&key, Vec::from("sit on a couch".as_bytes()), op()) {
+            Err(CryptoError::NonceReuse(_)) => {}
+            x => panic!("expected a NonceReuse error, got {:?}", x),
+        }
+        // a different key (or nonce) is its own pair
+        encrypt(&Key::random().unwrap(), Vec::from("sit on a couch".as_bytes()), op()).unwrap();
+        let op2 = CryptoOp::new_with_nonce("chacha20poly1305", random_nonce().unwrap()).unwrap();
+        encrypt(&key, Vec::from("sit on a couch".as_bytes()), op2).unwrap();
+        // random nonces aren't tracked at all
+        for _ in 0..3 {
+            encrypt(&key, Vec::from("sit on a couch".as_bytes()), CryptoOp::new("chacha20poly1305").unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn binds_associated_data() {
+        let key = Key::random().unwrap();
+        let plaintext = Vec::from(String::from("get a job").as_bytes());
+        let op = CryptoOp::new("chacha20poly1305").unwrap();
+        let enc = encrypt_with_aad(&key, plaintext.clone(), op, b"note:1234").unwrap();
+        assert_eq!(decrypt_with_aad(&key, enc.clone(), b"note:1234").unwrap(), plaintext);
+        // moving the ciphertext somewhere else (or dropping the aad) fails
+        assert!(decrypt_with_aad(&key, enc.clone(), b"note:5678").is_err());
+        assert!(decrypt(&key, enc.clone()).is_err());
+        assert!(decrypt_with_aad(&Key::random().unwrap(), enc, b"note:1234").is_err());
+
+        // no aad is the same as plain old encrypt/decrypt
+        let op = CryptoOp::new("chacha20poly1305").unwrap();
+        let enc = encrypt(&key, plaintext.clone(), op).unwrap();
+        assert_eq!(decrypt_with_aad(&key, enc.clone(), &[]).unwrap(), plaintext);
+        assert!(decrypt_with_aad(&key, enc, b"note:1234").is_err());
+    }
+
     #[test]
     fn can_gen_keys() {
         let username = String::from("andrew@thillygooth.com");
@@ -391,6 +665,21 @@ mod tests {
         assert_eq!(enc_str, "AAYBAAzGNuOg4N1zkQ2BlAiBbjNiYibICOs1NW18Jh/QfvdS+fR70+5kMnNCjXUSND05fU3m/FrcFZKPd3yQAl5gsP+4hWqkbWd+6/ip6HISeEz0NPBNTCWedSVgKYiEdnORSoiunl4l61vBmsyzQGnQl8fCYuerTLeGpq6j6Y5fBVmqmjWbmc5zeKqmg+LTfFUq9iNg5HoUPVKfjVm1aYlFG/fjMSk25j5zIgecFHAJOlQqtHXXPPCxwYLBoHBPsZE3kMu8jzE1QO8SAPOPyp2o3pD8fX1OhvqRHL/W34dqQzasmrscgvdvAy69l6nwbByOsjwvNSm2jWiNWGqFqxLgLXLy00r8A3E3hBDtQur4uo6Vs9ZSYn4mfLjEAyhyUsZeaoti8pKK5FVcJA9a//Blztbdmd8SPysXxks/6RvHIjy+aRCVxs/8Bw2Mv+AiSZ59dohNN4OUoVy3hNXk0RfdCDakw5AVq7xocAwmMLZeoWUgUt+Nb8ntt5W8KpfZVGMuxqIQoJoRMG7kf6TEHpL4vBOmosV0MwtLWkXwyXsx+zkP3GRw9mIcCkm5wEWpELYYzrOLmVQs4QHMetWsmyfTFOFlzVFPl7ctKlKuUOfbKETmrafvCNmoeOAWn58CXeEsD06ejrlg9zuPf5Vc3eIMSJ+EKIy8/eMLLFIDEzYkutqOfZoG6LJgevbgivLV7oXnG4kBF5pGVvwnpED4fTUFCFnc+MWATCN9aIJ58aLIdmF7TLYQwwXwNyyo9MvTJn/sEVjsbX/kpYrtknW1pjJ44e11du2Q5GpJXA4630g7BOOxooYTQgumoo/P3pPJnLjt9TJWPw7Q2h5rb2tqJowhltN19upncbOwMl1HPJcCqtOZOmttskMiDZGAjytiGOuD15TnfDUoZu3b97x0O6Nzm3RxGGBg4kQjC0q0RW0700EGGeCaiq9XAfUFIsS5XQ==");
     }
 
+    #[test]
+    fn validates_nonce_length() {
+        let op = CryptoOp::new("chacha20poly1305").unwrap();
+        assert_eq!(op.noncelen(), aead::noncelen());
+        assert_eq!(op.keylen(), aead::keylen());
+        CryptoOp::new_with_nonce("chacha20poly1305", vec![0; aead::noncelen()]).unwrap();
+        match CryptoOp::new_with_nonce("chacha20poly1305", vec![0; 8]) {
+            Err(CryptoError::BadData(x)) => {
+                assert!(x.contains(&format!("expected {} bytes, got 8", aead::noncelen())));
+            }
+            x => panic!("expected a BadData error, got {:?}", x),
+        }
+        assert!(CryptoOp::new_with_nonce("chacha20poly1305", vec![0; aead::noncelen() + 1]).is_err());
+    }
+
     #[test]
     fn can_gen_random_keys() {
         // test a number of hashes
@@ -454,5 +743,65 @@ mod tests {
         let res = asym::decrypt(&her_pk, &her_sk, encrypted);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn passes_self_test() {
+        self_test().unwrap();
+    }
+
+    #[test]
+    fn estimates_password_strength() {
+        for weak in &["", "password", "PASSWORD", "123456", "aaaaaaaaaaaa", "abcdefghijkl", "hunter2", "password1", "Xk9#mP"] {
+            assert!(estimate_password_strength(weak) <= 1, "{} scored too high", weak);
+        }
+        for strong in &["Tr0ub4dor&3", "correct horse battery staple", "Xk9#mP2$vL7@qR4!"] {
+            assert!(estimate_password_strength(strong) >= 3, "{} scored too low", strong);
+        }
+        assert_eq!(estimate_password_strength("correct horse battery staple"), 4);
+        assert!(estimate_password_strength("a7kq9xm2") < estimate_password_strength("a7kq9xm2-Rp4!vN8"));
+    }
+
+    #[test]
+    fn verifies_file_hmacs() {
+        let key = Key::random().unwrap();
+        let data = (0..300000).map(|x| (x % 256) as u8).collect::<Vec<_>>();
+        let mac = low::hmac(key.data().as_slice(), &data).unwrap();
+        let path = ::std::env::temp_dir().join(format!("turtl-hmac-test-{}", random_hash().unwrap()));
+        ::std::fs::write(&path, &data).unwrap();
+        assert!(verify_file_hmac(&key, &path, &mac).unwrap());
+        assert!(!verify_file_hmac(&Key::random().unwrap(), &path, &mac).unwrap());
+        let mut tampered = data.clone();
+        tampered[150000] ^= 1;
+        ::std::fs::write(&path, &tampered).unwrap();
+        assert!(!verify_file_hmac(&key, &path, &mac).unwrap());
+        ::std::fs::remove_file(&path).unwrap();
+        assert!(verify_file_hmac(&key, &path, &mac).is_err());
+    }
+
+    #[test]
+    fn derives_subkeys() {
+        let master = Key::new(from_base64(&String::from("jlz71VUIns1xM3Hq0fETZT98dxzhlqUxqb0VXYq1KtQ=")).unwrap());
+        let search1 = derive_subkey(&master, "search-index", 32).unwrap();
+        let search2 = derive_subkey(&master, "search-index", 32).unwrap();
+        let storage = derive_subkey(&master, "storage", 32).unwrap();
+        assert_eq!(search1, search2);
+        assert!(search1 != storage);
+        assert!(search1 != master);
+        assert_eq!(search1.len(), 32);
+        assert_eq!(storage.len(), 32);
+
+        // longer keys span multiple HKDF blocks, and the first block matches
+        // the shorter key
+        let long = derive_subkey(&master, "storage", 100).unwrap();
+        assert_eq!(long.len(), 100);
+        assert_eq!(&long.data()[0..32], &storage.data()[..]);
+
+        // a different master gives a different key for the same context
+        let other = derive_subkey(&Key::random().unwrap(), "search-index", 32).unwrap();
+        assert!(other != search1);
+
+        assert!(derive_subkey(&master, "storage", 0).is_err());
+        assert!(derive_subkey(&master, "storage", 255 * 32 + 1).is_err());
+    }
 }
 
@@ -20,12 +20,15 @@ use ::models::protected::Protected;
 use ::models::user::User;
 use ::models::space::Space;
 use ::models::space_member::SpaceMember;
+use ::lib_permissions::Role;
 use ::models::note::Note;
+use ::models::board::Board;
+use ::models::keychain;
 use ::models::invite::{Invite, InviteRequest};
 use ::models::file::FileData;
 use ::models::sync_record::{SyncAction, SyncType, SyncRecord};
 use ::models::feedback::Feedback;
-use ::clippo::{self, CustomParser};
+use ::clippo::{self, CustomParser, ClipOptions};
 use ::sync::sync_model;
 use ::sync;
 use ::messaging::{self, Event};
@@ -33,8 +36,19 @@ use ::migrate;
 use ::crypto::{self, Key};
 use ::std::panic;
 
+/// Commands we'll still run while the session is locked
+const LOCKED_COMMANDS: [&'static str; 5] = ["app:unlock", "app:lock", "app:connected", "user:logout", "ping"];
+
 /// Does our actual message dispatching
 fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
+    if turtl.is_locked() && !LOCKED_COMMANDS.contains(&cmd.as_str()) {
+        return TErr!(TError::Locked);
+    }
+    // pings are just the UI checking in, so they don't count as activity when
+    // deciding whether to auto-lock
+    if cmd != "ping" {
+        turtl.touch();
+    }
     match cmd.as_ref() {
         "user:login" => {
             let username: String = jedi::get(&["2"], &data)?;
@@ -64,10 +78,18 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             let user_guard = lockr!(turtl.user);
             user_guard.data()
         }
+        "user:password-strength" => {
+            let password: String = jedi::get(&["2"], &data)?;
+            Ok(json!(crypto::estimate_password_strength(&password)))
+        }
         "user:can-migrate" => {
             let old_username: String = jedi::get(&["2"], &data)?;
             let old_password: String = jedi::get(&["3"], &data)?;
-            match migrate::check_login(&old_username, &old_password) {
+            let old_endpoint: String = match jedi::get_opt(&["4"], &data) {
+                Some(x) => x,
+                None => migrate::default_endpoint()?,
+            };
+            match migrate::check_login(&old_endpoint, &old_username, &old_password) {
                 Ok(x) => {
                     match x {
                         Some(_) => Ok(json!(true)),
@@ -82,7 +104,8 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             let old_password: String = jedi::get(&["3"], &data)?;
             let new_username: String = jedi::get(&["4"], &data)?;
             let new_password: String = jedi::get(&["5"], &data)?;
-            turtl.join_migrate(old_username, old_password, new_username, new_password)?;
+            let old_endpoint: Option<String> = jedi::get_opt(&["6"], &data);
+            turtl.join_migrate(old_username, old_password, new_username, new_password, old_endpoint)?;
             let user_guard = lockr!(turtl.user);
             user_guard.data()
         }
@@ -123,6 +146,11 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             turtl.delete_account()?;
             Ok(json!({}))
         }
+        "user:rotate-keypair" => {
+            User::rotate_keypair(turtl)?;
+            let user_guard = lockr!(turtl.user);
+            user_guard.data()
+        }
         "user:resend-confirmation" => {
             User::resend_confirmation(turtl)?;
             Ok(json!({}))
@@ -135,6 +163,20 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             let token = User::get_login_token(turtl)?;
             Ok(Value::String(token))
         }
+        "user:verify-password" => {
+            let password: String = jedi::get(&["2"], &data)?;
+            let user_guard = lockr!(turtl.user);
+            Ok(Value::Bool(user_guard.verify_password(&password)?))
+        }
+        "user:sessions" => {
+            let sessions = User::sessions(turtl)?;
+            Ok(jedi::to_val(&sessions)?)
+        }
+        "user:revoke-session" => {
+            let session_id: String = jedi::get(&["2"], &data)?;
+            User::revoke_session(turtl, &session_id)?;
+            Ok(json!({}))
+        }
         "user:save-login" => {
             let key = User::save_login(turtl)?;
             Ok(json!({"user_id": turtl.user_id()?, "key": key}))
@@ -144,12 +186,29 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             let user = User::find_by_email(turtl, &email)?;
             Ok(jedi::to_val(&user)?)
         }
+        "settings:export" => {
+            User::export_settings(turtl)
+        }
+        "settings:import" => {
+            let settings: Value = jedi::get(&["2"], &data)?;
+            let replace: bool = jedi::get_opt(&["3"], &data).unwrap_or(false);
+            User::import_settings(turtl, settings, replace)
+        }
         "app:connected" => {
             let connguard = lockr!(turtl.connected);
             let connected: bool = *connguard;
             drop(connguard);
             Ok(Value::Bool(connected))
         }
+        "app:lock" => {
+            turtl.lock()?;
+            Ok(json!({}))
+        }
+        "app:unlock" => {
+            let password: String = jedi::get(&["2"], &data)?;
+            turtl.unlock(password)?;
+            Ok(json!({}))
+        }
         "app:wipe-user-data" => {
             messaging::ui_event("user:logout:clear-cookie", &Value::Null)
                 .unwrap_or_else(|e| error!("dispatch::dispatch() -- error sending ui event: {}", e));
@@ -162,6 +221,42 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             turtl.wipe_app_data()?;
             Ok(json!({}))
         }
+        "app:wipe-local-cache" => {
+            turtl.wipe_local_cache()?;
+            Ok(json!({}))
+        }
+        "app:optimize" => {
+            turtl.optimize_storage()
+        }
+        "app:storage-usage" => {
+            turtl.storage_usage()
+        }
+        "app:selftest-perf" => {
+            turtl.selftest_perf()
+        }
+        "app:backup-db" => {
+            let path: String = jedi::get(&["2"], &data)?;
+            let db_guard = lock!(turtl.db);
+            match db_guard.as_ref() {
+                Some(db) => db.backup_to(&path)?,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            }
+            Ok(json!({}))
+        }
+        "app:recent" => {
+            let limit: i32 = jedi::get_opt(&["2"], &data).unwrap_or(25);
+            let limit = ::std::cmp::max(::std::cmp::min(limit, 100), 0);
+            Ok(jedi::to_val(&turtl.recent(limit)?)?)
+        }
+        "app:copy-account" => {
+            let username: String = jedi::get(&["2"], &data)?;
+            let password: String = jedi::get(&["3"], &data)?;
+            let result = turtl.copy_account(username, password)?;
+            Ok(jedi::to_val(&result)?)
+        }
+        "app:ping-server" => {
+            turtl.api.ping()
+        }
         "app:api:set-config" => {
             let api_config: Value = jedi::get(&["2"], &data)?;
             let config_merge = json!({
@@ -176,6 +271,9 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
         "app:get-config" => {
             Ok(config::dump()?)
         }
+        "app:dump-config" => {
+            Ok(config::dump_redacted()?)
+        }
         "app:get-log" => {
             let lines: i32 = jedi::get(&["2"], &data)?;
             let contents = logger::read_log(lines)?;
@@ -210,11 +308,22 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             let pending = SyncRecord::get_all_pending(turtl)?;
             Ok(jedi::to_val(&pending)?)
         }
+        "sync:errors" => {
+            Ok(jedi::to_val(&turtl.sync_errors())?)
+        }
+        "sync:pending" => {
+            let summary = SyncRecord::pending_summary(turtl)?;
+            Ok(jedi::to_val(&summary)?)
+        }
         "sync:unfreeze-item" => {
             let sync_id: String = jedi::get(&["2"], &data)?;
             SyncRecord::kick_frozen_sync(turtl, &sync_id)?;
             Ok(json!({}))
         }
+        "sync:retry-frozen" => {
+            let count = SyncRecord::retry_frozen(turtl)?;
+            Ok(json!({"unfrozen": count}))
+        }
         "sync:delete-item" => {
             let sync_id: String = jedi::get(&["2"], &data)?;
             SyncRecord::delete_sync_item(turtl, &sync_id)?;
@@ -266,7 +375,32 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             space.edit_member(turtl, &mut member)?;
             Ok(space.data()?)
         }
-        "profile:space:delete-member" => {
+        "space:invite-member" => {
+            let space_id: String = jedi::get(&["2"], &data)?;
+            let email: String = jedi::get(&["3"], &data)?;
+            let role: Role = jedi::get(&["4"], &data)?;
+            let title: Option<String> = jedi::get_opt(&["5"], &data);
+            let mut profile_guard = lockw!(turtl.profile);
+            let space = match Profile::finder(&mut profile_guard.spaces, &space_id) {
+                Some(s) => s,
+                None => return TErr!(TError::MissingData(format!("couldn't find space {}", space_id))),
+            };
+            space.invite_member(turtl, &email, role, title)?;
+            Ok(space.data()?)
+        }
+        "space:set-member-role" => {
+            let space_id: String = jedi::get(&["2"], &data)?;
+            let user_id: String = jedi::get(&["3"], &data)?;
+            let role: Role = jedi::get(&["4"], &data)?;
+            let mut profile_guard = lockw!(turtl.profile);
+            let space = match Profile::finder(&mut profile_guard.spaces, &space_id) {
+                Some(s) => s,
+                None => return TErr!(TError::MissingData(format!("couldn't find space {}", space_id))),
+            };
+            space.set_member_role(turtl, &user_id, role)?;
+            Ok(space.data()?)
+        }
+        "profile:space:delete-member" | "space:remove-member" => {
             let space_id: String = jedi::get(&["2"], &data)?;
             let user_id: String = jedi::get(&["3"], &data)?;
             let mut profile_guard = lockw!(turtl.profile);
@@ -318,6 +452,10 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             space.delete_invite(turtl, &invite_id)?;
             Ok(space.data()?)
         }
+        "space:stats" => {
+            let space_id: String = jedi::get(&["2"], &data)?;
+            Space::stats(turtl, &space_id)
+        }
         "profile:accept-invite" => {
             let mut invite: Invite = jedi::get(&["2"], &data)?;
             let passphrase: Option<String> = jedi::get_opt(&["3"], &data);
@@ -329,11 +467,88 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
             Invite::delete_user_invite(turtl, &invite_id)?;
             Ok(json!({}))
         }
+        "note:history" => {
+            let note_id: String = jedi::get(&["2"], &data)?;
+            let history = Note::history(turtl, &note_id)?;
+            Ok(jedi::to_val(&history)?)
+        }
+        "note:reorder" => {
+            let note_ids: Vec<String> = jedi::get(&["2"], &data)?;
+            let notes = Note::reorder(turtl, &note_ids)?;
+            Ok(jedi::to_val(&notes)?)
+        }
+        "board:reorder" => {
+            let board_ids: Vec<String> = jedi::get(&["2"], &data)?;
+            let boards = Board::reorder(turtl, &board_ids)?;
+            Ok(jedi::to_val(&boards)?)
+        }
+        "note:share-link" => {
+            let note_id: String = jedi::get(&["2"], &data)?;
+            let ttl_secs: Option<u64> = jedi::get_opt(&["3"], &data);
+            let link = Note::share_link(turtl, &note_id, ttl_secs)?;
+            Ok(jedi::to_val(&link)?)
+        }
+        "note:revoke-share-link" => {
+            let note_id: String = jedi::get(&["2"], &data)?;
+            let share_id: String = jedi::get(&["3"], &data)?;
+            Note::revoke_share_link(turtl, &note_id, &share_id)?;
+            Ok(json!({}))
+        }
+        "profile:reload" => {
+            turtl.reload_profile()?;
+            Ok(json!({}))
+        }
+        "keychain:repair" => {
+            let remove_orphans: bool = jedi::get_opt(&["2"], &data).unwrap_or(false);
+            keychain::repair(turtl, remove_orphans)
+        }
+        "keychain:list" => {
+            let profile_guard = lockr!(turtl.profile);
+            Ok(jedi::to_val(&profile_guard.keychain.list())?)
+        }
+        "profile:index" => {
+            turtl.index_notes_background()?;
+            Ok(json!({}))
+        }
+        "profile:verify" => {
+            turtl.verify_profile()
+        }
         "profile:get-notes" => {
             let note_ids = jedi::get(&["2"], &data)?;
             let notes: Vec<Note> = turtl.load_notes(&note_ids)?;
             Ok(jedi::to_val(&notes)?)
         }
+        "note:import-encrypted" => {
+            let body: String = jedi::get(&["2"], &data)?;
+            let key: Key = jedi::get(&["3"], &data)?;
+            let space_id: String = jedi::get(&["4"], &data)?;
+            let board_id: Option<String> = jedi::get_opt(&["5"], &data);
+            Note::import_encrypted(turtl, body, key, &space_id, board_id)
+        }
+        "bookmark:import-batch" => {
+            let urls: Vec<String> = jedi::get(&["2"], &data)?;
+            let space_id: String = jedi::get(&["3"], &data)?;
+            let board_id: Option<String> = jedi::get_opt(&["4"], &data);
+            let custom_parsers: Vec<CustomParser> = jedi::get_opt(&["5"], &data).unwrap_or(vec![]);
+            let imports = Note::import_bookmarks(turtl, urls, custom_parsers, &space_id, board_id)?;
+            Ok(jedi::to_val(&imports)?)
+        }
+        "note:find-duplicates" => {
+            let duplicates = Note::find_duplicates(turtl)?;
+            Ok(jedi::to_val(&duplicates)?)
+        }
+        "note:merge-duplicates" => {
+            let note_ids: Vec<String> = jedi::get(&["2"], &data)?;
+            let kept = Note::merge_duplicates(turtl, &note_ids)?;
+            Ok(Value::String(kept))
+        }
+        "note:move" => {
+            let note_ids: Vec<String> = jedi::get(&["2"], &data)?;
+            let space_id: String = jedi::get(&["3"], &data)?;
+            let board_id: Option<String> = jedi::get_opt(&["4"], &data);
+            let notes = Note::move_many(turtl, &note_ids, &space_id, board_id)?;
+            Ok(jedi::to_val(&notes)?)
+        }
         "profile:find-notes" => {
             let qry: Query = match jedi::get(&["2"], &data) {
                 Ok(x) => x,
@@ -341,6 +556,7 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
                     return TErr!(TError::BadValue(format!("error deserializing search query: {}", e)));
                 }
             };
+            let qry = turtl.readable_query(&qry)?;
             let search_guard = lock!(turtl.search);
             if search_guard.is_none() {
                 return TErr!(TError::MissingField(format!("turtl is missing `search` object")));
@@ -355,6 +571,15 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
                 "total": total,
             }))
         }
+        "profile:find-notes:live" => {
+            let qry: Query = match jedi::get(&["2"], &data) {
+                Ok(x) => x,
+                Err(e) => {
+                    return TErr!(TError::BadValue(format!("error deserializing search query: {}", e)));
+                }
+            };
+            turtl.live_search(&qry)
+        }
         "profile:find-tags" => {
             let qry: Query = match jedi::get(&["2"], &data) {
                 Ok(x) => x,
@@ -362,6 +587,7 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
                     return TErr!(TError::BadValue(format!("error deserializing search query: {}", e)));
                 }
             };
+            let qry = turtl.readable_query(&qry)?;
             let search_guard = lock!(turtl.search);
             if search_guard.is_none() {
                 return TErr!(TError::MissingField(format!("turtl is missing `search` object")));
@@ -397,9 +623,14 @@ fn dispatch(cmd: &String, turtl: &Turtl, data: Value) -> TResult<Value> {
         "clip" => {
             let url: String = jedi::get(&["2"], &data)?;
             let custom_parsers: Vec<CustomParser> = jedi::get(&["3"], &data)?;
-            let proxy_cfg: Option<String> = config::get(&["api", "proxy"])
-                .unwrap_or(None);
-            let res = clippo::clip(&url, &custom_parsers, proxy_cfg)?;
+            let skip_cache: bool = jedi::get_opt(&["5"], &data).unwrap_or(false);
+            let options = ClipOptions {
+                proxy: config::get(&["api", "proxy"]).unwrap_or(None),
+                extract_text: jedi::get_opt(&["4"], &data).unwrap_or(false),
+                extract_links: jedi::get_opt(&["6"], &data).unwrap_or(false),
+                use_cache: !skip_cache,
+            };
+            let res = clippo::clip(&url, &custom_parsers, &options)?;
             Ok(jedi::to_val(&res)?)
         }
         "ping" => {
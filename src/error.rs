@@ -69,6 +69,18 @@ quick_error! {
             description("connection required")
             display("{}", json!({"type": "connection_required"}))
         }
+        Offline {
+            description("offline mode")
+            display("{}", json!({"type": "offline"}))
+        }
+        Locked {
+            description("session locked")
+            display("{}", json!({"type": "locked"}))
+        }
+        DbCorrupt(location: String) {
+            description("database corrupt")
+            display("{}", quick_error_obj!("db_corrupt", location))
+        }
         Crypto(err: CryptoError) {
             cause(err)
             description("crypto error")
@@ -139,6 +151,72 @@ impl TError {
             _ => self,
         }
     }
+
+    /// Get a stable code for this error that clients can branch on. Unlike the
+    /// error's message, these never change, so add new ones if you need to but
+    /// leave the existing ones alone.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TError::Wrapped(_, _, _, ref err) => err.code(),
+            TError::Boxed(..) => "generic",
+            TError::Msg(..) => "generic",
+            TError::Panic(..) => "panic",
+            TError::BadValue(..) => "bad_value",
+            TError::MissingField(..) => "missing_field",
+            TError::MissingData(..) => "missing_data",
+            TError::MissingCommand(..) => "missing_command",
+            TError::NotFound(..) => "not_found",
+            TError::PermissionDenied(..) => "permission_denied",
+            TError::Validation(..) => "validation",
+            TError::ConnectionRequired => "connection_required",
+            TError::Offline => "offline",
+            TError::Locked => "locked",
+            TError::DbCorrupt(..) => "db_corrupt",
+            TError::Crypto(..) => "crypto_error",
+            TError::JSON(..) => "json_error",
+            TError::Dumpy(..) => "dumpy_error",
+            TError::Clippo(..) => "clippo_error",
+            TError::Migrate(..) => "migrate_error",
+            TError::Io(..) => "io_error",
+            TError::Api(..) => "api",
+            TError::Http(..) => "http",
+            TError::ParseError(..) => "parse_error",
+            TError::TryAgain => "try_again",
+            TError::NotImplemented => "not_implemented",
+        }
+    }
+
+    /// Build the payload we send back to the UI for this error. It always has
+    /// a `code` (see `code()`) and a `message` for the humans. If `show_wrap`
+    /// is true, we include the file/line info from any wrapping.
+    pub fn to_response(&self, show_wrap: bool) -> Value {
+        let mut errval = util::json_or_string(format!("{}", self));
+        let wrapped = match errval.get("wrapped") {
+            Some(&Value::Bool(x)) => x,
+            _ => false,
+        };
+        if !show_wrap && wrapped {
+            errval = errval.get("err").cloned().unwrap_or(Value::Null);
+        }
+        let mut errval = match errval {
+            Value::Object(x) => x,
+            x => {
+                let mut obj = ::jedi::Map::new();
+                obj.insert(String::from("message"), x);
+                obj
+            }
+        };
+        if !errval.contains_key("message") {
+            #[allow(deprecated)]
+            let msg = match self {
+                TError::Wrapped(_, _, _, ref err) => String::from(err.description()),
+                _ => String::from(self.description()),
+            };
+            errval.insert(String::from("message"), Value::String(msg));
+        }
+        errval.insert(String::from("code"), Value::String(String::from(self.code())));
+        Value::Object(errval)
+    }
 }
 
 /// Define a macro that, if and when the time is right, returns a static string
@@ -329,3 +407,34 @@ macro_rules! ftry {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(TError::ConnectionRequired.code(), "connection_required");
+        assert_eq!(TError::PermissionDenied(String::from("no")).code(), "permission_denied");
+        let wrapped = twrap!(TError::PermissionDenied(String::from("get out")));
+        assert_eq!(wrapped.code(), "permission_denied");
+
+        let res = wrapped.to_response(false);
+        assert_eq!(res, json!({
+            "type": "permission_denied",
+            "code": "permission_denied",
+            "message": "get out",
+        }));
+        let res = wrapped.to_response(true);
+        assert_eq!(res["code"], json!("permission_denied"));
+        assert_eq!(res["wrapped"], json!(true));
+        assert_eq!(res["err"]["message"], json!("get out"));
+
+        let res = twrap!(TError::ConnectionRequired).to_response(false);
+        assert_eq!(res, json!({
+            "type": "connection_required",
+            "code": "connection_required",
+            "message": "connection required",
+        }));
+    }
+}
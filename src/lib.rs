@@ -62,6 +62,7 @@ use ::std::thread;
 use ::std::sync::Arc;
 use ::std::env;
 use ::std::fs;
+use ::std::time::Duration;
 use ::jedi::Value;
 use ::error::TResult;
 use ::fs2::FileExt;
@@ -115,6 +116,22 @@ pub fn init(config_str: String) -> TResult<()> {
     if data_folder != ":memory:" {
         info!("main::init() -- created data folder: {}", data_folder);
     }
+
+    if config::get(&["crypto", "self_test"]).unwrap_or(false) {
+        crypto::self_test()
+            .map_err(|e| {
+                error!("turtl::init() -- crypto self-test failed: {}", e);
+                e
+            })?;
+    }
+
+    let clip_cache_size: usize = config::get(&["clip", "cache_size"]).unwrap_or(0);
+    let clip_cache_ttl: u64 = config::get(&["clip", "cache_ttl_secs"]).unwrap_or(0);
+    clippo::set_cache(clip_cache_size, Duration::from_secs(clip_cache_ttl));
+    let clip_respect_noindex: bool = config::get(&["clip", "respect_noindex"]).unwrap_or(false);
+    let clip_allow_domains: Vec<String> = config::get(&["clip", "allow_domains"]).unwrap_or(vec![]);
+    let clip_deny_domains: Vec<String> = config::get(&["clip", "deny_domains"]).unwrap_or(vec![]);
+    clippo::set_policy(clip_respect_noindex, clip_allow_domains, clip_deny_domains);
     Ok(())
 }
 
@@ -159,6 +176,23 @@ pub fn start() -> thread::JoinHandle<()> {
             // create our turtl object
             let turtl = Arc::new(turtl::Turtl::new()?);
 
+            // periodically check if we've been idle long enough to lock the
+            // session. holds a weak ref so we don't keep turtl alive.
+            let turtl_weak = Arc::downgrade(&turtl);
+            thread::Builder::new().name(String::from("turtl-auto-lock")).spawn(move || {
+                loop {
+                    util::sleep(10000);
+                    let turtl = match turtl_weak.upgrade() {
+                        Some(x) => x,
+                        None => break,
+                    };
+                    match turtl.auto_lock_check() {
+                        Ok(_) => {}
+                        Err(e) => error!("main::start() -- auto-lock: {}", e),
+                    }
+                }
+            })?;
+
             // start our messaging thread
             let msg_res = messaging::start(move |msg: String| {
                 let turtl2 = turtl.clone();
@@ -1,6 +1,6 @@
 use ::jedi::Value;
 
-use ::error::TResult;
+use ::error::{TResult, TError};
 use ::crypto::Key;
 use ::models::model::Model;
 use ::models::validate::{self, Validate};
@@ -11,6 +11,8 @@ use ::models::sync_record::{SyncRecord, SyncAction};
 use ::turtl::Turtl;
 use ::sync::sync_model::{self, SyncModel, MemorySaver};
 use ::models::storable::Storable;
+use ::models::space::Space;
+use ::lib_permissions::Permission;
 
 protected! {
     #[derive(Serialize, Deserialize)]
@@ -27,6 +29,9 @@ protected! {
         #[serde(skip_serializing_if = "Option::is_none")]
         #[protected_field(private)]
         pub title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[protected_field(private)]
+        pub order: Option<i64>,
     }
 }
 
@@ -73,6 +78,39 @@ impl Board {
         Ok(())
     }
 
+    /// Sort boards by their `order`, putting the ones that were never ordered
+    /// last (the sort is stable, so they keep whatever order they had)
+    pub fn sort_by_order(boards: &mut Vec<Board>) {
+        boards.sort_by_key(|x| (x.order.is_none(), x.order));
+    }
+
+    /// Persist an ordering for a set of boards: each board's `order` is set to
+    /// its position in `board_ids` and the edits are synced out, so the order
+    /// carries over to other devices.
+    pub fn reorder(turtl: &Turtl, board_ids: &Vec<String>) -> TResult<Vec<Board>> {
+        let mut boards = {
+            let profile_guard = lockr!(turtl.profile);
+            let mut boards = Vec::with_capacity(board_ids.len());
+            for board_id in board_ids {
+                match profile_guard.boards.iter().find(|x| x.id() == Some(board_id)) {
+                    Some(board) => boards.push(board.clone()?),
+                    None => return TErr!(TError::MissingData(format!("cannot find board {}", board_id))),
+                }
+            }
+            boards
+        };
+        for board in &boards {
+            Space::permission_check(turtl, &board.space_id, &Permission::EditBoard)?;
+        }
+        for (idx, board) in boards.iter_mut().enumerate() {
+            board.order = Some(idx as i64);
+            sync_model::save_model(SyncAction::Edit, turtl, board, false)?;
+        }
+        let mut profile_guard = lockw!(turtl.profile);
+        Board::sort_by_order(&mut profile_guard.boards);
+        Ok(boards)
+    }
+
     /// Given a Turtl/board_id, grab that boards's space_id (if it exists)
     pub fn get_space_id(turtl: &Turtl, board_id: &String) -> Option<String> {
         let mut db_guard = lock!(turtl.db);
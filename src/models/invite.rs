@@ -165,6 +165,24 @@ impl Invite {
         Ok(())
     }
 
+    /// Take an invite sealed to our old pubkey and re-seal it to our new one
+    /// (used when rotating the user's keypair). Passphrase-protected invites
+    /// can't be re-sealed since we'd need the passphrase to get at the message.
+    pub fn reseal(&mut self, old_pubkey: &Key, old_privkey: &Key, new_pubkey: &Key) -> TResult<()> {
+        if !self.is_pubkey_protected { return Ok(()); }
+        if self.is_passphrase_protected {
+            return TErr!(TError::BadValue(format!("invite {:?} is passphrase-protected and cannot be re-sealed", self.id())));
+        }
+        self.open(old_pubkey, old_privkey, None)?;
+        let message = match self.message.take() {
+            Some(x) => x,
+            None => return TErr!(TError::MissingField(String::from("Invite.message"))),
+        };
+        self.message = Some(crypto::asym::encrypt(new_pubkey, message)?);
+        Protected::serialize(self)?;
+        Ok(())
+    }
+
     /// Ship it!
     pub fn send(&self, turtl: &Turtl) -> TResult<()> {
         let url = format!("/spaces/{}/invites", self.space_id);
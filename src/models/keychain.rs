@@ -257,6 +257,23 @@ impl Keychain {
         }
         found
     }
+
+    /// List out our entries' metadata (entry id, item id, type, and whether
+    /// we have the key) for debugging. This is built field by field instead of
+    /// serializing the entries so key material (`k` or the encrypted `body`)
+    /// never makes it out.
+    pub fn list(&self) -> Vec<Value> {
+        self.entries.iter()
+            .map(|entry| {
+                json!({
+                    "id": entry.id(),
+                    "item_id": entry.item_id,
+                    "type": entry.ty,
+                    "has_key": entry.k.is_some(),
+                })
+            })
+            .collect::<Vec<_>>()
+    }
 }
 
 // NOTE: for the following two functions, instead of saving to
@@ -330,10 +347,57 @@ pub fn remove_key(turtl: &Turtl, item_id: &String, skip_remote_sync: bool) -> TR
 }
 // <<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<
 
+/// Check the keychain against the profile. Reports orphaned entries (keys for
+/// spaces/boards we no longer have) and spaces that have no keychain entry at
+/// all. If `remove_orphans` is set, the orphaned entries are deleted (and the
+/// deletions synced out). Missing keys are only reported: if we don't have an
+/// entry for a space, we don't have a trustworthy key to save for it either.
+pub fn repair(turtl: &Turtl, remove_orphans: bool) -> TResult<Value> {
+    let (orphaned, missing) = {
+        let profile_guard = lockr!(turtl.profile);
+        let space_ids = profile_guard.spaces.iter()
+            .filter_map(|x| x.id())
+            .collect::<Vec<_>>();
+        let board_ids = profile_guard.boards.iter()
+            .filter_map(|x| x.id())
+            .collect::<Vec<_>>();
+        let orphaned = profile_guard.keychain.entries.iter()
+            .filter(|entry| !space_ids.contains(&&entry.item_id) && !board_ids.contains(&&entry.item_id))
+            .map(|entry| {
+                json!({
+                    "id": entry.id(),
+                    "item_id": entry.item_id,
+                    "type": entry.ty,
+                })
+            })
+            .collect::<Vec<_>>();
+        let missing = space_ids.iter()
+            .filter(|id| profile_guard.keychain.find_entry(id).is_none())
+            .map(|id| json!({"id": id, "type": "space"}))
+            .collect::<Vec<_>>();
+        (orphaned, missing)
+    };
+
+    let mut removed = 0;
+    if remove_orphans {
+        for entry in &orphaned {
+            let entry_id: String = jedi::get(&["id"], entry)?;
+            sync_model::delete_model::<KeychainEntry>(turtl, &entry_id, false)?;
+            removed += 1;
+        }
+    }
+    Ok(json!({
+        "orphaned": orphaned,
+        "missing": missing,
+        "removed": removed,
+    }))
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
     use ::crypto::Key;
+    use ::models::space::Space;
 
     #[test]
     fn upserts_keys_properly() {
@@ -353,5 +417,70 @@ pub mod tests {
         let entry_b_id = kc.find_entry(&item1_id).unwrap().id().unwrap().clone();
         assert_eq!(entry_a_id, entry_b_id);
     }
+
+    #[test]
+    fn repairs_keychain() {
+        let turtl = ::turtl::tests::with_test(true);
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        // a key for a space that's long gone
+        let orphan_id = String::from("1234");
+        save_key(&turtl, &orphan_id, &Key::random().unwrap(), &String::from("space"), false).unwrap();
+        // and a space that never made it into the keychain
+        let mut keyless: Space = jedi::from_val(json!({"user_id": 51, "title": "get a better job"})).unwrap();
+        let keyless_id = keyless.generate_id().unwrap().clone();
+        keyless.generate_key().unwrap();
+        lockw!(turtl.profile).spaces.push(keyless);
+
+        let report = repair(&turtl, false).unwrap();
+        assert_eq!(report["orphaned"].as_array().unwrap().len(), 1);
+        assert_eq!(report["orphaned"][0]["item_id"], json!(orphan_id));
+        assert_eq!(report["orphaned"][0]["type"], json!("space"));
+        assert_eq!(report["missing"], json!([{"id": keyless_id, "type": "space"}]));
+        assert_eq!(report["removed"], json!(0));
+        assert!(lockr!(turtl.profile).keychain.find_entry(&orphan_id).is_some());
+
+        let orphan_entry_id: String = jedi::get(&["orphaned", "0", "id"], &report).unwrap();
+        let report = repair(&turtl, true).unwrap();
+        assert_eq!(report["removed"], json!(1));
+        {
+            let profile_guard = lockr!(turtl.profile);
+            assert!(profile_guard.keychain.find_entry(&orphan_id).is_none());
+            assert!(profile_guard.keychain.find_entry(&space_id).is_some());
+        }
+        let db_guard = lock!(turtl.db);
+        let stored: Option<KeychainEntry> = db_guard.as_ref().unwrap().get("keychain", &orphan_entry_id).unwrap();
+        assert!(stored.is_none());
+        drop(db_guard);
+
+        let report = repair(&turtl, true).unwrap();
+        assert_eq!(report["orphaned"], json!([]));
+        assert_eq!(report["removed"], json!(0));
+    }
+
+    #[test]
+    fn lists_without_keys() {
+        let turtl = ::turtl::tests::with_test(true);
+        let mut kc = Keychain::new();
+        let key = Key::random().unwrap();
+        kc.upsert_key(&turtl, &String::from("1234"), &key, &String::from("space")).unwrap();
+        kc.upsert_key(&turtl, &String::from("5678"), &key, &String::from("board")).unwrap();
+        kc.entries[1].serialize().unwrap();
+
+        let list = kc.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0]["item_id"], json!("1234"));
+        assert_eq!(list[0]["type"], json!("space"));
+        assert_eq!(list[0]["has_key"], json!(true));
+        assert_eq!(list[1]["item_id"], json!("5678"));
+        for item in &list {
+            let mut fields = item.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+            fields.sort();
+            assert_eq!(fields, vec!["has_key", "id", "item_id", "type"]);
+        }
+        let listed = jedi::stringify(&list).unwrap();
+        assert!(!listed.contains(&::crypto::to_base64(key.data()).unwrap()));
+    }
 }
 
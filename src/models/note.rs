@@ -1,15 +1,25 @@
+use ::jedi::{self, Value};
 use ::turtl::Turtl;
-use ::error::TResult;
+use ::error::{TResult, TError, TFutureResult};
 use ::models::model::Model;
 use ::models::validate::{self, Validate};
-use ::models::protected::{Keyfinder, Protected};
+use ::models::protected::{self, Keyfinder, Protected};
 use ::models::keychain::{Keychain, KeyRef, KeyType};
 use ::models::file::{File, FileData};
-use ::models::sync_record::{SyncRecord, SyncAction};
-use ::crypto::Key;
+use ::models::sync_record::{SyncRecord, SyncAction, SyncType};
+use ::crypto::{self, Key};
 use ::sync::sync_model::{self, SyncModel, MemorySaver};
 use ::std::fs;
 use ::models::storable::Storable;
+use ::models::space::Space;
+use ::models::board::Board;
+use ::lib_permissions::Permission;
+use ::config;
+use ::std::time::{SystemTime, UNIX_EPOCH};
+use ::std::collections::HashMap;
+use ::std::sync::Arc;
+use ::clippo::{self, CustomParser, ClipOptions};
+use ::futures::{future, Future};
 
 protected! {
     #[derive(Serialize, Deserialize)]
@@ -59,9 +69,29 @@ protected! {
         #[serde(skip_serializing_if = "Option::is_none")]
         #[protected_field(private)]
         pub color: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[protected_field(private)]
+        pub order: Option<i64>,
     }
 }
 
+/// A time-limited link that lets someone without a Turtl account view a note.
+///
+/// The note's key is encrypted under a random share key and registered with
+/// the server, but the share key itself only lives in the link's fragment
+/// (which browsers never send along), so the server can't read the note.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShareLink {
+    /// The server's id for this link (used to revoke it)
+    pub id: String,
+    /// The note being shared
+    pub note_id: String,
+    /// The link itself
+    pub url: String,
+    /// When the link stops working (unix time, in seconds)
+    pub expires: u64,
+}
+
 make_storable!(Note, "notes");
 impl SyncModel for Note {}
 
@@ -78,6 +108,19 @@ impl Validate for Note {
     }
 }
 
+/// How a single url fared in a bookmark import
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookmarkImport {
+    /// The url we were asked to import
+    pub url: String,
+    /// The id of the bookmark note we created (if all went well)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note_id: Option<String>,
+    /// What went wrong (if it didn't)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 impl Note {
     /// Remove the files attached to this note, if any.
     fn clear_files(&self) -> TResult<()> {
@@ -98,6 +141,316 @@ impl Note {
         Ok(())
     }
 
+    /// Move a bunch of notes into the given space/board in one go.
+    ///
+    /// Everything is checked (permissions, notes exist, the board lives in the
+    /// target space) before any note is touched so we don't end up with half
+    /// the notes moved. Notes changing spaces get their keyrefs regenerated
+    /// against the new space (and board) by `save_model()`, and each move
+    /// lands in the outgoing queue so the sync system sends them up together.
+    pub fn move_many(turtl: &Turtl, note_ids: &Vec<String>, to_space_id: &String, to_board_id: Option<String>) -> TResult<Vec<Note>> {
+        if let Some(board_id) = to_board_id.as_ref() {
+            match Board::get_space_id(turtl, board_id) {
+                Some(ref space_id) if space_id == to_space_id => {}
+                Some(_) => return TErr!(TError::BadValue(format!("board {} is not in space {}", board_id, to_space_id))),
+                None => return TErr!(TError::MissingData(format!("cannot find board {}", board_id))),
+            }
+        }
+        let mut notes = turtl.load_notes(note_ids)?;
+        if notes.len() != note_ids.len() {
+            let missing = note_ids.iter()
+                .filter(|id| !notes.iter().any(|n| n.id() == Some(id)))
+                .map(|x| x.clone())
+                .collect::<Vec<_>>();
+            return TErr!(TError::MissingData(format!("cannot find notes {:?}", missing)));
+        }
+        Space::permission_check(turtl, to_space_id, &Permission::AddNote)?;
+        for note in &notes {
+            if &note.space_id == to_space_id {
+                Space::permission_check(turtl, &note.space_id, &Permission::EditNote)?;
+            } else {
+                Space::permission_check(turtl, &note.space_id, &Permission::DeleteNote)?;
+            }
+        }
+        for note in &mut notes {
+            if &note.space_id == to_space_id {
+                note.board_id = to_board_id.clone();
+                sync_model::save_model(SyncAction::Edit, turtl, note, false)?;
+            } else {
+                note.move_spaces(turtl, to_space_id.clone(), to_board_id.clone())?;
+            }
+        }
+        Ok(notes)
+    }
+
+    /// Persist an ordering for a set of notes: each note's `order` is set to
+    /// its position in `note_ids` and the edits are synced out. Searching with
+    /// `"sort": "order"` returns notes in this order.
+    pub fn reorder(turtl: &Turtl, note_ids: &Vec<String>) -> TResult<Vec<Note>> {
+        let mut notes = turtl.load_notes(note_ids)?;
+        if notes.len() != note_ids.len() {
+            let missing = note_ids.iter()
+                .filter(|id| !notes.iter().any(|n| n.id() == Some(id)))
+                .map(|x| x.clone())
+                .collect::<Vec<_>>();
+            return TErr!(TError::MissingData(format!("cannot find notes {:?}", missing)));
+        }
+        for note in &notes {
+            Space::permission_check(turtl, &note.space_id, &Permission::EditNote)?;
+        }
+        for (idx, note) in notes.iter_mut().enumerate() {
+            note.order = Some(idx as i64);
+            sync_model::save_model(SyncAction::Edit, turtl, note, false)?;
+        }
+        Ok(notes)
+    }
+
+    /// Grab the prior versions of a note from the server and decrypt them with
+    /// the note's key, oldest first. Versions we can't decrypt are skipped.
+    pub fn history(turtl: &Turtl, note_id: &String) -> TResult<Vec<Value>> {
+        let notes = turtl.load_notes(&vec![note_id.clone()])?;
+        let key = match notes.get(0).and_then(|x| x.key()) {
+            Some(x) => x.clone(),
+            None => return TErr!(TError::MissingData(format!("cannot find note {} (or its key)", note_id))),
+        };
+        let url = format!("/notes/{}/versions", note_id);
+        let versions: Vec<Value> = turtl.api.get(url.as_str())?.call()?;
+        let mut history: Vec<Note> = Vec::with_capacity(versions.len());
+        for version in versions {
+            let mut note: Note = jedi::from_val(version)?;
+            note.set_key(Some(key.clone()));
+            match note.deserialize() {
+                Ok(_) => history.push(note),
+                Err(e) => warn!("Note::history() -- skipping version of note {} ({:?}): {}", note_id, note.mod_, e),
+            }
+        }
+        history.sort_by_key(|x| x.mod_);
+        let mut out = Vec::with_capacity(history.len());
+        for note in &history {
+            out.push(note.data()?);
+        }
+        Ok(out)
+    }
+
+    /// Import a single note from its encrypted body and the key it was
+    /// encrypted with. If the body decrypts, the note's content is saved as a
+    /// brand new note (fresh id/keys) in the given space/board and synced out.
+    pub fn import_encrypted(turtl: &Turtl, body: String, key: Key, space_id: &String, board_id: Option<String>) -> TResult<Value> {
+        let mut imported = Note::new();
+        imported.set_body(body);
+        imported.set_key(Some(key));
+        match imported.deserialize() {
+            Ok(_) => {}
+            Err(e) => return TErr!(TError::BadValue(format!("cannot decrypt note: {}", e))),
+        }
+        let mut notedata = imported.data()?;
+        // only the note's content carries over. anything tying it to the old
+        // note (id, keys, file, etc) is regenerated when we save it.
+        for field in &["id", "body", "keys", "user_id", "has_file", "file", "mod"] {
+            match jedi::remove(&[*field], &mut notedata) {
+                Ok(_) => {}
+                Err(_) => {}
+            }
+        }
+        jedi::set(&["space_id"], &mut notedata, space_id)?;
+        jedi::set(&["board_id"], &mut notedata, &board_id)?;
+        let mut sync_record = SyncRecord::default();
+        sync_record.action = SyncAction::Add;
+        sync_record.ty = SyncType::Note;
+        sync_record.data = Some(notedata);
+        sync_model::dispatch(turtl, sync_record)
+    }
+
+    /// Clip a bunch of urls and save each one as a bookmark note in the given
+    /// space/board. The clipping runs on our worker pool, and once it's done
+    /// the notes are saved one by one.
+    /// One bad url doesn't sink the rest: we hand back how each url fared, in
+    /// the order given.
+    pub fn import_bookmarks(turtl: &Turtl, urls: Vec<String>, parsers: Vec<CustomParser>, space_id: &String, board_id: Option<String>) -> TResult<Vec<BookmarkImport>> {
+        Space::permission_check(turtl, space_id, &Permission::AddNote)?;
+        let options = ClipOptions {
+            proxy: config::get(&["api", "proxy"]).unwrap_or(None),
+            use_cache: true,
+            ..Default::default()
+        };
+        let parsers = Arc::new(parsers);
+        let ref work = turtl.work;
+        let futures = urls.iter()
+            .map(|url| -> TFutureResult<TResult<Value>> {
+                let url = url.clone();
+                let parsers = parsers.clone();
+                let options = options.clone();
+                // hang onto each clip's result (good or bad) so one failed url
+                // doesn't take the rest of the import down with it
+                let fut = work.run_async(move || Ok(jedi::to_val(&clippo::clip(&url, &parsers, &options)?)?))
+                    .then(|res| -> TFutureResult<TResult<Value>> { FOk!(res) });
+                Box::new(fut)
+            })
+            .collect::<Vec<_>>();
+        // join_all hands these back in the order we started them
+        let clips = future::join_all(futures).wait()?;
+        let mut imports = Vec::with_capacity(urls.len());
+        for (url, clipped) in urls.iter().zip(clips) {
+            let res = clipped.and_then(|clip| {
+                let notedata = json!({
+                    "space_id": space_id,
+                    "board_id": &board_id,
+                    "type": "bookmark",
+                    "url": url,
+                    "title": jedi::get_opt::<String>(&["title"], &clip),
+                    "text": jedi::get_opt::<String>(&["description"], &clip),
+                });
+                let mut sync_record = SyncRecord::default();
+                sync_record.action = SyncAction::Add;
+                sync_record.ty = SyncType::Note;
+                sync_record.data = Some(notedata);
+                let note = sync_model::dispatch(turtl, sync_record)?;
+                Ok(jedi::get::<String>(&["id"], &note)?)
+            });
+            imports.push(match res {
+                Ok(note_id) => BookmarkImport { url: url.clone(), note_id: Some(note_id), error: None },
+                Err(e) => {
+                    warn!("Note::import_bookmarks() -- failed to import {}: {}", url, e);
+                    BookmarkImport { url: url.clone(), note_id: None, error: Some(format!("{}", e)) }
+                }
+            });
+        }
+        Ok(imports)
+    }
+
+    /// Hash the parts of this note a person would call its content. Two notes
+    /// with the same hash are duplicates of each other. The space/board count
+    /// as content here, so the same note living in two places isn't a dupe.
+    fn content_hash(&self) -> TResult<String> {
+        let mut tags = self.tags.clone().unwrap_or(vec![]);
+        tags.sort();
+        let file = self.file.as_ref().map(|x| json!({
+            "name": &x.name,
+            "size": &x.size,
+            "type": &x.ty,
+        }));
+        let content = json!({
+            "space_id": &self.space_id,
+            "board_id": &self.board_id,
+            "type": &self.type_,
+            "title": &self.title,
+            "tags": tags,
+            "url": &self.url,
+            "username": &self.username,
+            "password": &self.password,
+            "text": &self.text,
+            "embed": &self.embed,
+            "color": &self.color,
+            "file": file,
+        });
+        Ok(crypto::content_hash(jedi::stringify(&content)?.as_bytes())?)
+    }
+
+    /// Find sets of notes with the same content (see `content_hash()`). Each
+    /// set is a list of note ids, oldest first.
+    pub fn find_duplicates(turtl: &Turtl) -> TResult<Vec<Vec<String>>> {
+        let notes = {
+            let db_guard = lock!(turtl.db);
+            let db = match db_guard.as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            let mut notes: Vec<Note> = db.all("notes")?;
+            turtl.find_models_keys(&mut notes)?;
+            protected::map_deserialize(turtl, notes)?
+        };
+        let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+        for note in &notes {
+            let note_id = note.id_or_else()?;
+            by_hash.entry(note.content_hash()?).or_insert(vec![]).push(note_id);
+        }
+        let mut duplicates = by_hash.into_iter()
+            .map(|(_, mut ids)| { ids.sort(); ids })
+            .filter(|ids| ids.len() > 1)
+            .collect::<Vec<_>>();
+        duplicates.sort();
+        Ok(duplicates)
+    }
+
+    /// Merge a set of duplicate notes: the first note in `note_ids` is kept and
+    /// the rest are deleted (and the deletions synced out). The notes have to
+    /// actually be duplicates, so a set straight out of `find_duplicates()`
+    /// keeps the oldest note. Returns the id of the note we kept.
+    pub fn merge_duplicates(turtl: &Turtl, note_ids: &Vec<String>) -> TResult<String> {
+        if note_ids.len() < 2 {
+            return TErr!(TError::BadValue(String::from("need at least two notes to merge")));
+        }
+        let notes = turtl.load_notes(note_ids)?;
+        if notes.len() != note_ids.len() {
+            let missing = note_ids.iter()
+                .filter(|id| !notes.iter().any(|n| n.id() == Some(id)))
+                .map(|x| x.clone())
+                .collect::<Vec<_>>();
+            return TErr!(TError::MissingData(format!("cannot find notes {:?}", missing)));
+        }
+        let keep_hash = notes[0].content_hash()?;
+        for note in &notes[1..] {
+            if note.content_hash()? != keep_hash {
+                return TErr!(TError::BadValue(format!("note {} is not a duplicate of note {}", note.id_or_else()?, note_ids[0])));
+            }
+            Space::permission_check(turtl, &note.space_id, &Permission::DeleteNote)?;
+        }
+        for note in &notes[1..] {
+            sync_model::delete_model::<Note>(turtl, &note.id_or_else()?, false)?;
+        }
+        Ok(note_ids[0].clone())
+    }
+
+    /// Create a share link for a note that expires after `ttl_secs` (or the
+    /// configured `share.default_ttl_secs` if not given).
+    pub fn share_link(turtl: &Turtl, note_id: &String, ttl_secs: Option<u64>) -> TResult<ShareLink> {
+        let notes = turtl.load_notes(&vec![note_id.clone()])?;
+        let (space_id, note_key) = match notes.get(0) {
+            Some(note) => match note.key() {
+                Some(key) => (note.space_id.clone(), key.clone()),
+                None => return TErr!(TError::MissingData(format!("note {} has no key", note_id))),
+            },
+            None => return TErr!(TError::MissingData(format!("cannot find note {}", note_id))),
+        };
+        Space::permission_check(turtl, &space_id, &Permission::EditNote)?;
+        let ttl = match ttl_secs {
+            Some(x) => x,
+            None => config::get(&["share", "default_ttl_secs"])?,
+        };
+        if ttl == 0 {
+            return TErr!(TError::BadValue(String::from("share link ttl must be greater than 0")));
+        }
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0) + ttl;
+
+        let share_key = Key::random()?;
+        let encrypted_key = protected::encrypt_key(&share_key, note_key)?;
+        let url = format!("/notes/{}/shares", note_id);
+        let data = json!({"key": encrypted_key, "expires": expires});
+        let share: Value = turtl.api.post(url.as_str())?.json(&data).call()?;
+        let share_id: String = jedi::get(&["id"], &share)?;
+        let base: String = config::get(&["share", "url"])?;
+        Ok(ShareLink {
+            url: format!("{}/{}#{}", base.trim_end_matches('/'), share_id, crypto::to_hex(share_key.data())?),
+            id: share_id,
+            note_id: note_id.clone(),
+            expires: expires,
+        })
+    }
+
+    /// Revoke a note's share link, killing it before it expires
+    pub fn revoke_share_link(turtl: &Turtl, note_id: &String, share_id: &String) -> TResult<()> {
+        let space_id = match Note::get_space_id(turtl, note_id) {
+            Some(x) => x,
+            None => return TErr!(TError::MissingData(format!("cannot find note {}", note_id))),
+        };
+        Space::permission_check(turtl, &space_id, &Permission::EditNote)?;
+        let url = format!("/notes/{}/shares/{}", note_id, share_id);
+        turtl.api.delete(url.as_str())?.call::<Value>()?;
+        Ok(())
+    }
+
     /// Given a Turtl/note_id, grab that note's space_id (if it exists)
     pub fn get_space_id(turtl: &Turtl, note_id: &String) -> Option<String> {
         let mut db_guard = lock!(turtl.db);
@@ -5,6 +5,7 @@ use ::models::note::Note;
 use ::models::invite::{Invite, InviteRequest};
 use ::models::protected::{Keyfinder, Protected};
 use ::models::space_member::SpaceMember;
+use ::models::user::User;
 use ::models::sync_record::{SyncRecord, SyncAction};
 use ::models::validate::{self, Validate};
 use ::models::keychain;
@@ -184,6 +185,86 @@ impl Space {
         }
     }
 
+    /// Like `permission_check()`, but checks that the current user can read
+    /// the notes in the given space (see `Space.can_read()`).
+    pub fn read_check(turtl: &Turtl, space_id: &String) -> TResult<()> {
+        let user_id = turtl.user_id()?;
+        let profile_guard = lockr!(turtl.profile);
+        let space = match profile_guard.spaces.iter().find(|space| space.id() == Some(space_id)) {
+            Some(x) => x,
+            None => return TErr!(TError::PermissionDenied(format!("user {} cannot read space {} (space is missing)", user_id, space_id))),
+        };
+        match space.can_read(&user_id) {
+            true => Ok(()),
+            false => TErr!(TError::PermissionDenied(format!("user {} cannot read space {}", user_id, space_id))),
+        }
+    }
+
+    /// Gather some counts about a space for debugging: how many boards/notes
+    /// it has, how many of those we couldn't decrypt, and how many outgoing
+    /// syncs for the space (or anything in it) are still waiting to go out.
+    ///
+    /// Decrypt failures are counted by actually trying to find each model's key
+    /// and decrypt it (the same check `Turtl.verify_profile()` runs).
+    pub fn stats(turtl: &Turtl, space_id: &String) -> TResult<Value> {
+        /// Count the models we can't find a key for or can't decrypt
+        fn count_failed<T>(turtl: &Turtl, models: Vec<T>) -> usize
+            where T: Protected + Keyfinder
+        {
+            let mut failed = 0;
+            for mut model in models {
+                let decrypted = turtl.find_model_key(&mut model)
+                    .and_then(|_| model.deserialize());
+                if decrypted.is_err() { failed += 1; }
+            }
+            failed
+        }
+
+        let board_ids = {
+            let profile_guard = lockr!(turtl.profile);
+            if !profile_guard.spaces.iter().any(|x| x.id() == Some(space_id)) {
+                return TErr!(TError::MissingData(format!("couldn't find space {}", space_id)));
+            }
+            profile_guard.boards.iter()
+                .filter(|x| &x.space_id == space_id)
+                .filter_map(|x| x.id().map(|id| id.clone()))
+                .collect::<Vec<_>>()
+        };
+        let (boards, notes) = {
+            let db_guard = lock!(turtl.db);
+            let db = match db_guard.as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            let boards: Vec<Board> = db.find("boards", "space_id", &vec![space_id.clone()])?;
+            let notes: Vec<Note> = db.find("notes", "space_id", &vec![space_id.clone()])?;
+            (boards, notes)
+        };
+        let note_ids = notes.iter()
+            .filter_map(|x| x.id().map(|id| id.clone()))
+            .collect::<Vec<_>>();
+        let failed_boards = count_failed(turtl, boards);
+        let failed_notes = count_failed(turtl, notes);
+        let syncs = SyncRecord::get_all_pending(turtl)?
+            .into_iter()
+            .filter(|x| &x.item_id == space_id || board_ids.contains(&x.item_id) || note_ids.contains(&x.item_id))
+            .collect::<Vec<_>>();
+        let num_frozen = syncs.iter().filter(|x| x.frozen).count();
+        Ok(json!({
+            "space_id": space_id,
+            "boards": board_ids.len(),
+            "notes": note_ids.len(),
+            "decrypt_failed": {
+                "boards": failed_boards,
+                "notes": failed_notes,
+            },
+            "sync": {
+                "pending": syncs.len(),
+                "frozen": num_frozen,
+            },
+        }))
+    }
+
     /// Checks if a user has the given permission on the current space
     pub fn can_i(&self, user_id: &String, permission: &Permission) -> TResult<bool> {
         // if we're the owner, we can do anything
@@ -201,6 +282,14 @@ impl Space {
         Ok(me.role.can(&permission))
     }
 
+    /// Checks if a user can read the notes in this space. There's no read
+    /// permission in lib_permissions (every role can read, even guests), so
+    /// this is the same as asking if the user has any role in the space.
+    pub fn can_read(&self, user_id: &String) -> bool {
+        if user_id == &self.user_id { return true; }
+        self.members.iter().any(|member| &member.user_id == user_id)
+    }
+
     /// Checks if a user has the given permission on the current space, and if
     /// not, returns an error
     pub fn can_i_or_else(&self, user_id: &String, permission: &Permission) -> TResult<()> {
@@ -294,6 +383,59 @@ impl Space {
         Ok(())
     }
 
+    /// Invite an existing Turtl user to this space. We look up their pubkey
+    /// and seal the space key to it, so the invite doesn't need a passphrase:
+    /// only they can open it. Nothing is added to the space's members here;
+    /// they become a member (with the given role) once they accept.
+    pub fn invite_member(&mut self, turtl: &Turtl, email: &String, role: Role, title: Option<String>) -> TResult<()> {
+        turtl.assert_connected()?;
+        model_getter!(get_field, "Space.invite_member()");
+        let space_id = get_field!(self, id);
+        let user_id = turtl.user_id()?;
+        self.can_i_or_else(&user_id, &Permission::AddSpaceInvite)?;
+        if role == Role::Owner {
+            return TErr!(TError::BadValue(String::from("cannot invite a member as owner (use set_owner() instead)")));
+        }
+
+        let their_pubkey = match User::find_by_email(turtl, email)? {
+            Some(user) => match user.pubkey {
+                Some(x) => x,
+                None => return TErr!(TError::MissingData(format!("user {} has no public key", email))),
+            },
+            None => return TErr!(TError::NotFound(format!("no user found with email {}", email))),
+        };
+        let title = match title {
+            Some(x) => x,
+            None => self.title.clone().unwrap_or(String::from("Shared space")),
+        };
+        let invite_request = InviteRequest {
+            space_id: space_id,
+            to_user: email.clone(),
+            role: role,
+            title: title,
+            their_pubkey: Some(their_pubkey),
+            passphrase: None,
+        };
+        self.send_invite(turtl, invite_request)
+    }
+
+    /// Change a member's role (and the permissions that go along with it)
+    pub fn set_member_role(&mut self, turtl: &Turtl, member_user_id: &String, role: Role) -> TResult<()> {
+        if role == Role::Owner {
+            return TErr!(TError::BadValue(String::from("cannot set a member's role to owner (use set_owner() instead)")));
+        }
+        let mut member: SpaceMember = {
+            let existing_member = self.find_member_by_user_id_or_else(member_user_id)?;
+            if existing_member.role == Role::Owner {
+                return TErr!(TError::BadValue(String::from("cannot change the owner's role (use set_owner() instead)")));
+            }
+            jedi::from_val(jedi::to_val(existing_member)?)?
+        };
+        member.permissions = role.allowed_permissions();
+        member.role = role;
+        self.edit_member(turtl, &mut member)
+    }
+
     /// Leave the space (as the current user). Like delete, but without a
     /// permission check.
     pub fn leave(&mut self, turtl: &Turtl) -> TResult<()> {
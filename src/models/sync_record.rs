@@ -117,6 +117,18 @@ make_storable!(SyncRecord, "sync");
 impl SyncModel for SyncRecord {}
 impl Keyfinder for SyncRecord {}
 
+/// A rundown of what's sitting in our outgoing sync queue, waiting for the
+/// server to confirm it.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct PendingSummary {
+    /// How many records are waiting to go out
+    pub count: usize,
+    /// Roughly how many bytes those records take up (serialized)
+    pub bytes: usize,
+    /// How many of the waiting records are frozen
+    pub frozen: usize,
+}
+
 impl SyncRecord {
     /// Clone the non-data, mostly-important bits of a sync record.
     pub fn clone_shallow(&self) -> Self {
@@ -183,6 +195,25 @@ impl SyncRecord {
         Ok(pending)
     }
 
+    /// Summarize the outgoing sync records the server hasn't confirmed yet, so
+    /// the UI can tell the user what they'd lose by going offline. Incoming
+    /// file downloads live in the same queue but don't count.
+    pub fn pending_summary(turtl: &Turtl) -> TResult<PendingSummary> {
+        let mut db_guard = lock!(turtl.db);
+        let db = match db_guard.as_mut() {
+            Some(x) => x,
+            None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+        };
+        let pending = SyncRecord::allbut(db, &vec![SyncType::FileIncoming])?;
+        let mut summary: PendingSummary = Default::default();
+        for sync in &pending {
+            summary.count += 1;
+            summary.bytes += jedi::stringify(sync)?.len();
+            if sync.frozen { summary.frozen += 1; }
+        }
+        Ok(summary)
+    }
+
     /// Increment this SyncRecord's errcount. If it's above a magic number, we
     /// mark the sync as failed, which excludes it from further outgoing syncs
     /// until it gets manually shaken/removed.
@@ -226,6 +257,28 @@ impl SyncRecord {
         Ok(())
     }
 
+    /// Unfreeze every frozen sync record and reset its error count, so they all
+    /// get another go in the next outgoing sync (handy once whatever was making
+    /// the server choke on them is fixed). Returns how many we unfroze.
+    pub fn retry_frozen(turtl: &Turtl) -> TResult<usize> {
+        let mut db_guard = lock!(turtl.db);
+        let db = match db_guard.as_mut() {
+            Some(x) => x,
+            None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+        };
+        let frozen = SyncRecord::find(db, None)?
+            .into_iter()
+            .filter(|x| x.frozen)
+            .collect::<Vec<_>>();
+        let count = frozen.len();
+        for mut rec in frozen {
+            rec.frozen = false;
+            rec.errcount = 0;
+            db.save(&rec)?;
+        }
+        Ok(count)
+    }
+
     /// Public/static method for deleting a sync record (probably initiated from
     /// the UI).
     pub fn delete_sync_item(turtl: &Turtl, sync_id: &String) -> TResult<()> {
@@ -2,10 +2,11 @@ use ::std::collections::HashMap;
 use ::jedi::{self, Value, Serialize};
 use ::error::{TResult, TError};
 use ::crypto::{self, Key, CryptoOp};
-use ::api::{ApiReq, StatusCode};
+use ::api::{Api, ApiReq, StatusCode};
 use ::models::model::{self, Model};
 use ::models::space::Space;
 use ::models::board::Board;
+use ::models::invite::Invite;
 use ::models::protected::{Keyfinder, Protected};
 use ::models::sync_record::{SyncType, SyncAction, SyncRecord};
 use ::models::validate::{self, Validate};
@@ -78,6 +79,28 @@ impl LoginToken {
     }
 }
 
+/// A device/app that's currently logged into the user's account, as the API
+/// sees it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    /// The server's id for this session (used to revoke it)
+    pub id: String,
+    /// Whatever the client told the API about itself when logging in
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// When the session was created (unix time, in seconds)
+    #[serde(default)]
+    pub created: Option<i64>,
+    /// When the session was last used (unix time, in seconds)
+    #[serde(default)]
+    pub last_active: Option<i64>,
+    /// Whether this is the session we're using right now
+    #[serde(default)]
+    pub current: bool,
+}
+
 make_storable!(User, "users");
 impl SyncModel for User {
     // handle change-password syncs
@@ -159,27 +182,31 @@ pub fn generate_auth(username: &String, password: &String, version: u16) -> TRes
     Ok(key_auth)
 }
 
-/// A function that tries authenticating a username/password against various
-/// versions, starting from latest to earliest until it runs out of versions or
-/// we get a match.
-fn do_login(turtl: &Turtl, username: &String, key: Key, auth: String) -> TResult<()> {
-    turtl.api.set_auth(username.clone(), auth.clone())?;
+/// Hand our auth to the API and return the id of the user it belongs to.
+fn post_auth(api: &Api, username: &String, auth: &String) -> TResult<String> {
+    api.set_auth(username.clone(), auth.clone())?;
     let opt = ApiReq::new().timeout(10);
-    let user_id: Value = turtl.api.post("/auth")?.call_opt(opt)?;
-
-    let mut user_guard_w = lockw!(turtl.user);
+    let user_id: Value = api.post("/auth")?.call_opt(opt)?;
     let id_err = TErr!(TError::BadValue(format!("auth was successful, but API returned strange id object: {:?}", user_id)));
-    let user_id = match user_id {
+    match user_id {
         Value::Number(x) => {
             match x.as_i64() {
-                Some(id) => id.to_string(),
-                None => return id_err,
+                Some(id) => Ok(id.to_string()),
+                None => id_err,
             }
         },
-        Value::String(x) => x,
-        _ => return id_err,
-    };
+        Value::String(x) => Ok(x),
+        _ => id_err,
+    }
+}
+
+/// A function that tries authenticating a username/password against various
+/// versions, starting from latest to earliest until it runs out of versions or
+/// we get a match.
+fn do_login(turtl: &Turtl, username: &String, key: Key, auth: String) -> TResult<()> {
+    let user_id = post_auth(&turtl.api, username, &auth)?;
     let url = format!("/users/{}", user_id);
+    let mut user_guard_w = lockw!(turtl.user);
     user_guard_w.id = Some(user_id);
     user_guard_w.do_login(key, auth);
     drop(user_guard_w);
@@ -241,6 +268,30 @@ impl User {
             })
     }
 
+    /// Authenticate as a user on the given API session without logging them
+    /// in here, handing back their id and key. Like `login()`, we fall back to
+    /// lesser auth versions if the current one is rejected.
+    pub fn authenticate(api: &Api, username: String, password: String, version: u16) -> TResult<(String, Key)> {
+        let username = username.to_lowercase();
+        let (key, auth) = generate_auth(&username, &password, version)?;
+        match post_auth(api, &username, &auth) {
+            Ok(user_id) => Ok((user_id, key)),
+            Err(e) => {
+                api.clear_auth();
+                match e.shed() {
+                    TError::Api(StatusCode::UNAUTHORIZED, y) => {
+                        if version == 0 {
+                            TErr!(TError::Api(StatusCode::UNAUTHORIZED, y))
+                        } else {
+                            User::authenticate(api, username, password, version - 1)
+                        }
+                    },
+                    e => Err(e),
+                }
+            },
+        }
+    }
+
     /// Log the user in given a token returned from get_login_token()
     pub fn login_token(turtl: &Turtl, token: String) -> TResult<()> {
         let token_encrypted = crypto::from_base64(&token)?;
@@ -494,6 +545,14 @@ impl User {
                         None => {}
                     }
                 }
+                // migrated files live on disk (as base64) instead of in the
+                // migration result, so load the file right before we save the
+                // note so we only ever hold one of them in memory.
+                let decoded_path: Option<String> = jedi::get_opt(&["file", "filedata", "path"], &noteval);
+                if let Some(path) = decoded_path.as_ref() {
+                    let data = fs::read_to_string(path)?;
+                    jedi::set(&["file", "filedata"], &mut noteval, &json!({"data": data}))?;
+                }
                 // NOTE: we use dispatch() instead of save_model() here because
                 // the note might have a `note.file.filedata` object and we want
                 // to save the imported file.
@@ -502,6 +561,11 @@ impl User {
                 sync.ty = SyncType::Note;
                 sync.data = Some(noteval);
                 sync_model::dispatch(turtl, sync)?;
+                if let Some(path) = decoded_path {
+                    if let Err(e) = fs::remove_file(&path) {
+                        warn!("User::post_join() -- problem removing migrated file {}: {}", path, e);
+                    }
+                }
             }
         }
 
@@ -554,12 +618,85 @@ impl User {
         Ok(())
     }
 
+    /// Replace the user's keypair with a freshly-generated one. Any pending
+    /// invites sealed to our old pubkey are re-sealed to the new one so they
+    /// can still be accepted. The keychain is protected by the user's master
+    /// key (not the keypair) so it's left alone.
+    ///
+    /// Invites that are both pubkey- and passphrase-protected can't be
+    /// re-sealed, so we refuse to rotate until they're accepted or deleted.
+    pub fn rotate_keypair(turtl: &Turtl) -> TResult<()> {
+        let keypair = {
+            let user_guard = lockr!(turtl.user);
+            match (user_guard.pubkey.as_ref(), user_guard.privkey.as_ref()) {
+                (Some(pk), Some(sk)) => Some((pk.clone(), sk.clone())),
+                _ => None,
+            }
+        };
+        let (old_pk, old_sk) = match keypair {
+            Some(x) => x,
+            // nothing to rotate, just make sure we have a keypair
+            None => return User::ensure_keypair(turtl),
+        };
+        let (pk, sk) = crypto::asym::keygen()?;
+
+        // re-seal our pending invites. we do this on copies so nothing changes
+        // if one of them fails to open.
+        let resealed = {
+            let profile_guard = lockr!(turtl.profile);
+            let mut resealed = Vec::new();
+            for invite in &profile_guard.invites {
+                if !invite.is_pubkey_protected { continue; }
+                let mut invite = invite.clone()?;
+                invite.reseal(&old_pk, &old_sk, &pk)?;
+                resealed.push(invite);
+            }
+            resealed
+        };
+        // push each re-sealed invite to the API so the new seal is what the
+        // server (and our other devices) see, then save it locally. the
+        // profile guard above is gone by now, and save_model() takes the db
+        // and profile locks itself.
+        for mut invite in resealed {
+            invite.edit(turtl, None)?;
+            sync_model::save_model(SyncAction::Edit, turtl, &mut invite, true)?;
+        }
+
+        // same dance as ensure_keypair(): set the new keys into turtl.user
+        // directly, then drop the guard and sync the change
+        let mut user_guard = lockw!(turtl.user);
+        let mut save_user = user_guard.clone()?;
+        user_guard.pubkey = Some(pk.clone());
+        user_guard.privkey = Some(sk.clone());
+        drop(user_guard);
+        save_user.pubkey = Some(pk);
+        save_user.privkey = Some(sk);
+        sync_model::save_model(SyncAction::Edit, turtl, &mut save_user, false)?;
+        info!("User::rotate_keypair() -- keypair rotated");
+        Ok(())
+    }
+
     /// Resend a user's confirmation email
     pub fn resend_confirmation(turtl: &Turtl) -> TResult<()> {
         turtl.api.post("/users/confirmation/resend")?.call::<bool>()?;
         Ok(())
     }
 
+    /// Grab the list of sessions that are currently active on this account
+    pub fn sessions(turtl: &Turtl) -> TResult<Vec<Session>> {
+        let user_id = turtl.user_id()?;
+        let url = format!("/users/{}/sessions", user_id);
+        turtl.api.get(url.as_str())?.call()
+    }
+
+    /// Invalidate one of this account's sessions, logging that device out
+    pub fn revoke_session(turtl: &Turtl, session_id: &String) -> TResult<()> {
+        let user_id = turtl.user_id()?;
+        let url = format!("/users/{}/sessions/{}", user_id, session_id);
+        turtl.api.delete(url.as_str())?.call::<Value>()?;
+        Ok(())
+    }
+
     /// Returns a string that can be saved and used to log back in later.
     ///
     /// WARNING: this token contains the user's master key!!
@@ -618,6 +755,50 @@ impl User {
         self.logged_in = true;
     }
 
+    /// Re-derive our key/auth from a password (trying each auth version,
+    /// newest first), returning the key if the auth matches the one we logged
+    /// in with.
+    fn check_password(&self, password: &String) -> TResult<Option<Key>> {
+        let auth = match self.auth.as_ref() {
+            Some(x) => x,
+            None => return TErr!(TError::MissingField(String::from("User.auth"))),
+        };
+        let username = self.username.to_lowercase();
+        let mut version = CURRENT_AUTH_VERSION;
+        loop {
+            let (key, try_auth) = generate_auth(&username, password, version)?;
+            if crypto::secure_compare(try_auth.as_bytes(), auth.as_bytes())? {
+                return Ok(Some(key));
+            }
+            if version == 0 { break; }
+            version -= 1;
+        }
+        Ok(None)
+    }
+
+    /// Re-derive our key from a password, making sure it matches the auth we
+    /// logged in with. Used for unlocking a locked session.
+    pub fn unlock_key(&self, password: &String) -> TResult<Key> {
+        match self.check_password(password)? {
+            Some(key) => Ok(key),
+            None => TErr!(TError::PermissionDenied(String::from("bad password"))),
+        }
+    }
+
+    /// Check a password against the one the user logged in with, without
+    /// touching the session. Handy for re-confirming the password before
+    /// doing anything destructive.
+    pub fn verify_password(&self, password: &String) -> TResult<bool> {
+        Ok(self.check_password(password)?.is_some())
+    }
+
+    /// Forget our key and any decrypted private data, but stay logged in
+    pub fn do_lock(&mut self) {
+        self.set_key(None);
+        self.settings = None;
+        self.privkey = None;
+    }
+
     /// Logout the user
     pub fn do_logout(&mut self) {
         self.set_key(None);
@@ -644,6 +825,44 @@ impl User {
         Ok(())
     }
 
+    /// Grab a copy of the current user's settings object, suitable for
+    /// importing later via `User::import_settings()`.
+    pub fn export_settings(turtl: &Turtl) -> TResult<Value> {
+        let user_guard = lockr!(turtl.user);
+        match user_guard.settings.as_ref() {
+            Some(settings) => Ok(jedi::to_val(settings)?),
+            None => Ok(json!({})),
+        }
+    }
+
+    /// Import a settings object (as exported by `User::export_settings()`)
+    /// into the current user. By default the imported values are merged over
+    /// the existing settings, but if `replace` is true we throw out whatever
+    /// settings we had first. Returns the resulting settings object.
+    pub fn import_settings(turtl: &Turtl, settings: Value, replace: bool) -> TResult<Value> {
+        let imported: HashMap<String, Value> = match settings {
+            Value::Object(_) => jedi::from_val(settings)?,
+            _ => return TErr!(TError::BadValue(String::from("settings must be an object"))),
+        };
+        let mut user_guard = lockw!(turtl.user);
+        if replace || user_guard.settings.is_none() {
+            user_guard.settings = Some(Default::default());
+        }
+        match user_guard.settings.as_mut() {
+            Some(ref mut existing) => {
+                for (key, val) in imported {
+                    existing.insert(key, val);
+                }
+            }
+            None => {
+                return TErr!(TError::MissingField(String::from("User.settings")));
+            }
+        }
+        sync_model::save_model(SyncAction::Edit, turtl, &mut (*user_guard), false)?;
+        let settings = jedi::to_val(&user_guard.settings)?;
+        Ok(settings)
+    }
+
     /// Given an email address, find a matching user (pubkey and all)
     pub fn find_by_email(turtl: &Turtl, email: &String) -> TResult<Option<User>> {
         let url = format!("/users/email/{}", email.to_lowercase());
@@ -656,6 +875,7 @@ mod tests {
     //! Tests for our high-level Crypto module interface.
 
     use super::*;
+    use ::models::invite::InviteRequest;
 
     #[test]
     pub fn authgen() {
@@ -664,4 +884,125 @@ mod tests {
         let (_key, auth) = generate_auth(&username, &password, 0).unwrap();
         assert_eq!(auth, "000601000c9af06607bbb78b0cab4e01f29a8d06da9a65e5698768b88ac4f4c04002c96fcfcb18a1644d5ba2546901452d0ebd6c162fe494997b52660d9d190ed525076523a1a576ea7596fdaec2e0f0606f3290bd6e5815f76889a4eada71fc20dad21703453928c74db36880cf6035922e3f7093ed1eef01a630750ebd8d64baaf34e325536011de40f3a72a4d95155ca32e851257d8bc7736d2d41c92213e93");
     }
+
+    #[test]
+    fn rotated_keypair_opens_resealed_invites() {
+        let (old_pk, old_sk) = crypto::asym::keygen().unwrap();
+        let space_key = Key::random().unwrap();
+        let req: InviteRequest = jedi::from_val(json!({
+            "space_id": "1234",
+            "to_user": "slappy@turtlapp.com",
+            "role": "member",
+            "title": "get a job",
+            "their_pubkey": old_pk,
+        })).unwrap();
+        let mut invite = Invite::from_invite_request(&String::from("6969"), &String::from("andrew@lyonbros.com"), &space_key, req).unwrap();
+
+        // rotate, then make sure the new keypair opens the invite and the old
+        // one doesn't
+        let (new_pk, new_sk) = crypto::asym::keygen().unwrap();
+        invite.reseal(&old_pk, &old_sk, &new_pk).unwrap();
+        let mut stale = invite.clone().unwrap();
+        assert!(stale.open(&old_pk, &old_sk, None).is_err());
+        invite.open(&new_pk, &new_sk, None).unwrap();
+        let message: Value = jedi::parse(&String::from_utf8(invite.message.clone().unwrap()).unwrap()).unwrap();
+        let opened_key: Key = jedi::get(&["space_key"], &message).unwrap();
+        assert_eq!(opened_key, space_key);
+    }
+
+    #[test]
+    fn rotates_keypair_and_reseals_invites() {
+        let _lock = ::turtl::tests::MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = ::turtl::tests::with_test(true);
+        *lockw!(turtl.connected) = true;
+        User::ensure_keypair(&turtl).unwrap();
+        let (old_pk, old_sk) = {
+            let user_guard = lockr!(turtl.user);
+            (user_guard.pubkey.clone().unwrap(), user_guard.privkey.clone().unwrap())
+        };
+        let space_key = Key::random().unwrap();
+        let req: InviteRequest = jedi::from_val(json!({
+            "space_id": "1234",
+            "to_user": "andrew@lyonbros.com",
+            "role": "member",
+            "title": "get a job",
+            "their_pubkey": old_pk,
+        })).unwrap();
+        let invite = Invite::from_invite_request(&String::from("6969"), &String::from("slippy@turtlapp.com"), &space_key, req).unwrap();
+        let invite_id = invite.id_or_else().unwrap();
+        lockw!(turtl.profile).invites.push(invite);
+
+        let handle = ::turtl::tests::mock_api("200 OK", String::from("{}"));
+        User::rotate_keypair(&turtl).unwrap();
+        let reqline = handle.join().unwrap();
+        assert!(reqline.starts_with(&format!("PUT /spaces/1234/invites/{} ", invite_id)));
+
+        let (new_pk, new_sk) = {
+            let user_guard = lockr!(turtl.user);
+            (user_guard.pubkey.clone().unwrap(), user_guard.privkey.clone().unwrap())
+        };
+        assert!(new_pk != old_pk);
+        let open_key = |invite: &mut Invite| -> Key {
+            invite.open(&new_pk, &new_sk, None).unwrap();
+            let message: Value = jedi::parse(&String::from_utf8(invite.message.clone().unwrap()).unwrap()).unwrap();
+            jedi::get(&["space_key"], &message).unwrap()
+        };
+        // both the in-memory and stored copies open with our new keypair
+        let mut mem_invite = lockr!(turtl.profile).invites[0].clone().unwrap();
+        assert_eq!(open_key(&mut mem_invite), space_key);
+        let mut db_invite: Invite = {
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().get("invites", &invite_id).unwrap().unwrap()
+        };
+        assert_eq!(open_key(&mut db_invite), space_key);
+    }
+
+    #[test]
+    fn verifies_passwords() {
+        let username = String::from("slippyslappy@turtlapp.com");
+        let password = String::from("get a job");
+        let (key, auth) = generate_auth(&username, &password, CURRENT_AUTH_VERSION).unwrap();
+        let mut user: User = jedi::from_val(json!({"id": "51", "username": username})).unwrap();
+        assert!(user.verify_password(&password).is_err());
+        user.do_login(key.clone(), auth.clone());
+
+        assert!(user.verify_password(&password).unwrap());
+        assert!(!user.verify_password(&String::from("get a life")).unwrap());
+        assert!(!user.verify_password(&String::from("")).unwrap());
+        // nothing about the session changed
+        assert!(user.logged_in);
+        assert_eq!(user.key(), Some(&key));
+        assert_eq!(user.auth, Some(auth));
+    }
+
+    #[test]
+    fn round_trips_settings() {
+        let turtl = ::turtl::tests::with_test(true);
+        assert_eq!(User::export_settings(&turtl).unwrap(), json!({}));
+        {
+            let mut user_guard = lockw!(turtl.user);
+            user_guard.set_setting(&turtl, "default_space", &String::from("1234")).unwrap();
+            user_guard.set_setting(&turtl, "theme", &String::from("dark")).unwrap();
+        }
+        let exported = User::export_settings(&turtl).unwrap();
+        assert_eq!(exported, json!({"default_space": "1234", "theme": "dark"}));
+
+        // simulate a fresh install and bring our settings over
+        lockw!(turtl.user).settings = None;
+        let imported = User::import_settings(&turtl, exported.clone(), false).unwrap();
+        assert_eq!(imported, exported);
+        assert_eq!(User::export_settings(&turtl).unwrap(), exported);
+        let stored: User = {
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().get("users", &String::from("51")).unwrap().unwrap()
+        };
+        assert!(stored.body.is_some());
+
+        // merging keeps existing values, replacing doesn't
+        let merged = User::import_settings(&turtl, json!({"theme": "light"}), false).unwrap();
+        assert_eq!(merged, json!({"default_space": "1234", "theme": "light"}));
+        let replaced = User::import_settings(&turtl, json!({"theme": "light"}), true).unwrap();
+        assert_eq!(replaced, json!({"theme": "light"}));
+        assert!(User::import_settings(&turtl, json!(["theme"]), false).is_err());
+    }
 }
@@ -11,7 +11,7 @@ use ::turtl::Turtl;
 use ::error::{TResult, TError};
 use ::jedi::{self, Value};
 use ::models::model::{self, Model};
-use ::models::keychain::Keychain;
+use ::models::keychain::{Keychain, KeychainEntry};
 use ::models::space::Space;
 use ::models::board::Board;
 use ::models::note::Note;
@@ -23,7 +23,7 @@ use ::models::storable::Storable;
 use ::sync::sync_model;
 use ::lib_permissions::Permission;
 use ::config;
-use ::crypto;
+use ::crypto::{self, Key};
 use ::messaging;
 
 /// A structure holding a collection of objects that represent's a user's
@@ -140,6 +140,101 @@ impl Profile {
         Ok(export)
     }
 
+    /// Build an export from a set of (encrypted) sync records straight from the
+    /// API, decrypting everything with the given user key. This lets us read
+    /// a profile that isn't loaded into Turtl (see `Turtl.copy_account()`).
+    /// Anything we can't find a key for is skipped. Files are not included.
+    pub fn export_from_sync(user_key: &Key, records: Vec<SyncRecord>) -> TResult<Export> {
+        info!("Profile::export_from_sync() -- running export on {} records", records.len());
+        let mut keychain: Vec<KeychainEntry> = Vec::new();
+        let mut spaces: Vec<Space> = Vec::new();
+        let mut boards: Vec<Board> = Vec::new();
+        let mut notes: Vec<Note> = Vec::new();
+        for rec in records {
+            let data = match rec.data {
+                Some(x) => x,
+                None => continue,
+            };
+            match rec.ty {
+                SyncType::Keychain => keychain.push(jedi::from_val(data)?),
+                SyncType::Space => spaces.push(jedi::from_val(data)?),
+                SyncType::Board => boards.push(jedi::from_val(data)?),
+                SyncType::Note => notes.push(jedi::from_val(data)?),
+                _ => {}
+            }
+        }
+
+        // item id -> key, for every key we've managed to decrypt so far
+        let mut keys: HashMap<String, Key> = HashMap::new();
+        for mut entry in keychain {
+            entry.set_key(Some(user_key.clone()));
+            match entry.deserialize() {
+                Ok(_) => {
+                    if let Some(k) = entry.k.take() {
+                        keys.insert(entry.item_id.clone(), k);
+                    }
+                }
+                Err(e) => warn!("Profile::export_from_sync() -- skipping keychain entry {:?}: {}", entry.id(), e),
+            }
+        }
+
+        /// Find each model's key (either directly or via its keyrefs) and
+        /// decrypt it. Models we decrypt add their keys to the pool, so spaces
+        /// have to go before boards, and boards before notes.
+        fn decrypt<T: Protected>(models: Vec<T>, keys: &mut HashMap<String, Key>) -> Vec<T> {
+            let mut res = Vec::with_capacity(models.len());
+            for mut model in models {
+                let id = match model.id() {
+                    Some(x) => x.clone(),
+                    None => continue,
+                };
+                let mut key = keys.get(&id).cloned();
+                if key.is_none() {
+                    let def = Vec::new();
+                    for keyref in model.get_keys().unwrap_or(&def) {
+                        let found = keys.get(&keyref.id)
+                            .and_then(|decrypting_key| protected::decrypt_key(decrypting_key, &keyref.k).ok());
+                        if found.is_some() {
+                            key = found;
+                            break;
+                        }
+                    }
+                }
+                let key = match key {
+                    Some(x) => x,
+                    None => {
+                        warn!("Profile::export_from_sync() -- skipping model {}/{}: key not found", id, model.model_type());
+                        continue;
+                    }
+                };
+                model.set_key(Some(key.clone()));
+                if let Err(e) = model.deserialize() {
+                    warn!("Profile::export_from_sync() -- skipping model {}/{}: {}", id, model.model_type(), e);
+                    continue;
+                }
+                keys.insert(id, key);
+                model.clear_body();
+                model.set_keys(Vec::new());
+                res.push(model);
+            }
+            res
+        }
+
+        let mut export = Export::default();
+        export.schema_version = 2;
+        export.spaces = decrypt(spaces, &mut keys)
+            .into_iter()
+            .map(|mut x| {
+                x.members = Vec::new();
+                x.invites = Vec::new();
+                x
+            })
+            .collect::<Vec<_>>();
+        export.boards = decrypt(boards, &mut keys);
+        export.notes = decrypt(notes, &mut keys);
+        Ok(export)
+    }
+
     /// Import a dump into the current Turtl profile.
     ///
     /// If an item is added (as opposed to editing an existing model), it's
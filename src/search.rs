@@ -22,8 +22,12 @@ pub struct Query {
     pub text: Option<String>,
     #[serde(default)]
     pub notes: Vec<String>,
+    /// The space to search in. If blank, we search all of `spaces` instead.
+    #[serde(default)]
     pub space_id: String,
     #[serde(default)]
+    pub spaces: Vec<String>,
+    #[serde(default)]
     pub boards: Vec<String>,
     #[serde(default)]
     pub tags: Vec<String>,
@@ -58,7 +62,7 @@ impl Search {
     /// Create a new Search object
     pub fn new() -> TResult<Search> {
         let idx = Clouseau::new()?;
-        idx.conn.execute("CREATE TABLE IF NOT EXISTS notes (id VARCHAR(64) PRIMARY KEY, space_id VARCHAR(96), board_id VARCHAR(96), has_file BOOL, created INTEGER, mod INTEGER, type VARCHAR(32), color INTEGER, url VARCHAR(256))", NO_PARAMS)?;
+        idx.conn.execute("CREATE TABLE IF NOT EXISTS notes (id VARCHAR(64) PRIMARY KEY, space_id VARCHAR(96), board_id VARCHAR(96), has_file BOOL, created INTEGER, mod INTEGER, type VARCHAR(32), color INTEGER, url VARCHAR(256), sort_order INTEGER)", NO_PARAMS)?;
         idx.conn.execute("CREATE TABLE IF NOT EXISTS notes_tags (id ROWID, note_id VARCHAR(64), tag VARCHAR(128))", NO_PARAMS)?;
         Ok(Search {
             idx: idx,
@@ -84,8 +88,8 @@ impl Search {
         let type_ = get_field!(note, type_, String::from("text"));
         let color = get_field!(note, color, 0);
         self.idx.conn.execute(
-            "INSERT INTO notes (id, space_id, board_id, has_file, created, mod, type, color, url) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            params![id, space_id, board_id, has_file, id_mod, mod_, type_, color, note.url]
+            "INSERT INTO notes (id, space_id, board_id, has_file, created, mod, type, color, url, sort_order) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![id, space_id, board_id, has_file, id_mod, mod_, type_, color, note.url, note.order]
         )?;
 
         let tags = get_field!(note, tags, Vec::new());
@@ -123,6 +127,16 @@ impl Search {
         self.index_note(note)
     }
 
+    /// Get the size of the search index, in bytes
+    pub fn size(&self) -> TResult<i64> {
+        Ok(self.idx.size()?)
+    }
+
+    /// Tidy up the full-text index after a lot of indexing/unindexing
+    pub fn optimize(&self) -> TResult<()> {
+        Ok(self.idx.optimize()?)
+    }
+
     /// Search for notes. Returns the note IDs only. Loading them from the db
     /// and decrypting are up to you...OR YOUR MOM.
     ///
@@ -136,10 +150,16 @@ impl Search {
         let mut exclude_queries: Vec<String> = Vec::new();
         let mut qry_vals: Vec<SearchVal> = Vec::new();
 
-        let mut space_qry: Vec<&str> = Vec::with_capacity(1);
-        space_qry.push("SELECT id FROM notes WHERE space_id = ?");
-        qry_vals.push(SearchVal::String(query.space_id.clone()));
-        queries.push(space_qry.as_slice().join(""));
+        let space_ids = if query.space_id != "" {
+            vec![query.space_id.clone()]
+        } else {
+            query.spaces.clone()
+        };
+        let space_placeholders = vec!["?"; space_ids.len()];
+        queries.push(format!("SELECT id FROM notes WHERE space_id IN ({})", space_placeholders.join(",")));
+        for space_id in space_ids {
+            qry_vals.push(SearchVal::String(space_id));
+        }
 
         // this one is kind of weird. we basically do
         //   SELECT id FROM notes WHERE id IN (id1, id2)
@@ -263,7 +283,12 @@ impl Search {
         if page < 1 { page = 1; }
         if per_page < 1 { per_page = 50; }
 
-        let orderby = format!(" ORDER BY {} {}", sort, sort_dir);
+        // notes that were never ordered go last, no matter the direction
+        let orderby = if sort == "order" {
+            format!(" ORDER BY sort_order IS NULL, sort_order {}", sort_dir)
+        } else {
+            format!(" ORDER BY {} {}", sort, sort_dir)
+        };
         let pagination = format!(" LIMIT {} OFFSET {}", per_page, (page - 1) * per_page);
         let final_query = (filter_query.clone() + &orderby) + &pagination;
         let total_query = format!("SELECT COUNT(search.id) AS total FROM ({}) AS search", filter_query);
@@ -286,6 +311,12 @@ impl Search {
         Ok((note_ids, total))
     }
 
+    /// Count how many notes we have indexed for a space
+    pub fn count_notes(&self, space_id: &String) -> TResult<i64> {
+        let count = self.idx.conn.query_row("SELECT COUNT(*) FROM notes WHERE space_id = ?", &[space_id], |row| row.get(0))?;
+        Ok(count)
+    }
+
     /// Given a query object, find the tags that match it. This disregards page
     /// and per_page, since we want a list of all tags that match that result.
     pub fn find_tags(&self, query: &Query) -> TResult<Vec<(String, i32)>> {
@@ -3,18 +3,21 @@
 
 use ::std::sync::{Arc, RwLock};
 use ::std::mem;
+use ::std::fs;
+use ::std::path::Path;
+use ::std::time::{SystemTime, UNIX_EPOCH};
 
 use ::crypto;
-use ::rusqlite::{self, Connection};
+use ::rusqlite::{self, Connection, DatabaseName};
 use ::jedi::{self, Value};
-use ::dumpy::Dumpy;
+use ::dumpy::{Dumpy, DError};
 use ::config;
 
 use ::models::model::{self};
 use ::models::protected::Protected;
 use ::models::storable::Storable;
 
-use ::error::TResult;
+use ::error::{TResult, TError};
 
 /// Given a db filename, return the foll path we'll use for the db file
 pub fn db_location(db_name: &String) -> TResult<String> {
@@ -46,6 +49,47 @@ pub fn setup_client_id(storage: Arc<RwLock<Storage>>) -> TResult<()> {
     model::set_client_id(id)
 }
 
+/// Check if an error from SQLite means the db file itself is busted
+fn is_corrupt(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(ref e, _) => {
+            e.code == rusqlite::ErrorCode::DatabaseCorrupt || e.code == rusqlite::ErrorCode::NotADatabase
+        }
+        _ => false,
+    }
+}
+
+/// Open the db at the given location. If it turns out to be corrupt, we move
+/// the bad file (and its WAL/shm files) out of the way instead of deleting it
+/// and start over with a fresh db. Returns the new db along with where the
+/// corrupt file went, if we had to move it.
+///
+/// Note that a fresh db has no sync id, so the next sync pulls the full profile
+/// down from the server again. Anything that hadn't synced out is left behind
+/// in the corrupt file.
+pub fn open_or_recover(location: &String, schema: Value) -> TResult<(Storage, Option<String>)> {
+    match Storage::new(location, schema.clone()) {
+        Ok(x) => return Ok((x, None)),
+        Err(e) => {
+            if e.code() != "db_corrupt" || location == ":memory:" { return Err(e); }
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    let moved_to = format!("{}.corrupt-{}", location, now);
+    warn!("storage::open_or_recover() -- db {} is corrupt, moving it to {} and starting over", location, moved_to);
+    fs::rename(location, &moved_to)?;
+    for suffix in &["-wal", "-shm"] {
+        let extra = format!("{}{}", location, suffix);
+        if Path::new(&extra).exists() {
+            fs::rename(&extra, format!("{}{}", moved_to, suffix))?;
+        }
+    }
+    let storage = Storage::new(location, schema)?;
+    Ok((storage, Some(moved_to)))
+}
+
 /// This structure holds state for persisting (encrypted) data to disk.
 pub struct Storage {
     pub conn: Connection,
@@ -53,7 +97,8 @@ pub struct Storage {
 }
 
 impl Storage {
-    /// Make a Storage lol
+    /// Make a Storage lol. If the file at `location` isn't a usable db, you get
+    /// a `TError::DbCorrupt` (see `open_or_recover()`).
     pub fn new(location: &String, schema: Value) -> TResult<Storage> {
         // open in multi-threaded mode: we can have the same db open in multiple
         // threads as long as each thread has its own connection:
@@ -63,15 +108,25 @@ impl Storage {
             rusqlite::OpenFlags::SQLITE_OPEN_CREATE |
             rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX |
             rusqlite::OpenFlags::SQLITE_OPEN_URI;
-        let conn = if location == ":memory:" {
+        let opened = if location == ":memory:" {
             Connection::open_in_memory_with_flags(flags)
         } else {
             Connection::open_with_flags(location, flags)
-        }?;
-
-        // set up dumpy
+        };
+        let conn = match opened {
+            Ok(x) => x,
+            Err(ref e) if is_corrupt(e) => return TErr!(TError::DbCorrupt(location.clone())),
+            Err(e) => return Err(From::from(e)),
+        };
+
+        // set up dumpy. this is our first real look at the file, so it's
+        // where we'll find out if it's garbage.
         let dumpy = Dumpy::new(schema);
-        dumpy.init(&conn)?;
+        match dumpy.init(&conn) {
+            Ok(_) => {}
+            Err(DError::SqlError(ref e)) if is_corrupt(e) => return TErr!(TError::DbCorrupt(location.clone())),
+            Err(e) => return Err(From::from(e)),
+        }
 
         Ok(Storage {
             conn: conn,
@@ -132,6 +187,13 @@ impl Storage {
         self.all_limit(table, None)
     }
 
+    /// Get (table, id, updated_at) for the most recently updated objects across
+    /// a set of "tables", newest first
+    pub fn recent(&self, tables: &[&str], limit: Option<i32>) -> TResult<Vec<(String, String, i64)>> {
+        let tables = tables.iter().map(|x| String::from(*x)).collect::<Vec<_>>();
+        Ok(self.dumpy.recent(&self.conn, &tables, limit)?)
+    }
+
     /// Find values by index/value in a "table"
     pub fn find<T>(&self, table: &str, index: &str, vals: &Vec<String>) -> TResult<Vec<T>>
         where T: Protected + Storable
@@ -160,6 +222,26 @@ impl Storage {
         Ok(self.dumpy.kv_delete(&self.conn, key)?)
     }
 
+    /// Make a copy of our db at the given path using SQLite's online backup
+    /// API, so we get a consistent snapshot even while the db is in use. The
+    /// copy holds the same (encrypted) data the db does.
+    pub fn backup_to(&self, path: &str) -> TResult<()> {
+        Ok(self.conn.backup(DatabaseName::Main, path, None)?)
+    }
+
+    /// Get the size of the db, in bytes
+    pub fn size(&self) -> TResult<i64> {
+        Ok(self.dumpy.size(&self.conn)?)
+    }
+
+    /// Compact the db: rebuild it to reclaim space from deleted objects, then
+    /// flush (and truncate) the WAL.
+    pub fn optimize(&self) -> TResult<()> {
+        self.dumpy.vacuum(&self.conn)?;
+        self.dumpy.checkpoint(&self.conn)?;
+        Ok(())
+    }
+
     /// Close the db connection
     pub fn close(&mut self) -> TResult<()> {
         let mut conn = Connection::open_in_memory()?;
@@ -261,6 +343,66 @@ mod tests {
         assert!(sheeb.is_none());
     }
 
+    #[test]
+    fn backs_up() {
+        let storage = pretest();
+        let mut model = Shiba::new_with_id().unwrap();
+        let key = model.generate_key().unwrap().clone();
+        model.name = Some(String::from("Kofi"));
+        model.serialize().unwrap();
+        storage.save(&model).unwrap();
+        storage.kv_set("get a job", &String::from("no way")).unwrap();
+
+        let mut path = ::std::env::temp_dir();
+        path.push(format!("turtl-backup-test-{}.sqlite", crypto::random_hash().unwrap()));
+        let path = String::from(path.to_str().unwrap());
+        storage.backup_to(&path).unwrap();
+        // the original is still good to go
+        storage.kv_set("get a job", &String::from("ok fine")).unwrap();
+
+        let backup = Storage::new(&path, jedi::parse(&String::from("{}")).unwrap()).unwrap();
+        let mut shiba: Shiba = backup.get("shibas", model.id().unwrap()).unwrap().unwrap();
+        shiba.set_key(Some(key));
+        shiba.deserialize().unwrap();
+        assert_eq!(shiba.name, Some(String::from("Kofi")));
+        assert_eq!(backup.kv_get("get a job").unwrap(), Some(String::from("no way")));
+        drop(backup);
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recovers_corrupt_dbs() {
+        let mut path = ::std::env::temp_dir();
+        path.push(format!("turtl-corrupt-test-{}.sqlite", crypto::random_hash().unwrap()));
+        let path = String::from(path.to_str().unwrap());
+        let garbage = "this is not a database. it is a grocery list: eggs, milk, huge ships. ".repeat(100);
+        fs::write(&path, &garbage).unwrap();
+
+        let schema = jedi::parse(&String::from("{}")).unwrap();
+        match Storage::new(&path, jedi::parse(&String::from("{}")).unwrap()) {
+            Err(e) => assert_eq!(e.code(), "db_corrupt"),
+            Ok(_) => panic!("opened a corrupt db"),
+        }
+
+        let (storage, moved_to) = open_or_recover(&path, schema).unwrap();
+        let moved_to = moved_to.unwrap();
+        assert!(moved_to.starts_with(&format!("{}.corrupt-", path)));
+        // the bad file is kept around, untouched
+        assert_eq!(fs::read_to_string(&moved_to).unwrap(), garbage);
+        // and we have a working db in its place
+        storage.kv_set("get a job", &String::from("no way")).unwrap();
+        assert_eq!(storage.kv_get("get a job").unwrap(), Some(String::from("no way")));
+        drop(storage);
+
+        // a healthy db opens without any fuss
+        let (storage, moved_to2) = open_or_recover(&path, jedi::parse(&String::from("{}")).unwrap()).unwrap();
+        assert_eq!(moved_to2, None);
+        assert_eq!(storage.kv_get("get a job").unwrap(), Some(String::from("no way")));
+        drop(storage);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&moved_to).unwrap();
+    }
+
     #[test]
     fn kv_stuff() {
         // ^kv stuff? were the midterms hard?
@@ -6,7 +6,7 @@ use ::sync::{SyncConfig, Syncer};
 use ::sync::sync_model::{SyncModel, MemorySaver};
 use ::storage::Storage;
 use ::rusqlite::NO_PARAMS;
-use ::api::{Api, ApiReq};
+use ::api::{self, Api, ApiReq};
 use ::messaging;
 use ::models;
 use ::models::protected::{Protected, Keyfinder};
@@ -84,6 +84,14 @@ pub fn ignore_syncs_maybe(turtl: &Turtl, val_with_sync_ids: &Value, errtype: &st
     }
 }
 
+/// Grab a user's entire profile (as a set of sync records) from the API. The
+/// records are handed back as-is (encrypted), so it's up to the caller to
+/// decide what to do with them.
+pub fn fetch_full_profile(api: &Api) -> TResult<Vec<SyncRecord>> {
+    let syncdata: SyncResponse = api.get("/sync/full")?.call_opt(ApiReq::new().timeout(120))?;
+    Ok(syncdata.records)
+}
+
 /// Holds the state for data going from API -> turtl (incoming sync data),
 /// including tracking which sync item's we've seen and which we haven't.
 pub struct SyncIncoming {
@@ -369,7 +377,7 @@ impl Syncer for SyncIncoming {
         let sync_id = with_db!{ db, self.db, db.kv_get("sync_id") }?;
         let skip_init = {
             let config_guard = lockr!(self.config);
-            config_guard.skip_api_init
+            config_guard.skip_api_init || api::is_offline()
         };
         let res = if !skip_init {
             match sync_id {
@@ -23,19 +23,41 @@ pub mod sync_model;
 
 use ::std::thread;
 use ::std::sync::{Arc, RwLock, Mutex, mpsc};
+use ::std::collections::VecDeque;
+use ::std::time::{SystemTime, UNIX_EPOCH};
 use ::config;
 use ::sync::outgoing::SyncOutgoing;
 use ::sync::incoming::SyncIncoming;
 use ::sync::files::outgoing::FileSyncOutgoing;
 use ::sync::files::incoming::FileSyncIncoming;
-use ::models::sync_record::SyncRecord;
+use ::models::sync_record::{SyncRecord, SyncType};
 use ::util;
 use ::error::{TResult, TError};
 use ::storage::Storage;
-use ::api::Api;
+use ::api::{self, Api};
 use ::messaging;
 use ::crossbeam::sync::MsQueue;
 
+/// How many sync errors we hang onto (see `SyncConfig.errors`)
+const MAX_SYNC_ERRORS: usize = 50;
+
+/// A record of something going wrong in the sync system, kept around so the
+/// UI (or support) can see what's failing without digging through logs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncErrorEntry {
+    /// When it happened (unix time, in seconds)
+    pub time: u64,
+    /// Which syncer hit the error ("outgoing", "files:incoming", etc)
+    pub syncer: String,
+    /// The type of item that failed to sync (if the error was item-specific)
+    #[serde(rename = "type")]
+    pub ty: Option<SyncType>,
+    /// The id of the item that failed to sync (if the error was item-specific)
+    pub item_id: Option<String>,
+    /// What went wrong
+    pub msg: String,
+}
+
 /// This holds the configuration for the sync system (whether it's enabled, the
 /// current user id/api endpoint, and any other information we need to make
 /// informed decisions about syncing).
@@ -78,6 +100,9 @@ pub struct SyncConfig {
     /// SyncIncoming thread (since the sync threads are all generalized). Deal
     /// with it.
     pub incoming_sync: Arc<MsQueue<SyncRecord>>,
+    /// The most recent errors our sync threads have run into, oldest first
+    /// (capped at `MAX_SYNC_ERRORS`). Cleared when the user logs out.
+    pub errors: VecDeque<SyncErrorEntry>,
 }
 
 impl SyncConfig {
@@ -90,7 +115,16 @@ impl SyncConfig {
             skip_api_init: false,
             run_version: 0,
             incoming_sync: Arc::new(MsQueue::new()),
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Remember a sync error, dropping the oldest one if we're full up
+    pub fn track_error(&mut self, entry: SyncErrorEntry) {
+        while self.errors.len() >= MAX_SYNC_ERRORS {
+            self.errors.pop_front();
         }
+        self.errors.push_back(entry);
     }
 }
 
@@ -160,7 +194,7 @@ pub trait Syncer {
         let guard = lockr!(local_config);
         let run_version = self.get_run_version();
         let run_mismatch = guard.run_version != run_version;
-        guard.enabled.clone() && config_enabled && !run_mismatch
+        guard.enabled.clone() && config_enabled && !run_mismatch && !api::is_offline()
     }
 
     /// Get our sync_id key (for our k/v store)
@@ -175,6 +209,22 @@ pub trait Syncer {
         Ok(format!("{}:{}", user_id, api_endpoint))
     }
 
+    /// Add an error to the sync config's error history
+    fn track_error(&self, ty: Option<SyncType>, item_id: Option<String>, msg: String) {
+        let entry = SyncErrorEntry {
+            time: SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or(0),
+            syncer: String::from(self.get_name()),
+            ty: ty,
+            item_id: item_id,
+            msg: msg,
+        };
+        let local_config = self.get_config();
+        let mut guard = lockw!(local_config);
+        guard.track_error(entry);
+    }
+
     /// Runs our syncer, with some quick checks on run status.
     fn runner(&mut self, init_tx: mpsc::Sender<TResult<()>>) {
         // pull our run version from the config
@@ -213,9 +263,15 @@ pub trait Syncer {
         info!("sync::runner() -- {} main loop", self.get_name());
         while !self.should_quit() {
             let delay = self.get_delay();
-            if self.is_enabled() {
+            // if the api asked us to back off, sit this run out (we don't sleep
+            // for the whole backoff here so we can still quit in a timely way)
+            let rate_limited = api::rate_limit_delay().is_some();
+            if self.is_enabled() && !rate_limited {
                 match self.run_sync() {
-                    Err(e) => error!("sync::runner() -- {}: main loop: {}", self.get_name(), e),
+                    Err(e) => {
+                        error!("sync::runner() -- {}: main loop: {}", self.get_name(), e);
+                        self.track_error(None, None, format!("{}", e));
+                    }
                     _ => (),
                 }
                 util::sleep(delay);
@@ -86,6 +86,7 @@ impl SyncOutgoing {
                 None => String::from("<blank error>"),
             };
             warn!("SyncOutgoing.handle_sync_failures() -- failwhale: {:?}/{:?}: {}", failure.ty, failure.action, errmsg);
+            self.track_error(Some(failure.ty.clone()), Some(failure.item_id.clone()), errmsg);
             with_db!{ db, self.db,
                 SyncRecord::handle_failed_sync(db, failure)?;
             }
@@ -174,6 +175,8 @@ mod tests {
     use super::*;
     use ::std::sync::{Arc, RwLock, Mutex};
     use ::models::sync_record::SyncRecord;
+    use ::sync::MAX_SYNC_ERRORS;
+    use ::turtl::tests::{mock_api, MOCK_API_LOCK};
     use ::jedi;
     use ::schema;
 
@@ -205,6 +208,47 @@ mod tests {
         assert_eq!(outgoing.len(), 2);
     }
 
+    #[test]
+    fn tracks_sync_errors() {
+        ::init(String::from("{}")).unwrap();
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let sync_config = Arc::new(RwLock::new(SyncConfig::new()));
+        let api = Arc::new(Api::new());
+        let db = Storage::new(&String::from(":memory:"), schema::get_schema()).unwrap();
+        let db = Arc::new(Mutex::new(Some(db)));
+
+        let sync: SyncRecord = jedi::from_val(json!({"id": "1", "action": "add", "item_id": "69", "user_id": 12, "type": "note"})).unwrap();
+        {
+            let mut db_guard = lock!(db);
+            db_guard.as_mut().unwrap().save(&sync).unwrap();
+        }
+
+        let handle = mock_api("200 OK", String::from(r#"{"success":[],"failures":[{"id":"1","action":"add","item_id":"69","user_id":12,"type":"note","error":{"code":400,"msg":"that note is no good"}}]}"#));
+        let mut sync_outgoing = SyncOutgoing::new(sync_config.clone(), api, db.clone());
+        sync_outgoing.run_sync().unwrap();
+        assert!(handle.join().unwrap().starts_with("POST /sync "));
+
+        let errors = lockr!(sync_config).errors.iter().cloned().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].syncer, "outgoing");
+        assert_eq!(errors[0].ty, Some(SyncType::Note));
+        assert_eq!(errors[0].item_id, Some(String::from("69")));
+        assert_eq!(errors[0].msg, "that note is no good");
+        assert!(errors[0].time > 0);
+
+        // the history doesn't grow forever
+        {
+            let mut guard = lockw!(sync_config);
+            for _ in 0..100 {
+                let mut entry = errors[0].clone();
+                entry.msg = String::from("again");
+                guard.track_error(entry);
+            }
+            assert_eq!(guard.errors.len(), MAX_SYNC_ERRORS);
+            assert!(guard.errors.iter().all(|x| x.msg == "again"));
+        }
+    }
+
     #[test]
     fn deserializes_sync_response() {
         let typical_mac_user = String::from(r#"{
@@ -3,8 +3,11 @@
 //! around to various pieces of the app running in the main thread.
 
 use ::std::sync::{Arc, RwLock, Mutex};
+use ::std::time::{Duration, Instant};
 use ::std::ops::Drop;
 use ::std::fs;
+use ::std::path::Path;
+use ::std::thread;
 use ::regex::Regex;
 use ::num_cpus;
 use ::jedi::{self, Value};
@@ -15,7 +18,7 @@ use ::util;
 use ::util::thredder::Thredder;
 use ::storage::{self, Storage};
 use ::api::Api;
-use ::profile::Profile;
+use ::profile::{Profile, ImportMode, ImportResult};
 use ::models::protected::{self, Keyfinder, Protected};
 use ::models::model::Model;
 use ::models::user::{self, User};
@@ -27,13 +30,19 @@ use ::models::note::Note;
 use ::models::file::FileData;
 use ::models::sync_record::{SyncRecord, SyncAction};
 use ::messaging::{self, Messenger, Response};
-use ::sync::{self, SyncConfig, SyncState};
+use ::sync::{self, SyncConfig, SyncState, SyncErrorEntry};
 use ::sync::sync_model::MemorySaver;
-use ::search::Search;
+use ::search::{Search, Query};
 use ::schema;
 use ::migrate::{self, MigrateResult};
 use ::std::collections::HashMap;
 
+/// How many notes a live search sends to the UI at a time
+const LIVE_SEARCH_BATCH: usize = 20;
+
+/// How many notes background indexing decrypts/indexes between progress events
+const INDEX_BATCH: usize = 100;
+
 pub fn data_folder() -> TResult<String> {
     let integration = config::get::<String>(&["integration_tests", "data_folder"])?;
     if cfg!(test) {
@@ -84,7 +93,7 @@ pub struct Turtl {
     pub api: Arc<Api>,
     /// Holds our heroic search object, used to index/find our notes once the
     /// profile is loaded.
-    pub search: Mutex<Option<Search>>,
+    pub search: Arc<Mutex<Option<Search>>>,
     /// Sync system configuration (shared state with the sync system).
     pub sync_config: Arc<RwLock<SyncConfig>>,
     /// Holds our sync state data
@@ -95,12 +104,24 @@ pub struct Turtl {
     pub incoming_sync_lock: Mutex<()>,
     /// Whether or not we're connected to the API
     pub connected: RwLock<bool>,
+    /// Whether the session is locked (keys and decrypted profile cleared from
+    /// memory until the user unlocks with their password)
+    pub locked: RwLock<bool>,
+    /// When we last heard from the UI, used for auto-locking idle sessions
+    last_activity: Mutex<Instant>,
+    /// The id of the newest live search. Running live searches watch this and
+    /// give up once a newer one comes along (see `Turtl.live_search()`).
+    live_search: RwLock<u64>,
+    /// Bumped every time we start building a new search index (or close the
+    /// old one), so background indexing knows when to give up (see
+    /// `Turtl.index_notes_background()`).
+    index_run: Arc<RwLock<u64>>,
 }
 
 impl Turtl {
     /// Create a new Turtl app
     pub fn new() -> TResult<Turtl> {
-        let num_workers = num_cpus::get() - 1;
+        let num_workers = Turtl::num_workers();
 
         let api = Arc::new(Api::new());
         let kv = Arc::new(RwLock::new(Turtl::open_kv()?));
@@ -114,18 +135,31 @@ impl Turtl {
             profile: RwLock::new(Profile::new()),
             api: api,
             msg: Messenger::new(),
-            work: Thredder::new("work", num_workers as u32),
+            work: Thredder::new("work", num_workers),
             kv: kv,
             db: Arc::new(Mutex::new(None)),
-            search: Mutex::new(None),
+            search: Arc::new(Mutex::new(None)),
             sync_config: Arc::new(RwLock::new(SyncConfig::new())),
             sync_state: Arc::new(RwLock::new(None)),
             connected: RwLock::new(false),
             incoming_sync_lock: Mutex::new(()),
+            locked: RwLock::new(false),
+            last_activity: Mutex::new(Instant::now()),
+            live_search: RwLock::new(0),
+            index_run: Arc::new(RwLock::new(0)),
         };
         Ok(turtl)
     }
 
+    /// Figure out how many worker threads we want. Uses `work.threads` from
+    /// the config if it's set, otherwise one less than the number of cpus.
+    fn num_workers() -> u32 {
+        match config::get::<Option<u32>>(&["work", "threads"]) {
+            Ok(Some(x)) if x > 0 => x,
+            _ => (num_cpus::get() as u32).saturating_sub(1),
+        }
+    }
+
     /// Create/open a new KV store connection
     pub fn open_kv() -> TResult<Storage> {
         let kv_location = storage::db_location(&String::from("turtl-kv"))?;
@@ -157,18 +191,11 @@ impl Turtl {
     /// Send an error response to a remote request
     pub fn msg_error(&self, mid: &String, err: &TError) -> TResult<()> {
         let reqres_append_mid: bool = config::get(&["messaging", "reqres_append_mid"])?;
-        let mut errval = util::json_or_string(format!("{}", err));
-        let wrapped = match jedi::get_opt::<bool>(&["wrapped"], &errval) {
-            Some(x) => x,
-            None => false,
-        };
         let wrap_errors: bool = match config::get(&["wrap_errors"]) {
             Ok(x) => x,
             Err(_) => false,
         };
-        if !wrap_errors && wrapped {
-            errval = jedi::get(&["err"], &errval)?;
-        }
+        let errval = err.to_response(wrap_errors);
         if reqres_append_mid {
             let res = Response::new(1, errval);
             let msg = jedi::stringify(&res)?;
@@ -203,6 +230,8 @@ impl Turtl {
 
         let mut sync_config_guard = lockw!(self.sync_config);
         sync_config_guard.user_id = None;
+        // the next user doesn't need to see our sync problems
+        sync_config_guard.errors.clear();
         drop(sync_config_guard);
     }
 
@@ -257,13 +286,18 @@ impl Turtl {
         self.do_join(username, password, None)
     }
 
-    /// Create a new user account by migrating from a v0.6 server.
-    pub fn join_migrate(&self, old_username: String, old_password: String, new_username: String, new_password: String) -> TResult<()> {
-        let login = migrate::check_login(&old_username, &old_password)?;
+    /// Create a new user account by migrating from a v0.6 server. If no server
+    /// is given, we use the one in the config (`api.v6.endpoint`).
+    pub fn join_migrate(&self, old_username: String, old_password: String, new_username: String, new_password: String, old_endpoint: Option<String>) -> TResult<()> {
+        let old_endpoint = match old_endpoint {
+            Some(x) => x,
+            None => migrate::default_endpoint()?,
+        };
+        let login = migrate::check_login(&old_endpoint, &old_username, &old_password)?;
         if login.is_none() {
             return TErr!(TError::PermissionDenied(String::from("login on old server failed")));
         }
-        let migrate_data = migrate::migrate(login.expect("turtl.join_migrate() -- login is None"), |ev, args| {
+        let migrate_data = migrate::migrate(&old_endpoint, login.expect("turtl.join_migrate() -- login is None"), |ev, args| {
             match messaging::ui_event("migration-event", &json!({"event": ev, "args": args})) {
                 Ok(_) => {}
                 Err(e) => {
@@ -281,6 +315,10 @@ impl Turtl {
             profile_guard.wipe();
             *profile_guard = Profile::new();
         }
+        {
+            let mut locked_guard = lockw!(self.locked);
+            *locked_guard = false;
+        }
         self.sync_shutdown(false)?;
         self.close_user_db()?;
         self.close_search();
@@ -298,6 +336,25 @@ impl Turtl {
         Ok(())
     }
 
+    /// Copy all the spaces, boards, and notes from another account into the
+    /// current one. We authenticate as the other account on its own API
+    /// session, pull down its full profile from the sync system and decrypt it
+    /// with that account's key, and then import it here, so everything gets
+    /// fresh ids and keys. Anything that already made it over in an earlier
+    /// copy is skipped.
+    ///
+    /// NOTE: file attachments are not copied (we never download them for the
+    /// source account).
+    pub fn copy_account(&self, username: String, password: String) -> TResult<ImportResult> {
+        self.assert_connected()?;
+        let api = Api::new();
+        let (user_id, key) = User::authenticate(&api, username, password, user::CURRENT_AUTH_VERSION)?;
+        info!("turtl.copy_account() -- copying profile from user {}", user_id);
+        let records = sync::incoming::fetch_full_profile(&api)?;
+        let export = Profile::export_from_sync(&key, records)?;
+        Profile::import(self, ImportMode::Restore, export)
+    }
+
     /// Change the current user's username/password
     pub fn change_user_password(&self, current_username: String, current_password: String, new_username: String, new_password: String) -> TResult<()> {
         self.assert_connected()?;
@@ -381,8 +438,9 @@ impl Turtl {
 
         self.load_profile()?;
         messaging::ui_event("profile:loaded", &())?;
-        self.index_notes()?;
-        messaging::ui_event("profile:indexed", &())?;
+        // indexing can take a while on big profiles, so do it in the background
+        // and let the UI get going with a partial index
+        self.index_notes_background()?;
 
         // wipe our incoming sync queue. we're about to synchronize all our
         // in-mem state with what's in the DB, so we don't really need to run
@@ -452,6 +510,12 @@ impl Turtl {
         }
     }
 
+    /// Grab the sync system's recent error history, oldest first
+    pub fn sync_errors(&self) -> Vec<SyncErrorEntry> {
+        let sync_config_guard = lockr!(self.sync_config);
+        sync_config_guard.errors.iter().cloned().collect()
+    }
+
     /// Returns whether or not syncing has been initialized (ie, sync_start has
     /// been called). Basically just tests for the presence of sync_state.
     pub fn sync_ready(&self) -> bool {
@@ -464,7 +528,11 @@ impl Turtl {
         let user_id = self.user_id()?;
         let db_location = self.get_user_db_location(&user_id)?;
         let dumpy_schema = schema::get_schema();
-        Storage::new(&db_location, dumpy_schema)
+        let (db, moved_to) = storage::open_or_recover(&db_location, dumpy_schema)?;
+        if let Some(moved_to) = moved_to {
+            messaging::ui_event("app:db-corrupt", &json!({"db": db_location, "moved_to": moved_to}))?;
+        }
+        Ok(db)
     }
 
     /// Close the per-user database.
@@ -480,9 +548,18 @@ impl Turtl {
     /// Shut down the search system
     pub fn close_search(&self) {
         let mut search_guard = lock!(self.search);
+        self.next_index_run();
         *search_guard = None;
     }
 
+    /// Start a new indexing run, which stops any background indexing that's
+    /// still going
+    fn next_index_run(&self) -> u64 {
+        let mut run_guard = lockw!(self.index_run);
+        *run_guard += 1;
+        *run_guard
+    }
+
     /// Get the physical location of the per-user database file we will use for
     /// the current logged-in user.
     pub fn get_user_db_location(&self, user_id: &String) -> TResult<String> {
@@ -673,7 +750,8 @@ impl Turtl {
 
         // now decrypt the boards
         self.find_models_keys(&mut boards)?;
-        let boards: Vec<Board> = protected::map_deserialize(self, boards)?;
+        let mut boards: Vec<Board> = protected::map_deserialize(self, boards)?;
+        Board::sort_by_order(&mut boards);
         for board in boards {
             board.mem_update(self, &mut sync_item)?;
         }
@@ -689,6 +767,112 @@ impl Turtl {
         Ok(())
     }
 
+    /// Note that the UI did something, resetting our idle timer
+    pub fn touch(&self) {
+        let mut activity_guard = lock!(self.last_activity);
+        *activity_guard = Instant::now();
+    }
+
+    /// Whether or not the session is locked
+    pub fn is_locked(&self) -> bool {
+        *lockr!(self.locked)
+    }
+
+    /// Clear the user's key and everything decrypted with it out of memory
+    fn clear_session_keys(&self) {
+        self.close_search();
+        {
+            let mut profile_guard = lockw!(self.profile);
+            profile_guard.wipe();
+        }
+        let mut user_guard = lockw!(self.user);
+        user_guard.do_lock();
+    }
+
+    /// Lock the session. This clears our keys and the decrypted profile out of
+    /// memory (pausing sync, since we can't decrypt anything coming in) but
+    /// leaves the user logged in and the local db alone. Use `unlock()` with
+    /// the user's password to get back to business.
+    pub fn lock(&self) -> TResult<()> {
+        {
+            let user_guard = lockr!(self.user);
+            if !user_guard.logged_in {
+                return TErr!(TError::BadValue(String::from("cannot lock, not logged in")));
+            }
+        }
+        let mut locked_guard = lockw!(self.locked);
+        if *locked_guard { return Ok(()); }
+        self.sync_pause();
+        self.clear_session_keys();
+        *locked_guard = true;
+        drop(locked_guard);
+        messaging::ui_event("app:locked", &())?;
+        Ok(())
+    }
+
+    /// Unlock a locked session by re-deriving the user's key from their
+    /// password, then reload the profile and resume sync.
+    pub fn unlock(&self, password: String) -> TResult<()> {
+        let mut locked_guard = lockw!(self.locked);
+        if !*locked_guard { return Ok(()); }
+        let key = {
+            let user_guard = lockr!(self.user);
+            user_guard.unlock_key(&password)?
+        };
+        {
+            let mut user_guard = lockw!(self.user);
+            user_guard.set_key(Some(key));
+        }
+        // same as after login, don't make the UI wait on the search index
+        let loaded = self.load_profile()
+            .and_then(|_| self.index_notes_background())
+            .map(|_| ());
+        if let Err(e) = loaded {
+            self.clear_session_keys();
+            return Err(e);
+        }
+        *locked_guard = false;
+        drop(locked_guard);
+        self.touch();
+        self.sync_resume();
+        messaging::ui_event("app:unlocked", &())?;
+        Ok(())
+    }
+
+    /// Lock the session if auto-locking is enabled (`auto_lock.idle_minutes`)
+    /// and we've been idle long enough. Returns whether we locked.
+    pub fn auto_lock_check(&self) -> TResult<bool> {
+        let idle_minutes = match config::get::<Option<u64>>(&["auto_lock", "idle_minutes"]) {
+            Ok(Some(x)) if x > 0 => x,
+            _ => return Ok(false),
+        };
+        if self.is_locked() || !lockr!(self.user).logged_in {
+            return Ok(false);
+        }
+        let idle = lock!(self.last_activity).elapsed();
+        if idle < Duration::from_secs(idle_minutes * 60) {
+            return Ok(false);
+        }
+        info!("turtl.auto_lock_check() -- idle for {}s, locking", idle.as_secs());
+        self.lock()?;
+        Ok(true)
+    }
+
+    /// Throw out our in-memory profile and rebuild it (and the search index)
+    /// from the local db. Handy if the two have drifted apart (say, after an
+    /// import or a manual db fix) and we'd rather not log out and back in.
+    pub fn reload_profile(&self) -> TResult<()> {
+        {
+            let mut profile_guard = lockw!(self.profile);
+            profile_guard.wipe();
+        }
+        self.load_profile()?;
+        messaging::ui_event("profile:loaded", &())?;
+        self.index_notes()?;
+        messaging::ui_event("profile:indexed", &())?;
+        Ok(())
+    }
+
     /// Load/deserialize a set of notes by id.
     pub fn load_notes(&self, note_ids: &Vec<String>) -> TResult<Vec<Note>> {
         let db_guard = lock!(self.db);
@@ -716,6 +900,331 @@ impl Turtl {
         protected::map_deserialize(self, notes)
     }
 
+    /// Grab the most recently updated notes/boards/spaces, newest first. Each
+    /// item is tagged with its type and when it was last saved locally. Items
+    /// we can't load (say, a note we no longer have the key for) are skipped.
+    pub fn recent(&self, limit: i32) -> TResult<Vec<Value>> {
+        let recent = {
+            let db_guard = lock!(self.db);
+            let db = match (*db_guard).as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            db.recent(&["notes", "boards", "spaces"], Some(limit))?
+        };
+
+        let note_ids = recent.iter()
+            .filter(|x| x.0 == "notes")
+            .map(|x| x.1.clone())
+            .collect::<Vec<_>>();
+        let mut notes: HashMap<String, Value> = HashMap::with_capacity(note_ids.len());
+        for note in self.load_notes(&note_ids)? {
+            let id = note.id_or_else()?;
+            notes.insert(id, jedi::to_val(&note)?);
+        }
+
+        let profile_guard = lockr!(self.profile);
+        let mut items = Vec::with_capacity(recent.len());
+        for (table, id, updated) in recent {
+            let (ty, item) = match table.as_str() {
+                "notes" => ("note", notes.remove(&id)),
+                "boards" => {
+                    let board = profile_guard.boards.iter().find(|x| x.id() == Some(&id));
+                    ("board", match board { Some(x) => Some(jedi::to_val(x)?), None => None })
+                }
+                "spaces" => {
+                    let space = profile_guard.spaces.iter().find(|x| x.id() == Some(&id));
+                    ("space", match space { Some(x) => Some(jedi::to_val(x)?), None => None })
+                }
+                _ => continue,
+            };
+            match item {
+                Some(item) => items.push(json!({"type": ty, "updated": updated, "item": item})),
+                None => debug!("turtl.recent() -- skipping {} {} (not loaded)", table, id),
+            }
+        }
+        Ok(items)
+    }
+
+    /// Check every keychain entry, space, board, and note we have stored to see
+    /// if we can find its key and decrypt it, without changing anything. Gives
+    /// back how many of each type we checked along with a list of problem items
+    /// (each with a `problem` of either "missing_key" or "decrypt_failed").
+    pub fn verify_profile(&self) -> TResult<Value> {
+        fn verify<T>(turtl: &Turtl, models: Vec<T>, problems: &mut Vec<Value>) -> usize
+            where T: Protected + Keyfinder
+        {
+            let count = models.len();
+            for mut model in models {
+                model.set_key(None);
+                let problem = match turtl.find_model_key(&mut model) {
+                    Ok(_) => match model.deserialize() {
+                        Ok(_) => continue,
+                        Err(e) => ("decrypt_failed", e),
+                    },
+                    Err(e) => ("missing_key", e),
+                };
+                problems.push(json!({
+                    "type": model.model_type(),
+                    "id": model.id(),
+                    "problem": problem.0,
+                    "error": problem.1.shed().to_response(false),
+                }));
+            }
+            count
+        }
+
+        let (keychain, spaces, boards, notes) = {
+            let db_guard = lock!(self.db);
+            let db = match (*db_guard).as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            let keychain: Vec<KeychainEntry> = db.all("keychain")?;
+            let spaces: Vec<Space> = db.all("spaces")?;
+            let boards: Vec<Board> = db.all("boards")?;
+            let notes: Vec<Note> = db.all("notes")?;
+            (keychain, spaces, boards, notes)
+        };
+        let mut problems = Vec::new();
+        let checked = json!({
+            "keychain": verify(self, keychain, &mut problems),
+            "spaces": verify(self, spaces, &mut problems),
+            "boards": verify(self, boards, &mut problems),
+            "notes": verify(self, notes, &mut problems),
+        });
+        Ok(json!({
+            "checked": checked,
+            "problems": problems,
+        }))
+    }
+
+    /// Compact our local storage: vacuum the db (and flush its WAL) and tidy up
+    /// the search index. Returns the sizes (in bytes) of each before and after
+    /// so the UI can show how much we saved. If notes haven't been indexed yet
+    /// the search stats come back null.
+    pub fn optimize_storage(&self) -> TResult<Value> {
+        let db_stats = {
+            let db_guard = lock!(self.db);
+            let db = match (*db_guard).as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            let before = db.size()?;
+            db.optimize()?;
+            json!({"before": before, "after": db.size()?})
+        };
+        let search_stats = {
+            let search_guard = lock!(self.search);
+            match search_guard.as_ref() {
+                Some(search) => {
+                    let before = search.size()?;
+                    search.optimize()?;
+                    json!({"before": before, "after": search.size()?})
+                }
+                None => Value::Null,
+            }
+        };
+        Ok(json!({
+            "db": db_stats,
+            "search": search_stats,
+        }))
+    }
+
+    /// Tally up how much room each note and board takes up locally: the length
+    /// of its encrypted body plus (for notes) the size of its attached file.
+    /// Comes back biggest first, so the UI can point out the worst offenders.
+    pub fn storage_usage(&self) -> TResult<Value> {
+        let (notes, boards): (Vec<Note>, Vec<Board>) = {
+            let db_guard = lock!(self.db);
+            let db = match (*db_guard).as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.db"))),
+            };
+            (db.all("notes")?, db.all("boards")?)
+        };
+        fn body_size<T: Protected>(model: &T) -> u64 {
+            model.get_body().map(|x| x.len() as u64).unwrap_or(0)
+        }
+
+        let mut usage = Vec::with_capacity(notes.len() + boards.len());
+        for note in &notes {
+            let note_id = note.id_or_else()?;
+            let body = body_size(note);
+            // prefer the size of the file we actually have on disk, falling
+            // back to what the note says it is
+            let file = if note.has_file {
+                FileData::file_finder(None, Some(&note_id)).ok()
+                    .and_then(|path| fs::metadata(path).ok())
+                    .map(|meta| meta.len())
+                    .or_else(|| note.file.as_ref().and_then(|x| x.size))
+                    .unwrap_or(0)
+            } else {
+                0
+            };
+            usage.push((body + file, json!({
+                "type": "note",
+                "id": note_id,
+                "space_id": note.space_id,
+                "body_size": body,
+                "file_size": file,
+                "size": body + file,
+            })));
+        }
+        for board in &boards {
+            let body = body_size(board);
+            usage.push((body, json!({
+                "type": "board",
+                "id": board.id_or_else()?,
+                "space_id": board.space_id,
+                "body_size": body,
+                "file_size": 0,
+                "size": body,
+            })));
+        }
+        usage.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(Value::Array(usage.into_iter().map(|(_, x)| x).collect()))
+    }
+
+    /// Take a quick performance snapshot of this device by timing the core's
+    /// hot paths against a throwaway note: encrypting/decrypting it, a round
+    /// trip through a scratch in-memory database, and indexing/searching it in
+    /// a scratch search index. Runs entirely locally and never touches the
+    /// user's data. Timings are in microseconds.
+    pub fn selftest_perf(&self) -> TResult<Value> {
+        fn micros(start: Instant) -> u64 {
+            let elapsed = start.elapsed();
+            elapsed.as_secs() * 1_000_000 + (elapsed.subsec_micros() as u64)
+        }
+        let text = "How to avoid huge ships: a comprehensive guide for the small boat owner. ".repeat(32);
+        let mut note: Note = jedi::from_val(json!({
+            "space_id": "selftest",
+            "user_id": 0,
+            "type": "text",
+            "title": "performance self-test",
+            "text": text,
+            "tags": ["ships", "boats", "avoidance"],
+        }))?;
+        let note_id = note.generate_id()?.clone();
+        note.generate_key()?;
+
+        let start = Instant::now();
+        let serialized = Protected::serialize(&mut note)?;
+        let encrypt = micros(start);
+        let mut decrypted: Note = jedi::from_val(serialized)?;
+        decrypted.set_key(note.key().map(|x| x.clone()));
+        let start = Instant::now();
+        decrypted.deserialize()?;
+        let decrypt = micros(start);
+
+        let db = Storage::new(&String::from(":memory:"), schema::get_schema())?;
+        let start = Instant::now();
+        db.save(&note)?;
+        let loaded: Option<Note> = db.get("notes", &note_id)?;
+        let db_roundtrip = micros(start);
+        if loaded.is_none() {
+            return TErr!(TError::MissingData(String::from("Turtl.selftest_perf() -- note didn't survive the db round trip")));
+        }
+
+        let mut search = Search::new()?;
+        let start = Instant::now();
+        search.index_note(&decrypted)?;
+        let search_index = micros(start);
+        let query: Query = jedi::from_val(json!({"space_id": "selftest", "text": "ships"}))?;
+        let start = Instant::now();
+        let (found, _) = search.find(&query)?;
+        let search_query = micros(start);
+        if found != vec![note_id] {
+            return TErr!(TError::MissingData(String::from("Turtl.selftest_perf() -- search didn't find our note")));
+        }
+
+        Ok(json!({
+            "encrypt": encrypt,
+            "decrypt": decrypt,
+            "db_roundtrip": db_roundtrip,
+            "search_index": search_index,
+            "search_query": search_query,
+            "total": encrypt + decrypt + db_roundtrip + search_index + search_query,
+        }))
+    }
+
+    /// Narrow a search query down to the spaces the current user can read. The
+    /// index can still hold notes from spaces we've been kicked out of, so
+    /// every search should go through here first. If the query names a space,
+    /// it only gets searched if it's readable. Otherwise we search all the
+    /// readable spaces in `spaces` (or all readable spaces, if that's empty).
+    pub fn readable_query(&self, query: &Query) -> TResult<Query> {
+        let mut query = query.clone();
+        let requested = if query.space_id != "" {
+            vec![query.space_id.clone()]
+        } else if query.spaces.len() > 0 {
+            query.spaces.clone()
+        } else {
+            let profile_guard = lockr!(self.profile);
+            profile_guard.spaces.iter()
+                .filter_map(|space| space.id().cloned())
+                .collect::<Vec<_>>()
+        };
+        query.space_id = String::from("");
+        query.spaces = requested.into_iter()
+            .filter(|space_id| Space::read_check(self, space_id).is_ok())
+            .collect::<Vec<_>>();
+        Ok(query)
+    }
+
+    /// Search-as-you-type. Each call supersedes any live search still running:
+    /// we wait out a short debounce (`search.live_debounce_ms`), run the query,
+    /// and send the notes to the UI in batches (`search:live:results`), making
+    /// sure no newer search has come in between each step and bailing if one
+    /// has. Only a search that makes it all the way through sends
+    /// `search:live:done` (with the total/tags).
+    pub fn live_search(&self, query: &Query) -> TResult<Value> {
+        let debounce: u64 = config::get(&["search", "live_debounce_ms"]).unwrap_or(150);
+        self.live_search_with(query, || util::sleep(debounce))
+    }
+
+    /// Does the work for `live_search()`, calling `debounce` to wait for more
+    /// typing instead of sleeping a set amount of time.
+    fn live_search_with<F>(&self, query: &Query, debounce: F) -> TResult<Value>
+        where F: FnOnce()
+    {
+        let search_id = {
+            let mut live_guard = lockw!(self.live_search);
+            *live_guard += 1;
+            *live_guard
+        };
+        let superseded = || *lockr!(self.live_search) != search_id;
+        let cancelled = json!({"search_id": search_id, "cancelled": true});
+
+        debounce();
+        if superseded() { return Ok(cancelled); }
+
+        let query = self.readable_query(query)?;
+        let (note_ids, total, tags) = {
+            let search_guard = lock!(self.search);
+            let search = match search_guard.as_ref() {
+                Some(x) => x,
+                None => return TErr!(TError::MissingField(String::from("Turtl.search"))),
+            };
+            let (note_ids, total) = search.find(&query)?;
+            (note_ids, total, search.find_tags(&query)?)
+        };
+        for batch in note_ids.chunks(LIVE_SEARCH_BATCH) {
+            if superseded() { return Ok(cancelled); }
+            let notes = self.load_notes(&batch.to_vec())?;
+            messaging::ui_event("search:live:results", &json!({"search_id": search_id, "notes": notes}))?;
+        }
+        if superseded() { return Ok(cancelled); }
+        let done = json!({
+            "search_id": search_id,
+            "cancelled": false,
+            "total": total,
+            "tags": tags,
+        });
+        messaging::ui_event("search:live:done", &done)?;
+        Ok(done)
+    }
+
     /// Take all the (encrypted) notes in our profile data then decrypt, index,
     /// and free them. The idea is we can get a set of note IDs from a search,
     /// but we're not holding all our notes decrypted in memory at all times.
@@ -741,10 +1250,87 @@ impl Turtl {
             }
         }
         let mut search_guard = lock!(self.search);
+        self.next_index_run();
         *search_guard = Some(search);
         Ok(())
     }
 
+    /// Like `index_notes()`, except we only grab the (encrypted) notes and
+    /// their keys up front, and decrypt/index them in batches on a background
+    /// thread. The search index is swapped in right away, so searches work
+    /// (on whatever's been indexed so far) while it fills up. Sends a
+    /// `profile:index-progress` event after each batch, then `profile:indexed`
+    /// once everything's in. Starting a new index (or closing the search
+    /// system) makes any background indexing still running give up.
+    pub fn index_notes_background(&self) -> TResult<thread::JoinHandle<()>> {
+        self.index_notes_background_with(|| {})
+    }
+
+    /// Does the work for `index_notes_background()`, calling `before_batch`
+    /// (on the indexing thread) before each batch is indexed.
+    fn index_notes_background_with<F>(&self, mut before_batch: F) -> TResult<thread::JoinHandle<()>>
+        where F: FnMut() + Send + 'static
+    {
+        let mut notes: Vec<Note> = {
+            let db_guard = lock!(self.db);
+            match db_guard.as_ref() {
+                Some(db) => db.all("notes")?,
+                None => return TErr!(TError::MissingData(String::from("Turtl.db"))),
+            }
+        };
+        self.find_models_keys(&mut notes)?;
+        let run = {
+            let mut search_guard = lock!(self.search);
+            let run = self.next_index_run();
+            *search_guard = Some(Search::new()?);
+            run
+        };
+
+        let search = self.search.clone();
+        let index_run = self.index_run.clone();
+        let handle = thread::Builder::new().name(String::from("turtl-index")).spawn(move || {
+            let total = notes.len();
+            let mut indexed = 0;
+            for batch in notes.chunks_mut(INDEX_BATCH) {
+                before_batch();
+                for note in batch.iter_mut() {
+                    if note.key().is_none() {
+                        warn!("turtl.index_notes_background() -- note {:?} has no key", note.id());
+                        continue;
+                    }
+                    if let Err(e) = note.deserialize() {
+                        error!("turtl.index_notes_background() -- problem deserializing note {:?}: {}", note.id(), e);
+                    }
+                }
+                {
+                    let mut search_guard = lock!(search);
+                    if *lockr!(index_run) != run {
+                        info!("turtl.index_notes_background() -- superseded, stopping at {}/{}", indexed, total);
+                        return;
+                    }
+                    let index = match search_guard.as_mut() {
+                        Some(x) => x,
+                        None => return,
+                    };
+                    for note in batch.iter() {
+                        // NOTE: reindex, not index, in case the note got saved
+                        // (and indexed) while we were getting to it
+                        match index.reindex_note(note) {
+                            Ok(_) => {}
+                            Err(e) => error!("turtl.index_notes_background() -- problem indexing note {:?}: {}", note.id(), e),
+                        }
+                    }
+                }
+                indexed += batch.len();
+                messaging::ui_event("profile:index-progress", &json!({"indexed": indexed, "total": total}))
+                    .unwrap_or_else(|e| error!("turtl.index_notes_background() -- error sending progress: {}", e));
+            }
+            messaging::ui_event("profile:indexed", &())
+                .unwrap_or_else(|e| error!("turtl.index_notes_background() -- error sending indexed event: {}", e));
+        })?;
+        Ok(handle)
+    }
+
     /// Log out the current user (if logged in) and wipe ALL local SQL databases
     /// from our data folder.
     pub fn wipe_app_data(&self) -> TResult<()> {
@@ -806,6 +1392,53 @@ impl Turtl {
         Ok(())
     }
 
+    /// Wipe the current user's local database and search index while keeping
+    /// them logged in, then restart sync. The fresh db has no sync_id, so the
+    /// sync system pulls the full profile from the API and rebuilds everything
+    /// from scratch. Useful for recovering from a corrupted local db.
+    ///
+    /// We refuse to do this if there are outgoing changes that haven't made it
+    /// to the server yet, since they only exist locally.
+    pub fn wipe_local_cache(&self) -> TResult<()> {
+        self.assert_connected()?;
+        let user_id = self.user_id()?;
+        let pending = SyncRecord::get_all_pending(self)?;
+        if pending.len() > 0 {
+            return TErr!(TError::BadValue(format!("there are {} changes waiting to be synced, refusing to wipe local cache", pending.len())));
+        }
+
+        self.sync_shutdown(true)?;
+        self.close_search();
+        self.close_user_db()?;
+        {
+            let mut profile_guard = lockw!(self.profile);
+            profile_guard.wipe();
+        }
+
+        let db_loc = self.get_user_db_location(&user_id)?;
+        if db_loc != ":memory:" {
+            info!("turtl.wipe_local_cache() -- removing {}", db_loc);
+            fs::remove_file(&db_loc)?;
+            // don't leave the old db's WAL/shm files around for the new db to
+            // pick up (same as storage::open_or_recover())
+            for suffix in &["-wal", "-shm"] {
+                let extra = format!("{}{}", db_loc, suffix);
+                if Path::new(&extra).exists() {
+                    fs::remove_file(&extra)?;
+                }
+            }
+        }
+        let db = self.create_user_db()?;
+        {
+            let mut db_guard = lock!(self.db);
+            *db_guard = Some(db);
+        }
+        // start with a fresh (empty) search index so notes coming in from the
+        // full sync have somewhere to go
+        self.index_notes()?;
+        self.sync_start()
+    }
+
     /// Shut down this Turtl instance and all the state/threads it manages
     pub fn shutdown(&mut self) -> TResult<()> {
         self.sync_shutdown(false)?;
@@ -829,20 +1462,27 @@ impl Drop for Turtl {
 pub mod tests {
     use super::*;
 
-    use ::std::sync::{RwLock, Mutex};
+    use ::std::sync::{RwLock, Mutex, mpsc};
+    use ::std::io::{BufRead, BufReader, Read, Write};
+    use ::std::net::TcpListener;
+    use ::std::thread;
 
     use ::jedi;
 
     use ::crypto::{self, Key};
     use ::search::Query;
     use ::models::model::Model;
-    use ::models::protected::Protected;
+    use ::models::protected::{self, Protected};
     use ::models::keychain::KeychainEntry;
     use ::models::user::User;
     use ::models::note::Note;
     use ::models::board::Board;
-    use ::models::sync_record::{SyncRecord, SyncAction, SyncType};
+    use ::models::space_member::SpaceMember;
+    use ::lib_permissions::Role;
+    use ::models::sync_record::{SyncRecord, SyncAction, SyncType, PendingSummary};
+    use ::models::storable::Storable;
     use ::sync::sync_model;
+    use ::dispatch;
 
     protected! {
         #[derive(Serialize, Deserialize)]
@@ -876,6 +1516,102 @@ pub mod tests {
         turtl
     }
 
+    /// Like `with_test(true)`, but logs our test user in with the given
+    /// key/auth (for tests that need to know the user's password)
+    pub fn with_test_login(user_key: Key, user_auth: String) -> Turtl {
+        let turtl = with_test(false);
+        let mut user: User = jedi::parse(&String::from(r#"{"id":"51","username":"slippyslappy@turtlapp.com","storage":104857600}"#)).unwrap();
+        user.do_login(user_key, user_auth);
+        let mut user_guard = lockw!(turtl.user);
+        *user_guard = user;
+        drop(user_guard);
+        turtl.set_user_id();
+        let db = turtl.create_user_db().unwrap();
+        let mut db_guard = lock!(turtl.db);
+        *db_guard = Some(db);
+        drop(db_guard);
+        turtl
+    }
+
+    lazy_static! {
+        /// The API endpoint lives in the global config, so tests that talk to a
+        /// mock API hold this to keep from stepping on each other.
+        pub static ref MOCK_API_LOCK: Mutex<()> = Mutex::new(());
+        /// Same idea for tests that tweak any other global config value. Hold
+        /// this while the value is changed, and put back whatever was there
+        /// before letting go.
+        pub static ref CONFIG_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// Start a fake API server that answers one request with the given status
+    /// line and body, and point our config at it. The handle returns the
+    /// request line the server got. Hold `MOCK_API_LOCK` while using this.
+    pub fn mock_api(status: &str, body: String) -> thread::JoinHandle<String> {
+        let handle = mock_api_many(vec![(status, body)]);
+        thread::spawn(move || handle.join().unwrap().remove(0))
+    }
+
+    /// Like `mock_api()`, but answers a run of requests (in order) with the
+    /// given responses, returning all the request lines it saw.
+    pub fn mock_api_many(responses: Vec<(&str, String)>) -> thread::JoinHandle<Vec<String>> {
+        let handle = mock_api_bodies(responses);
+        thread::spawn(move || {
+            handle.join().unwrap().into_iter()
+                .map(|(reqline, _)| reqline)
+                .collect::<Vec<_>>()
+        })
+    }
+
+    /// Like `mock_api_many()`, but hands back the request bodies along with
+    /// the request lines.
+    pub fn mock_api_bodies(responses: Vec<(&str, String)>) -> thread::JoinHandle<Vec<(String, String)>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        config::merge(&json!({"api": {"endpoint": format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port())}})).unwrap();
+        let responses = responses.into_iter()
+            .map(|(status, body)| (String::from(status), body))
+            .collect::<Vec<_>>();
+        thread::spawn(move || {
+            let mut reqlines = Vec::new();
+            for (status, body) in responses {
+                let mut stream = listener.incoming().next().unwrap().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut reqline = String::new();
+                reader.read_line(&mut reqline).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() { break; }
+                    if line.to_lowercase().starts_with("content-length:") {
+                        content_length = line[15..].trim().parse().unwrap();
+                    }
+                }
+                // drain the request body so closing doesn't reset the connection
+                let mut reqbody = vec![0; content_length];
+                reader.read_exact(&mut reqbody).unwrap();
+                let res = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                stream.write_all(res.as_bytes()).unwrap();
+                reqlines.push((reqline, String::from_utf8(reqbody).unwrap()));
+            }
+            reqlines
+        })
+    }
+
+    #[test]
+    fn configures_worker_threads() {
+        let _lock = CONFIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(false);
+        drop(turtl);
+        let old_threads = config::get::<Value>(&["work", "threads"]).unwrap_or(Value::Null);
+        config::merge(&json!({"work": {"threads": 3}})).unwrap();
+        let configured = Turtl::new().unwrap().work.workers();
+        config::merge(&json!({"work": {"threads": null}})).unwrap();
+        let defaulted = Turtl::new().unwrap().work.workers();
+        config::merge(&json!({"work": {"threads": old_threads}})).unwrap();
+        assert_eq!(configured, 3);
+        assert_eq!(defaulted, ::std::cmp::max((num_cpus::get() as u32).saturating_sub(1), 1));
+    }
+
     #[test]
     fn finding_keys() {
         let enc_board = String::from(r#"{"id":"015bac2244ea4944baee41b88207731eaeb7e2cc5c955fb8a05b028c1409aaf55024f5d26fa30034","space_id":"015bac22440a4944baee41b88207731eaeb7e2cc5c955fb8a05b028c1409aaf55024f5d26fa3001e","user_id":51,"keys":[{"k":"AAYBAAz9znE+csObRfJh7v1+vILRefrGx/ZC97qtGetYvtPYr3gO4v4AnhWPP/z49ESptJ1aSIOWTzPKBt5B1fI=","s":"015bac22440a4944baee41b88207731eaeb7e2cc5c955fb8a05b028c1409aaf55024f5d26fa3001e"}],"body":"AAYBAAxEVD6FeHQaEl9yh3M9LVJTh0poYU8FA1SxwYVn/8N1SBNYBYzuWcfXMoTFrmz0CHum"}"#);
@@ -1102,6 +1838,1071 @@ pub mod tests {
         assert_eq!(notes.len(), 0);
     }
 
+    #[test]
+    fn lists_recent_items() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 69, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note: Note = jedi::from_val(json!({"user_id": 69, "space_id": space_id, "type": "text", "title": "sit on a couch"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        let mut board: Board = jedi::from_val(json!({"user_id": 69, "space_id": space_id, "title": "things to avoid"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut board, false).unwrap();
+
+        let types = |items: &Vec<Value>| -> Vec<String> {
+            items.iter().map(|x| jedi::get::<String>(&["type"], x).unwrap()).collect()
+        };
+        let items = turtl.recent(10).unwrap();
+        assert_eq!(types(&items), vec!["board", "note", "space"]);
+        assert_eq!(jedi::get::<String>(&["item", "title"], &items[1]).unwrap(), "sit on a couch");
+
+        // editing the space bumps it to the top
+        space.title = Some(String::from("get a real job"));
+        sync_model::save_model(SyncAction::Edit, &turtl, &mut space, false).unwrap();
+        let items = turtl.recent(10).unwrap();
+        assert_eq!(types(&items), vec!["space", "board", "note"]);
+        assert_eq!(jedi::get::<String>(&["item", "title"], &items[0]).unwrap(), "get a real job");
+
+        let items = turtl.recent(2).unwrap();
+        assert_eq!(types(&items), vec!["space", "board"]);
+    }
+
+    #[test]
+    fn space_stats() {
+        let turtl = with_test(true);
+
+        let mut space1: Space = jedi::from_val(json!({"user_id": 69, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space1, false).unwrap();
+        let space1_id = space1.id().unwrap().clone();
+        let mut space2: Space = jedi::from_val(json!({"user_id": 69, "title": "sit on a couch"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space2, true).unwrap();
+        let space2_id = space2.id().unwrap().clone();
+
+        let mut board: Board = jedi::from_val(json!({"user_id": 69, "space_id": space1_id, "title": "jobs"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut board, false).unwrap();
+        for title in vec!["plumber", "astronaut"] {
+            let mut note: Note = jedi::from_val(json!({"user_id": 69, "space_id": space1_id, "type": "text", "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        }
+        let mut note: Note = jedi::from_val(json!({"user_id": 69, "space_id": space2_id, "type": "text", "title": "couch"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, true).unwrap();
+        // a note we have no hope of decrypting
+        {
+            let bad_note: Note = jedi::from_val(json!({"id": "01600000000000000000000000000000000000000000000000000000000000000000000000000000", "space_id": space2_id, "user_id": 69, "body": "AAYBAAzlol"})).unwrap();
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().save(&bad_note).unwrap();
+        }
+
+        let stats = Space::stats(&turtl, &space1_id).unwrap();
+        assert_eq!(stats, json!({
+            "space_id": space1_id,
+            "boards": 1,
+            "notes": 2,
+            "decrypt_failed": {"boards": 0, "notes": 0},
+            "sync": {"pending": 4, "frozen": 0},
+        }));
+        let stats = Space::stats(&turtl, &space2_id).unwrap();
+        assert_eq!(stats, json!({
+            "space_id": space2_id,
+            "boards": 0,
+            "notes": 2,
+            "decrypt_failed": {"boards": 0, "notes": 1},
+            "sync": {"pending": 0, "frozen": 0},
+        }));
+        assert!(Space::stats(&turtl, &String::from("1234")).is_err());
+    }
+
+    #[test]
+    fn moves_notes() {
+        let turtl = with_test(true);
+
+        let mut space1: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space1, false).unwrap();
+        let space1_id = space1.id().unwrap().clone();
+        let mut space2: Space = jedi::from_val(json!({"user_id": 51, "title": "sit on a couch"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space2, false).unwrap();
+        let space2_id = space2.id().unwrap().clone();
+        let mut board: Board = jedi::from_val(json!({"user_id": 51, "space_id": space2_id, "title": "couches"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut board, false).unwrap();
+        let board_id = board.id().unwrap().clone();
+
+        let mut note_ids = Vec::new();
+        for title in vec!["plumber", "astronaut"] {
+            let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space1_id, "type": "text", "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+            note_ids.push(note.id().unwrap().clone());
+        }
+
+        // can't move into a board that isn't in the target space
+        assert!(Note::move_many(&turtl, &note_ids, &space1_id, Some(board_id.clone())).is_err());
+        // can't move notes that don't exist
+        let bad_ids = vec![note_ids[0].clone(), String::from("1234")];
+        assert!(Note::move_many(&turtl, &bad_ids, &space2_id, None).is_err());
+
+        let moved = Note::move_many(&turtl, &note_ids, &space2_id, Some(board_id.clone())).unwrap();
+        assert_eq!(moved.len(), 2);
+        {
+            let db_guard = lock!(turtl.db);
+            let db = db_guard.as_ref().unwrap();
+            for id in &note_ids {
+                let note: Note = db.get(Note::tablename(), id).unwrap().unwrap();
+                assert_eq!(note.space_id, space2_id);
+                assert_eq!(note.board_id, Some(board_id.clone()));
+                // the note's key is now only reachable through the new space
+                // and board
+                let keys = note.get_keys().unwrap();
+                assert_eq!(keys.len(), 2);
+                assert!(keys.iter().all(|k| k.id == space2_id || k.id == board_id));
+            }
+        }
+
+        // ditch the old space (and its key) entirely. if the notes still
+        // decrypt, it's because of the new space.
+        sync_model::delete_model::<Space>(&turtl, &space1_id, false).unwrap();
+        let notes = turtl.load_notes(&note_ids).unwrap();
+        let titles = notes.iter().map(|x| x.title.clone().unwrap()).collect::<Vec<_>>();
+        assert_eq!(titles, vec!["plumber", "astronaut"]);
+    }
+
+    #[test]
+    fn verifies_profile() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "plumber"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+
+        let report = turtl.verify_profile().unwrap();
+        assert_eq!(report["checked"], json!({"keychain": 1, "spaces": 1, "boards": 0, "notes": 1}));
+        assert_eq!(report["problems"], json!([]));
+
+        // a note with no keys we can use, and one we have the key for but
+        // whose body is garbage
+        let keyless_id = String::from("01600000000000000000000000000000000000000000000000000000000000000000000000000000");
+        let garbage_id = String::from("01600000000000000000000000000000000000000000000000000000000000000000000000000001");
+        {
+            let keyless: Note = jedi::from_val(json!({"id": keyless_id, "space_id": space_id, "user_id": 51, "body": "AAYBAAzlol"})).unwrap();
+            let mut garbage: Note = note.clone().unwrap();
+            garbage.set_id(garbage_id.clone());
+            garbage.set_body(String::from("AAYBAAzlol"));
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().save(&keyless).unwrap();
+            db_guard.as_ref().unwrap().save(&garbage).unwrap();
+        }
+
+        let report = turtl.verify_profile().unwrap();
+        assert_eq!(report["checked"]["notes"], json!(3));
+        let problems = report["problems"].as_array().unwrap();
+        assert_eq!(problems.len(), 2);
+        let problem = |id: &String| -> Value {
+            problems.iter().find(|x| x["id"] == json!(id)).unwrap().clone()
+        };
+        assert_eq!(problem(&keyless_id)["type"], json!("note"));
+        assert_eq!(problem(&keyless_id)["problem"], json!("missing_key"));
+        assert_eq!(problem(&keyless_id)["error"]["code"], json!("not_found"));
+        assert_eq!(problem(&garbage_id)["problem"], json!("decrypt_failed"));
+
+        // nothing got touched
+        let notes = turtl.load_notes(&vec![note.id().unwrap().clone()]).unwrap();
+        assert_eq!(notes[0].title, Some(String::from("plumber")));
+    }
+
+    #[test]
+    fn reloads_profile() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note1: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "plumber"})).unwrap();
+        let mut note2: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "electrician"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note1, false).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note2, false).unwrap();
+        turtl.reload_profile().unwrap();
+
+        let find_notes = |turtl: &Turtl| -> Vec<String> {
+            let qry: Query = jedi::from_val(json!({"space_id": space_id, "page": 1, "per_page": 100})).unwrap();
+            let search_guard = lock!(turtl.search);
+            let mut ids = search_guard.as_ref().unwrap().find(&qry).unwrap().0;
+            ids.sort();
+            ids
+        };
+        let mut all_ids = vec![note1.id().unwrap().clone(), note2.id().unwrap().clone()];
+        all_ids.sort();
+        assert_eq!(find_notes(&turtl), all_ids);
+
+        // change things behind the profile's back
+        {
+            let mut edited = space.clone().unwrap();
+            edited.title = Some(String::from("get a better job"));
+            Protected::serialize(&mut edited).unwrap();
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().save(&edited).unwrap();
+            db_guard.as_ref().unwrap().delete(&note2).unwrap();
+        }
+        {
+            let profile_guard = lockr!(turtl.profile);
+            assert_eq!(profile_guard.spaces[0].title, Some(String::from("get a job")));
+        }
+
+        turtl.reload_profile().unwrap();
+        let profile_guard = lockr!(turtl.profile);
+        assert_eq!(profile_guard.spaces.len(), 1);
+        assert_eq!(profile_guard.spaces[0].title, Some(String::from("get a better job")));
+        assert_eq!(profile_guard.keychain.entries.len(), 1);
+        drop(profile_guard);
+        assert_eq!(find_notes(&turtl), vec![note1.id().unwrap().clone()]);
+    }
+
+    #[test]
+    fn reorders_boards_and_notes() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut board_ids = Vec::new();
+        for title in &["plumbing", "electrical", "carpentry"] {
+            let mut board: Board = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut board, false).unwrap();
+            board_ids.push(board.id().unwrap().clone());
+        }
+        let mut note_ids = Vec::new();
+        for title in &["plumber", "electrician", "carpenter", "couch sitter"] {
+            let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+            note_ids.push(note.id().unwrap().clone());
+        }
+
+        let boards = Board::reorder(&turtl, &vec![board_ids[2].clone(), board_ids[0].clone(), board_ids[1].clone()]).unwrap();
+        assert_eq!(boards.iter().map(|x| x.order.unwrap()).collect::<Vec<_>>(), vec![0, 1, 2]);
+        // the couch sitter doesn't get an order
+        Note::reorder(&turtl, &vec![note_ids[1].clone(), note_ids[2].clone(), note_ids[0].clone()]).unwrap();
+        assert!(Board::reorder(&turtl, &vec![String::from("1234")]).is_err());
+        assert!(Note::reorder(&turtl, &vec![note_ids[0].clone(), String::from("1234")]).is_err());
+
+        turtl.reload_profile().unwrap();
+        {
+            let profile_guard = lockr!(turtl.profile);
+            let titles = profile_guard.boards.iter().map(|x| x.title.clone().unwrap()).collect::<Vec<_>>();
+            assert_eq!(titles, vec!["carpentry", "plumbing", "electrical"]);
+        }
+        let find_notes = |dir: &str| -> Vec<String> {
+            let qry: Query = jedi::from_val(json!({"space_id": space_id, "sort": "order", "sort_direction": dir})).unwrap();
+            let search_guard = lock!(turtl.search);
+            search_guard.as_ref().unwrap().find(&qry).unwrap().0
+        };
+        let ordered = vec![note_ids[1].clone(), note_ids[2].clone(), note_ids[0].clone(), note_ids[3].clone()];
+        assert_eq!(find_notes("asc"), ordered);
+        let ordered = vec![note_ids[0].clone(), note_ids[2].clone(), note_ids[1].clone(), note_ids[3].clone()];
+        assert_eq!(find_notes("desc"), ordered);
+        // and the order is stored on the notes themselves
+        let notes = turtl.load_notes(&note_ids).unwrap();
+        assert_eq!(notes.iter().map(|x| x.order).collect::<Vec<_>>(), vec![Some(2), Some(0), Some(1), None]);
+    }
+
+    #[test]
+    fn cancels_stale_live_searches() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        for title in &["plumber", "plumbing supplies", "electrician"] {
+            let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        }
+        turtl.reload_profile().unwrap();
+
+        // type "p", "pl", "plumb" in quick succession. the first two searches
+        // sit in their debounce until we let them go, by which time "plumb"
+        // has come in and finished
+        let turtl = Arc::new(turtl);
+        let mut handles = Vec::new();
+        for text in &["p", "pl"] {
+            let turtl2 = turtl.clone();
+            let qry: Query = jedi::from_val(json!({"text": text})).unwrap();
+            let (waiting_tx, waiting_rx) = mpsc::channel::<()>();
+            let (go_tx, go_rx) = mpsc::channel::<()>();
+            handles.push((go_tx, thread::spawn(move || {
+                turtl2.live_search_with(&qry, move || {
+                    waiting_tx.send(()).unwrap();
+                    go_rx.recv().unwrap();
+                }).unwrap()
+            })));
+            waiting_rx.recv().unwrap();
+        }
+        let qry: Query = jedi::from_val(json!({"text": "plumb*"})).unwrap();
+        let last = turtl.live_search_with(&qry, || {}).unwrap();
+        for (go_tx, handle) in handles {
+            go_tx.send(()).unwrap();
+            let res = handle.join().unwrap();
+            assert_eq!(res["cancelled"], json!(true));
+            assert!(res.get("total").is_none());
+        }
+        assert_eq!(last["cancelled"], json!(false));
+        assert_eq!(last["total"], json!(2));
+    }
+
+    #[test]
+    fn locks_and_unlocks() {
+        let username = String::from("slippyslappy@turtlapp.com");
+        let password = String::from("get a job");
+        let (user_key, user_auth) = user::generate_auth(&username, &password, user::CURRENT_AUTH_VERSION).unwrap();
+        let turtl = with_test_login(user_key.clone(), user_auth);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "plumber"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        turtl.index_notes().unwrap();
+
+        turtl.lock().unwrap();
+        assert!(turtl.is_locked());
+        assert!(lockr!(turtl.user).key().is_none());
+        assert!(lockr!(turtl.user).logged_in);
+        assert_eq!(lockr!(turtl.profile).spaces.len(), 0);
+        assert_eq!(lockr!(turtl.profile).keychain.entries.len(), 0);
+        assert!(lock!(turtl.search).is_none());
+
+        match turtl.unlock(String::from("get a life")) {
+            Err(e) => assert_eq!(e.code(), "permission_denied"),
+            Ok(_) => panic!("unlocked with a bad password"),
+        }
+        assert!(turtl.is_locked());
+        assert!(lockr!(turtl.user).key().is_none());
+
+        turtl.unlock(password.clone()).unwrap();
+        assert!(!turtl.is_locked());
+        assert_eq!(lockr!(turtl.user).key(), Some(&user_key));
+        assert_eq!(lockr!(turtl.profile).spaces[0].title, Some(String::from("get a job")));
+        assert!(lock!(turtl.search).is_some());
+        let notes = turtl.load_notes(&vec![note.id().unwrap().clone()]).unwrap();
+        assert_eq!(notes[0].title, Some(String::from("plumber")));
+
+        // auto-lock only kicks in once we've been idle long enough
+        let _lock = CONFIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        config::merge(&json!({"auto_lock": {"idle_minutes": 1}})).unwrap();
+        assert!(!turtl.auto_lock_check().unwrap());
+        {
+            let mut activity_guard = lock!(turtl.last_activity);
+            *activity_guard = Instant::now().checked_sub(Duration::from_secs(120)).unwrap();
+        }
+        assert!(turtl.auto_lock_check().unwrap());
+        assert!(turtl.is_locked());
+        config::merge(&json!({"auto_lock": {"idle_minutes": null}})).unwrap();
+    }
+
+    #[test]
+    fn pings_dont_postpone_auto_lock() {
+        let _lock = CONFIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        let old_idle = config::get::<Value>(&["auto_lock", "idle_minutes"]).unwrap_or(Value::Null);
+        config::merge(&json!({"auto_lock": {"idle_minutes": 1}})).unwrap();
+        let idle_since = Instant::now().checked_sub(Duration::from_secs(120)).unwrap();
+
+        // a real command counts as activity...
+        *lock!(turtl.last_activity) = idle_since;
+        dispatch::process(&turtl, &String::from(r#"["1","app:connected"]"#)).unwrap();
+        let touched = *lock!(turtl.last_activity) != idle_since;
+        let locked_after_command = turtl.auto_lock_check().unwrap();
+
+        // ...but a ping doesn't
+        *lock!(turtl.last_activity) = idle_since;
+        dispatch::process(&turtl, &String::from(r#"["2","ping"]"#)).unwrap();
+        let pinged_activity = *lock!(turtl.last_activity);
+        let locked_after_ping = turtl.auto_lock_check().unwrap();
+
+        // and neither does a command we turned away because we're locked
+        dispatch::process(&turtl, &String::from(r#"["3","sync:pending"]"#)).unwrap();
+        let rejected_activity = *lock!(turtl.last_activity);
+        config::merge(&json!({"auto_lock": {"idle_minutes": old_idle}})).unwrap();
+
+        assert!(touched);
+        assert!(!locked_after_command);
+        assert_eq!(pinged_activity, idle_since);
+        assert!(locked_after_ping);
+        assert!(turtl.is_locked());
+        assert_eq!(rejected_activity, idle_since);
+    }
+
+    #[test]
+    fn grabs_note_history() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "astronaut", "mod": 300})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        let note_id = note.id().unwrap().clone();
+
+        // build some old versions, encrypted with the note's key
+        let mut versions = Vec::new();
+        for &(title, modtime) in &[("plumber", 200), ("couch sitter", 100)] {
+            let mut version = note.clone().unwrap();
+            version.title = Some(String::from(title));
+            version.mod_ = Some(modtime);
+            versions.push(Protected::serialize(&mut version).unwrap());
+        }
+        versions.push(json!({"id": note_id, "space_id": space_id, "user_id": 51, "mod": 150, "body": "AAYBAAzlol"}));
+
+        let handle = mock_api("200 OK", jedi::stringify(&versions).unwrap());
+        let history = Note::history(&turtl, &note_id).unwrap();
+        let reqline = handle.join().unwrap();
+        assert!(reqline.starts_with(&format!("GET /notes/{}/versions ", note_id)));
+        // the garbage version is skipped, and we get the rest oldest first
+        let titles = history.iter().map(|x| jedi::get::<String>(&["title"], x).unwrap()).collect::<Vec<_>>();
+        assert_eq!(titles, vec!["couch sitter", "plumber"]);
+        assert_eq!(jedi::get::<String>(&["id"], &history[0]).unwrap(), note_id);
+
+        assert!(Note::history(&turtl, &String::from("1234")).is_err());
+    }
+
+    #[test]
+    fn shares_notes() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        *lockw!(turtl.connected) = true;
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "astronaut"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        let note_id = note.id().unwrap().clone();
+        let note_key = note.key().unwrap().clone();
+
+        config::merge(&json!({"share": {"url": "https://turtlapp.com/share/"}})).unwrap();
+        let handle = mock_api_bodies(vec![
+            ("200 OK", String::from(r#"{"id":"share1234"}"#)),
+            ("200 OK", String::from("true")),
+        ]);
+        let link = Note::share_link(&turtl, &note_id, Some(3600)).unwrap();
+        Note::revoke_share_link(&turtl, &note_id, &link.id).unwrap();
+        let reqs = handle.join().unwrap();
+        assert!(reqs[0].0.starts_with(&format!("POST /notes/{}/shares ", note_id)));
+        assert!(reqs[1].0.starts_with(&format!("DELETE /notes/{}/shares/share1234 ", note_id)));
+
+        assert_eq!(link.id, "share1234");
+        assert_eq!(link.note_id, note_id);
+        assert!(link.url.starts_with("https://turtlapp.com/share/share1234#"));
+        // the key we sent the server opens with the key in the link (and only
+        // the key in the link), and gives us the note's key
+        let registered: Value = jedi::parse(&reqs[0].1).unwrap();
+        let encrypted_key: String = jedi::get(&["key"], &registered).unwrap();
+        let expires: u64 = jedi::get(&["expires"], &registered).unwrap();
+        assert_eq!(expires, link.expires);
+        let share_key = Key::new(crypto::from_hex(&String::from(link.url.split('#').nth(1).unwrap())).unwrap());
+        assert_eq!(protected::decrypt_key(&share_key, &encrypted_key).unwrap(), note_key);
+        assert!(protected::decrypt_key(&Key::random().unwrap(), &encrypted_key).is_err());
+
+        assert!(Note::share_link(&turtl, &note_id, Some(0)).is_err());
+        assert!(Note::share_link(&turtl, &String::from("1234"), None).is_err());
+    }
+
+    #[test]
+    fn indexes_in_the_background() {
+        let turtl = with_test(true);
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let num_notes = (INDEX_BATCH * 3) + 1;
+        for i in 0..num_notes {
+            let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": format!("plumber {}", i)})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+        }
+        turtl.load_profile().unwrap();
+
+        // we get control back (with a usable index) before any indexing is
+        // done. the indexer waits on us before each batch so we know exactly
+        // where it's at.
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+        let handle = turtl.index_notes_background_with(move || { let _ = go_rx.recv(); }).unwrap();
+        {
+            let search_guard = lock!(turtl.search);
+            let search = search_guard.as_ref().unwrap();
+            assert_eq!(search.count_notes(&space_id).unwrap(), 0);
+            let qry: Query = jedi::from_val(json!({"space_id": space_id, "text": "plumber"})).unwrap();
+            let (ids, _) = search.find(&qry).unwrap();
+            assert_eq!(ids.len(), 0);
+        }
+        drop(go_tx);
+        handle.join().unwrap();
+        {
+            let search_guard = lock!(turtl.search);
+            let search = search_guard.as_ref().unwrap();
+            assert_eq!(search.count_notes(&space_id).unwrap(), num_notes as i64);
+            let qry: Query = jedi::from_val(json!({"space_id": space_id, "text": "plumber", "per_page": 500})).unwrap();
+            let (ids, total) = search.find(&qry).unwrap();
+            assert_eq!(ids.len(), num_notes);
+            assert_eq!(total, num_notes as i32);
+        }
+
+        // a new index (or closing search) stops a run that's still going
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+        let handle = turtl.index_notes_background_with(move || { let _ = go_rx.recv(); }).unwrap();
+        turtl.close_search();
+        drop(go_tx);
+        handle.join().unwrap();
+        assert!(lock!(turtl.search).is_none());
+    }
+
+    #[test]
+    fn lists_and_revokes_sessions() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        *lockw!(turtl.connected) = true;
+
+        let handle = mock_api_many(vec![
+            ("200 OK", String::from(r#"[{"id":"s1","device":"turtl desktop","ip":"10.0.0.1","created":1500000000,"last_active":1500000600,"current":true},{"id":"s2","device":"turtl android"}]"#)),
+            ("200 OK", String::from("true")),
+            ("404 Not Found", String::from(r#"{"error":"no such session"}"#)),
+        ]);
+        let sessions = User::sessions(&turtl).unwrap();
+        User::revoke_session(&turtl, &String::from("s2")).unwrap();
+        assert!(User::revoke_session(&turtl, &String::from("s2")).is_err());
+        let reqs = handle.join().unwrap();
+        assert!(reqs[0].starts_with("GET /users/51/sessions "));
+        assert!(reqs[1].starts_with("DELETE /users/51/sessions/s2 "));
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, "s1");
+        assert_eq!(sessions[0].device, Some(String::from("turtl desktop")));
+        assert_eq!(sessions[0].last_active, Some(1500000600));
+        assert!(sessions[0].current);
+        assert_eq!(sessions[1].ip, None);
+        assert!(!sessions[1].current);
+    }
+
+    #[test]
+    fn copies_accounts() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // set up the account we're copying from
+        let username = String::from("slippyslappy@turtlapp.com");
+        let password = String::from("get a job");
+        let (source_key, source_auth) = user::generate_auth(&username, &password, user::CURRENT_AUTH_VERSION).unwrap();
+        let mut source_user: User = jedi::from_val(json!({"id": "69", "username": username, "storage": 104857600})).unwrap();
+        source_user.do_login(source_key, source_auth);
+        let mut source = with_test(false);
+        source.user = RwLock::new(source_user);
+        *lockw!(source.user_id) = Some(String::from("69"));
+        source.db = Arc::new(Mutex::new(Some(Storage::new(&String::from(":memory:"), schema::get_schema()).unwrap())));
+        let mut space: Space = jedi::from_val(json!({"user_id": 69, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &source, &mut space, false).unwrap();
+        let source_space_id = space.id().unwrap().clone();
+        let mut board: Board = jedi::from_val(json!({"user_id": 69, "space_id": source_space_id, "title": "plumbing"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &source, &mut board, false).unwrap();
+        let mut note: Note = jedi::from_val(json!({"user_id": 69, "space_id": source_space_id, "board_id": board.id().unwrap(), "type": "text", "title": "plumber"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &source, &mut note, false).unwrap();
+
+        // what the API hands back for the source account's full sync
+        fn records<T: Protected + Storable>(db: &Storage, ty: &str) -> Vec<Value> {
+            db.all::<T>(T::tablename()).unwrap().iter()
+                .map(|x| json!({"type": ty, "action": "add", "item_id": x.id().unwrap(), "user_id": 69, "data": x.data_for_storage().unwrap()}))
+                .collect()
+        }
+        let full_sync = {
+            let db_guard = lock!(source.db);
+            let db = db_guard.as_ref().unwrap();
+            let mut recs = records::<KeychainEntry>(db, "keychain");
+            recs.append(&mut records::<Space>(db, "space"));
+            recs.append(&mut records::<Board>(db, "board"));
+            recs.append(&mut records::<Note>(db, "note"));
+            json!({"records": recs, "sync_id": 1})
+        };
+        drop(source);
+
+        let turtl = with_test(true);
+        *lockw!(turtl.connected) = true;
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            responses.push(("200 OK", String::from(r#""69""#)));
+            responses.push(("200 OK", jedi::stringify(&full_sync).unwrap()));
+        }
+        let handle = mock_api_many(responses);
+        let result = turtl.copy_account(username.clone(), password.clone()).unwrap();
+        assert_eq!(jedi::to_val(&result).unwrap()["actions"].as_array().unwrap().len(), 3);
+        {
+            let profile_guard = lockr!(turtl.profile);
+            assert_eq!(profile_guard.spaces.len(), 1);
+            assert_eq!(profile_guard.spaces[0].title, Some(String::from("get a job")));
+            assert!(profile_guard.spaces[0].id().unwrap() != &source_space_id);
+            assert_eq!(profile_guard.boards.len(), 1);
+            assert_eq!(profile_guard.boards[0].space_id, *profile_guard.spaces[0].id().unwrap());
+        }
+        let copied: Vec<Note> = {
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().all("notes").unwrap()
+        };
+        assert_eq!(copied.len(), 1);
+        let notes = turtl.load_notes(&vec![copied[0].id().unwrap().clone()]).unwrap();
+        assert_eq!(notes[0].title, Some(String::from("plumber")));
+        assert_eq!(notes[0].user_id, "51");
+        assert_eq!(notes[0].space_id, *lockr!(turtl.profile).spaces[0].id().unwrap());
+
+        // copying again doesn't duplicate anything
+        let result = turtl.copy_account(username, password).unwrap();
+        assert_eq!(jedi::to_val(&result).unwrap()["actions"].as_array().unwrap().len(), 0);
+        let reqs = handle.join().unwrap();
+        assert!(reqs[0].starts_with("POST /auth "));
+        assert!(reqs[1].starts_with("GET /sync/full "));
+        assert_eq!(lockr!(turtl.user).id(), Some(&String::from("51")));
+        assert!(lockr!(turtl.user).logged_in);
+    }
+
+    #[test]
+    fn wipes_and_resyncs_local_cache() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        *lockw!(turtl.connected) = true;
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, true).unwrap();
+        let space_id = space.id().unwrap().clone();
+
+        // what the server knows about us
+        let full_sync = {
+            let db_guard = lock!(turtl.db);
+            let db = db_guard.as_ref().unwrap();
+            let mut recs = db.all::<KeychainEntry>("keychain").unwrap().iter()
+                .map(|x| json!({"type": "keychain", "action": "add", "item_id": x.id().unwrap(), "user_id": 51, "data": x.data_for_storage().unwrap()}))
+                .collect::<Vec<_>>();
+            recs.push(json!({"type": "space", "action": "add", "item_id": space_id, "user_id": 51, "data": space.data_for_storage().unwrap()}));
+            json!({"records": recs, "sync_id": 1})
+        };
+        // and something that only ever made it into our local cache
+        let mut stray: Space = jedi::from_val(json!({"user_id": 51, "title": "get a life"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut stray, true).unwrap();
+        assert_eq!(lockr!(turtl.profile).spaces.len(), 2);
+
+        let handle = mock_api("200 OK", jedi::stringify(&full_sync).unwrap());
+        turtl.wipe_local_cache().unwrap();
+        assert!(handle.join().unwrap().starts_with("GET /sync/full "));
+        turtl.sync_shutdown(true).unwrap();
+
+        {
+            let profile_guard = lockr!(turtl.profile);
+            assert_eq!(profile_guard.spaces.len(), 1);
+            assert_eq!(profile_guard.spaces[0].id(), Some(&space_id));
+            assert_eq!(profile_guard.spaces[0].title, Some(String::from("get a job")));
+        }
+        let stored: Vec<Space> = {
+            let db_guard = lock!(turtl.db);
+            db_guard.as_ref().unwrap().all("spaces").unwrap()
+        };
+        assert_eq!(stored.len(), 1);
+        assert!(lock!(turtl.search).is_some());
+    }
+
+    #[test]
+    fn imports_encrypted_notes() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+
+        // a note encrypted somewhere else, with a key we know
+        let key = Key::random().unwrap();
+        let mut exported: Note = jedi::from_val(json!({"id": "1234", "user_id": 69, "space_id": "6969", "type": "text", "title": "astronaut", "tags": ["space"]})).unwrap();
+        exported.set_key(Some(key.clone()));
+        let serialized = Protected::serialize(&mut exported).unwrap();
+        let body: String = jedi::get(&["body"], &serialized).unwrap();
+
+        let imported = Note::import_encrypted(&turtl, body.clone(), key, &space_id, None).unwrap();
+        let note_id: String = jedi::get(&["id"], &imported).unwrap();
+        assert!(note_id != "1234");
+        assert_eq!(jedi::get::<String>(&["space_id"], &imported).unwrap(), space_id);
+        let notes = turtl.load_notes(&vec![note_id.clone()]).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].title, Some(String::from("astronaut")));
+        assert_eq!(notes[0].tags, Some(vec![String::from("space")]));
+        assert_eq!(notes[0].user_id, "51");
+
+        // wrong key, no note
+        let err = Note::import_encrypted(&turtl, body, Key::random().unwrap(), &space_id, None).unwrap_err();
+        assert_eq!(err.code(), "bad_value");
+    }
+
+    #[test]
+    fn imports_bookmarks() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+
+        // a couple of pages to clip. the clips run side by side, so whichever
+        // page gets asked for first gets the first response (we sort out which
+        // was which from the request lines below)
+        let pages = vec![("get a job", "any job will do"), ("sit on a couch", "a comfy one")];
+        let responses = pages.iter()
+            .map(|(title, desc)| ("200 OK", format!(r#"<html><head><title>{}</title><meta name="description" content="{}"></head><body></body></html>"#, title, desc)))
+            .collect::<Vec<_>>();
+        let handle = mock_api_many(responses);
+        let base = config::get::<String>(&["api", "endpoint"]).unwrap();
+
+        let urls = vec![
+            format!("{}/job", base),
+            String::from("not a url"),
+            format!("{}/couch", base),
+        ];
+        let imports = Note::import_bookmarks(&turtl, urls.clone(), vec![], &space_id, None).unwrap();
+        assert_eq!(imports.len(), 3);
+        assert_eq!(imports.iter().map(|x| x.url.clone()).collect::<Vec<_>>(), urls);
+        assert!(imports[1].note_id.is_none());
+        assert!(imports[1].error.is_some());
+        let reqs = handle.join().unwrap();
+        let (job_page, couch_page) = if reqs[0].starts_with("GET /job ") {
+            assert!(reqs[1].starts_with("GET /couch "));
+            (pages[0], pages[1])
+        } else {
+            assert!(reqs[0].starts_with("GET /couch "));
+            assert!(reqs[1].starts_with("GET /job "));
+            (pages[1], pages[0])
+        };
+
+        let note_ids = vec![imports[0].note_id.clone().unwrap(), imports[2].note_id.clone().unwrap()];
+        let notes = turtl.load_notes(&note_ids).unwrap();
+        assert_eq!(notes.len(), 2);
+        let job = notes.iter().find(|x| x.id() == Some(&note_ids[0])).unwrap();
+        assert_eq!(job.type_, Some(String::from("bookmark")));
+        assert_eq!(job.url, Some(urls[0].clone()));
+        assert_eq!(job.title, Some(String::from(job_page.0)));
+        assert_eq!(job.text, Some(String::from(job_page.1)));
+        assert_eq!(job.space_id, space_id);
+        let couch = notes.iter().find(|x| x.id() == Some(&note_ids[1])).unwrap();
+        assert_eq!(couch.title, Some(String::from(couch_page.0)));
+    }
+
+    #[test]
+    fn merges_duplicate_notes() {
+        let turtl = with_test(true);
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+
+        let save_note = |mut data: Value| -> String {
+            jedi::set(&["space_id"], &mut data, &space_id).unwrap();
+            let mut note: Note = jedi::from_val(data).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+            note.id().unwrap().clone()
+        };
+        let orig = save_note(json!({"user_id": 51, "type": "text", "title": "get a job", "text": "any job", "tags": ["work", "money"]}));
+        let other = save_note(json!({"user_id": 51, "type": "text", "title": "get a job", "text": "a different job"}));
+        // tag order doesn't make a note any less of a dupe
+        let dupe1 = save_note(json!({"user_id": 51, "type": "text", "title": "get a job", "text": "any job", "tags": ["money", "work"]}));
+        let dupe2 = save_note(json!({"user_id": 51, "type": "text", "title": "get a job", "text": "any job", "tags": ["work", "money"]}));
+
+        let duplicates = Note::find_duplicates(&turtl).unwrap();
+        assert_eq!(duplicates, vec![vec![orig.clone(), dupe1.clone(), dupe2.clone()]]);
+
+        // only actual duplicates get merged
+        let err = Note::merge_duplicates(&turtl, &vec![orig.clone(), other.clone()]).unwrap_err();
+        assert_eq!(err.code(), "bad_value");
+        assert_eq!(turtl.load_notes(&vec![other.clone()]).unwrap().len(), 1);
+
+        let kept = Note::merge_duplicates(&turtl, &duplicates[0]).unwrap();
+        assert_eq!(kept, orig);
+        let notes = turtl.load_notes(&vec![orig.clone(), other.clone(), dupe1.clone(), dupe2.clone()]).unwrap();
+        assert_eq!(notes.iter().map(|x| x.id().unwrap().clone()).collect::<Vec<_>>(), vec![orig.clone(), other.clone()]);
+        assert_eq!(Note::find_duplicates(&turtl).unwrap().len(), 0);
+
+        // the deletions are headed out to the server
+        let db_guard = lock!(turtl.db);
+        let syncs: Vec<SyncRecord> = db_guard.as_ref().unwrap().all("sync").unwrap();
+        let deleted = syncs.iter()
+            .filter(|x| x.action == SyncAction::Delete && x.ty == SyncType::Note)
+            .map(|x| x.item_id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(deleted, vec![dupe1, dupe2]);
+    }
+
+    #[test]
+    fn manages_space_members() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(true);
+        *lockw!(turtl.connected) = true;
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let space_key = space.key().unwrap().clone();
+
+        // inviting a member seals the space key to their pubkey
+        let (her_pk, her_sk) = crypto::asym::keygen().unwrap();
+        let her = json!({"id": "52", "username": "jenny@turtlapp.com", "pubkey": her_pk});
+        let handle = mock_api_many(vec![
+            ("200 OK", jedi::stringify(&her).unwrap()),
+            ("200 OK", String::from("{}")),
+        ]);
+        space.invite_member(&turtl, &String::from("jenny@turtlapp.com"), Role::Member, None).unwrap();
+        let reqlines = handle.join().unwrap();
+        assert!(reqlines[0].starts_with("GET /users/email/jenny@turtlapp.com "));
+        assert!(reqlines[1].starts_with(&format!("POST /spaces/{}/invites ", space_id)));
+        assert_eq!(space.invites.len(), 1);
+        {
+            let invite = &mut space.invites[0];
+            assert_eq!(invite.to_user, "jenny@turtlapp.com");
+            assert_eq!(invite.title, "get a job");
+            assert!(invite.is_pubkey_protected);
+            assert!(!invite.is_passphrase_protected);
+            invite.open(&her_pk, &her_sk, None).unwrap();
+            let message: Value = jedi::parse(&String::from_utf8(invite.message.clone().unwrap()).unwrap()).unwrap();
+            assert_eq!(jedi::get::<Key>(&["space_key"], &message).unwrap(), space_key);
+        }
+
+        // can't invite them as owner
+        let err = space.invite_member(&turtl, &String::from("jenny@turtlapp.com"), Role::Owner, None).unwrap_err();
+        assert_eq!(err.code(), "bad_value");
+
+        // changing a role sends the new role/permissions to the API and saves
+        // what comes back
+        let member: SpaceMember = jedi::from_val(json!({"id": 3, "user_id": "52", "space_id": space_id, "username": "jenny@turtlapp.com", "role": "member", "created": "", "updated": ""})).unwrap();
+        space.members.push(member);
+        let saved = json!({"id": 3, "user_id": "52", "space_id": space_id, "username": "jenny@turtlapp.com", "role": "admin", "permissions": Role::Admin.allowed_permissions(), "created": "", "updated": "later"});
+        let handle = mock_api("200 OK", jedi::stringify(&saved).unwrap());
+        space.set_member_role(&turtl, &String::from("52"), Role::Admin).unwrap();
+        let reqline = handle.join().unwrap();
+        assert!(reqline.starts_with(&format!("PUT /spaces/{}/members/52 ", space_id)));
+        {
+            let member = space.members.iter().find(|x| x.user_id == "52").unwrap();
+            assert!(member.role == Role::Admin);
+            assert_eq!(member.updated, "later");
+        }
+
+        // missing members and owner promotions are a no-go
+        assert!(space.set_member_role(&turtl, &String::from("69"), Role::Admin).is_err());
+        let err = space.set_member_role(&turtl, &String::from("52"), Role::Owner).unwrap_err();
+        assert_eq!(err.code(), "bad_value");
+        *lockw!(turtl.connected) = false;
+    }
+
+    #[test]
+    fn runs_perf_selftest() {
+        let turtl = with_test(false);
+        let perf = turtl.selftest_perf().unwrap();
+        let fields = ["encrypt", "decrypt", "db_roundtrip", "search_index", "search_query"];
+        let mut sum = 0;
+        for field in &fields {
+            sum += jedi::get::<u64>(&[*field], &perf).unwrap();
+        }
+        assert_eq!(jedi::get::<u64>(&["total"], &perf).unwrap(), sum);
+        assert_eq!(perf.as_object().unwrap().len(), fields.len() + 1);
+        // it's all scratch data, so nothing was saved anywhere
+        assert!(lock!(turtl.db).is_none());
+        assert!(lock!(turtl.search).is_none());
+    }
+
+    #[test]
+    fn reports_storage_usage() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut board: Board = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "title": "jobs"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut board, false).unwrap();
+        let mut short: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "short"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut short, false).unwrap();
+        let long_text = (0..500).map(|_| "how to avoid huge ships").collect::<Vec<_>>().join(" ");
+        let mut long: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": "long", "text": long_text})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut long, false).unwrap();
+
+        let usage: Vec<Value> = jedi::from_val(turtl.storage_usage().unwrap()).unwrap();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(jedi::get::<String>(&["id"], &usage[0]).unwrap(), *long.id().unwrap());
+        assert_eq!(jedi::get::<String>(&["type"], &usage[0]).unwrap(), "note");
+        assert!(jedi::get::<u64>(&["size"], &usage[0]).unwrap() > long_text.len() as u64);
+        assert_eq!(jedi::get::<u64>(&["file_size"], &usage[0]).unwrap(), 0);
+        let sizes = usage.iter().map(|x| jedi::get::<u64>(&["size"], x).unwrap()).collect::<Vec<_>>();
+        assert!(sizes[0] >= sizes[1] && sizes[1] >= sizes[2]);
+        assert!(usage.iter().any(|x| jedi::get::<String>(&["type"], x).unwrap() == "board"));
+    }
+
+    #[test]
+    fn optimizes_storage() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        let space_id = space.id().unwrap().clone();
+        let mut note_ids = Vec::new();
+        for i in 0..50 {
+            let mut note: Note = jedi::from_val(json!({"user_id": 51, "space_id": space_id, "type": "text", "title": format!("note {}", i), "text": "how to avoid huge ships"})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut note, false).unwrap();
+            note_ids.push(note.id().unwrap().clone());
+        }
+
+        // no index yet, so no search stats
+        let stats = turtl.optimize_storage().unwrap();
+        assert!(jedi::get::<i64>(&["db", "before"], &stats).unwrap() > 0);
+        assert_eq!(stats["search"], Value::Null);
+
+        turtl.index_notes().unwrap();
+        for id in &note_ids[0..25] {
+            sync_model::delete_model::<Note>(&turtl, id, false).unwrap();
+        }
+        let stats = turtl.optimize_storage().unwrap();
+        let db_before: i64 = jedi::get(&["db", "before"], &stats).unwrap();
+        let db_after: i64 = jedi::get(&["db", "after"], &stats).unwrap();
+        assert!(db_after > 0 && db_after <= db_before);
+        assert!(jedi::get::<i64>(&["search", "after"], &stats).unwrap() > 0);
+
+        // everything still works after
+        assert_eq!(turtl.load_notes(&note_ids).unwrap().len(), 25);
+        let search_guard = lock!(turtl.search);
+        let qry: Query = jedi::from_val(json!({"space_id": space_id, "text": "ships", "page": 1, "per_page": 100})).unwrap();
+        let (found, total) = search_guard.as_ref().unwrap().find(&qry).unwrap();
+        assert_eq!(found.len(), 25);
+        assert_eq!(total, 25);
+    }
+
+    #[test]
+    fn searches_readable_spaces() {
+        let turtl = with_test(true);
+
+        let mut space1: Space = jedi::from_val(json!({"user_id": 51, "title": "mine"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space1, false).unwrap();
+        let space1_id = space1.id().unwrap().clone();
+        let mut space2: Space = jedi::from_val(json!({"user_id": 51, "title": "shared"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space2, false).unwrap();
+        let space2_id = space2.id().unwrap().clone();
+        let mut note1: Note = jedi::from_val(json!({"user_id": 51, "space_id": space1_id, "type": "text", "title": "how to avoid huge ships"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note1, false).unwrap();
+        let mut note2: Note = jedi::from_val(json!({"user_id": 51, "space_id": space2_id, "type": "text", "title": "how to avoid small ships"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut note2, false).unwrap();
+        turtl.index_notes().unwrap();
+
+        let find = |turtl: &Turtl, qry: Value| -> Vec<String> {
+            let qry: Query = jedi::from_val(qry).unwrap();
+            let qry = turtl.readable_query(&qry).unwrap();
+            let search_guard = lock!(turtl.search);
+            let mut ids = search_guard.as_ref().unwrap().find(&qry).unwrap().0;
+            ids.sort();
+            ids
+        };
+        let mut both = vec![note1.id().unwrap().clone(), note2.id().unwrap().clone()];
+        both.sort();
+        assert_eq!(find(&turtl, json!({"text": "ships", "page": 1, "per_page": 100})), both);
+        assert_eq!(find(&turtl, json!({"space_id": space2_id, "text": "ships", "page": 1, "per_page": 100})), vec![note2.id().unwrap().clone()]);
+
+        // the shared space gets handed off to someone else and we're no longer
+        // a member, but our index still has its notes
+        {
+            let mut profile_guard = lockw!(turtl.profile);
+            for space in profile_guard.spaces.iter_mut() {
+                if space.id() == Some(&space2_id) {
+                    space.user_id = String::from("52");
+                }
+            }
+        }
+        assert_eq!(find(&turtl, json!({"text": "ships", "page": 1, "per_page": 100})), vec![note1.id().unwrap().clone()]);
+        assert_eq!(find(&turtl, json!({"space_id": space2_id, "text": "ships", "page": 1, "per_page": 100})), Vec::<String>::new());
+        assert_eq!(find(&turtl, json!({"spaces": [space1_id, space2_id], "page": 1, "per_page": 100})), vec![note1.id().unwrap().clone()]);
+        let qry: Query = jedi::from_val(json!({"space_id": space2_id})).unwrap();
+        let qry = turtl.readable_query(&qry).unwrap();
+        assert_eq!(lock!(turtl.search).as_ref().unwrap().find_tags(&qry).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn pings_server() {
+        let _lock = MOCK_API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let turtl = with_test(false);
+        let handle = mock_api("200 OK", String::from(r#"{"greeting":"hi","version":"0.7.1"}"#));
+        let res = turtl.api.ping().unwrap();
+        assert!(handle.join().unwrap().starts_with("GET / "));
+        assert_eq!(res["reachable"], json!(true));
+        assert_eq!(res["version"], json!("0.7.1"));
+        assert!(res["latency"].is_u64());
+
+        // an unhappy server is still a reachable server
+        let handle = mock_api("500 Internal Server Error", String::from(r#"{"error":"lol"}"#));
+        let res = turtl.api.ping().unwrap();
+        handle.join().unwrap();
+        assert_eq!(res["reachable"], json!(true));
+        assert_eq!(res["version"], Value::Null);
+
+        // nobody home
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        config::merge(&json!({"api": {"endpoint": format!("http://127.0.0.1:{}", port)}})).unwrap();
+        let res = turtl.api.ping().unwrap();
+        assert_eq!(res["reachable"], json!(false));
+        assert!(res["error"]["code"].is_string());
+    }
+
+    #[test]
+    fn retries_frozen_syncs() {
+        let turtl = with_test(true);
+
+        let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": "get a job"})).unwrap();
+        sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+
+        // freeze everything but the first record
+        {
+            let db_guard = lock!(turtl.db);
+            let db = db_guard.as_ref().unwrap();
+            let syncs: Vec<SyncRecord> = db.all("sync").unwrap();
+            assert_eq!(syncs.len(), 2);
+            for mut sync in syncs.into_iter().skip(1) {
+                sync.frozen = true;
+                sync.errcount = 4;
+                db.save(&sync).unwrap();
+            }
+        }
+        let pending = SyncRecord::get_all_pending(&turtl).unwrap();
+        assert_eq!(pending.iter().filter(|x| x.frozen).count(), 1);
+
+        assert_eq!(SyncRecord::retry_frozen(&turtl).unwrap(), 1);
+        let pending = SyncRecord::get_all_pending(&turtl).unwrap();
+        assert_eq!(pending.len(), 2);
+        for sync in &pending {
+            assert!(!sync.frozen);
+            assert!(!sync.blocked);
+            assert_eq!(sync.errcount, 0);
+        }
+        assert_eq!(SyncRecord::retry_frozen(&turtl).unwrap(), 0);
+    }
+
+    #[test]
+    fn summarizes_pending_syncs() {
+        let turtl = with_test(true);
+        assert_eq!(SyncRecord::pending_summary(&turtl).unwrap(), Default::default());
+
+        for title in &["get a job", "sit on a couch"] {
+            let mut space: Space = jedi::from_val(json!({"user_id": 51, "title": title})).unwrap();
+            sync_model::save_model(SyncAction::Add, &turtl, &mut space, false).unwrap();
+        }
+        // incoming file syncs aren't ours to worry about, and a frozen record
+        // is still pending
+        let expected_bytes = {
+            let db_guard = lock!(turtl.db);
+            let db = db_guard.as_ref().unwrap();
+            let mut syncs: Vec<SyncRecord> = db.all("sync").unwrap();
+            assert_eq!(syncs.len(), 4);
+            syncs[0].frozen = true;
+            db.save(&syncs[0]).unwrap();
+            let mut incoming: SyncRecord = jedi::from_val(json!({"action": "add", "item_id": "1234", "user_id": 51, "type": "file:incoming"})).unwrap();
+            incoming.generate_id().unwrap();
+            db.save(&incoming).unwrap();
+            syncs.iter().fold(0, |acc, x| acc + jedi::stringify(x).unwrap().len())
+        };
+        let summary = SyncRecord::pending_summary(&turtl).unwrap();
+        assert_eq!(summary, PendingSummary {
+            count: 4,
+            bytes: expected_bytes,
+            frozen: 1,
+        });
+    }
+
     #[test]
     fn syncs_outgoing() {
         let user_key = Key::new(crypto::from_base64(&String::from("jlz71VUIns1xM3Hq0fETZT98dxzhlqUxqb0VXYq1KtQ=")).unwrap());
@@ -1,4 +1,4 @@
-use ::config;
+use ::config::{self, LogLevel};
 use ::fern;
 use ::log;
 use ::time;
@@ -161,22 +161,26 @@ fn prune_logfile() -> TResult<()> {
 /// a simple wrapper (pretty much direct from documentation) that sets up
 /// logging to STDOUT (and file if config allows) via fern/log
 pub fn setup_logger() -> TResult<()> {
-    let levelstr: String = match env::var("TURTL_LOGLEVEL") {
-        Ok(x) => x,
-        Err(_) => config::get(&["logging", "level"])?
+    let (level_from, levelstr): (&str, String) = match env::var("TURTL_LOGLEVEL") {
+        Ok(x) => ("TURTL_LOGLEVEL", x),
+        Err(_) => ("logging.level", config::get(&["logging", "level"])?)
     };
-    let level = match levelstr.to_lowercase().as_ref() {
-        "error" => log::LevelFilter::Error,
-        "warn" => log::LevelFilter::Warn,
-        "info" => log::LevelFilter::Info,
-        "debug" => log::LevelFilter::Debug,
-        "trace" => log::LevelFilter::Trace,
-        "off" => log::LevelFilter::Off,
-        _ => {
-            println!("logger::setup_logger() -- bad `log.level` value (\"{}\"), defaulting to \"warn\"", levelstr);
-            log::LevelFilter::Warn
+    let level = match config::parse_enum::<LogLevel>(level_from, &levelstr) {
+        Ok(x) => x,
+        Err(e) => {
+            // no logger yet, so this goes straight to stdout
+            println!("logger::setup_logger() -- {}, defaulting to \"warn\"", e);
+            LogLevel::Warn
         }
     };
+    let level = match level {
+        LogLevel::Error => log::LevelFilter::Error,
+        LogLevel::Warn => log::LevelFilter::Warn,
+        LogLevel::Info => log::LevelFilter::Info,
+        LogLevel::Debug => log::LevelFilter::Debug,
+        LogLevel::Trace => log::LevelFilter::Trace,
+        LogLevel::Off => log::LevelFilter::Off,
+    };
     let non_verbose_level = if level < log::LevelFilter::Info { level } else { log::LevelFilter::Info };
     let mut config = fern::Dispatch::new()
         .format(|out, message, record| {
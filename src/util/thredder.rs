@@ -14,6 +14,8 @@ pub struct Thredder {
     pub name: String,
     /// Stores the thread pooler for this Thredder
     pool: CpuPool,
+    /// How many threads are in our pool
+    workers: u32,
 }
 
 impl Thredder {
@@ -25,9 +27,15 @@ impl Thredder {
         Thredder {
             name: String::from(name),
             pool: CpuPool::new(workers as usize),
+            workers: workers,
         }
     }
 
+    /// Get the number of threads in this Thredder's pool
+    pub fn workers(&self) -> u32 {
+        self.workers
+    }
+
     /// Run an operation on this pool, returning the Future to be waited on at
     /// a later time.
     pub fn run_async<F, T>(&self, run: F) -> TFutureResult<T>